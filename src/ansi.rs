@@ -0,0 +1,648 @@
+// Interpreter for ANSI/VT100 escape sequences, writing to a TextGrid.
+// Supports cursor movement, SGR colours (16, 256 and 24-bit), erasing, scrolling regions and cursor visibility.
+use super::{
+    palette::Colour,
+    textgrid::TextGrid
+};
+
+use cgmath::Vector4;
+
+const ESC: u8 = 0x1B;
+const BEL: u8 = 0x07;
+const TAB_WIDTH: usize = 8;
+
+// Standard 16 colour VGA palette.
+const BASE_COLOURS: [[u8; 3]; 16] = [
+    [0, 0, 0],       [170, 0, 0],     [0, 170, 0],     [170, 85, 0],
+    [0, 0, 170],     [170, 0, 170],   [0, 170, 170],   [170, 170, 170],
+    [85, 85, 85],    [255, 85, 85],   [85, 255, 85],   [255, 255, 85],
+    [85, 85, 255],   [255, 85, 255],  [85, 255, 255],  [255, 255, 255]
+];
+
+const DEFAULT_FG: u8 = 7;
+const DEFAULT_BG: u8 = 0;
+
+fn rgb(r: u8, g: u8, b: u8) -> Colour {
+    Vector4::new(f32::from(r) / 255.0, f32::from(g) / 255.0, f32::from(b) / 255.0, 1.0)
+}
+
+// Get a colour from the xterm 256 colour palette.
+pub fn xterm_colour(index: u8) -> Colour {
+    match index {
+        0..=15 => {
+            let [r, g, b] = BASE_COLOURS[index as usize];
+            rgb(r, g, b)
+        },
+        16..=231 => {
+            // 6x6x6 colour cube.
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let i = index - 16;
+            rgb(level(i / 36), level((i / 6) % 6), level(i % 6))
+        },
+        _ => {
+            // Greyscale ramp.
+            let v = 8 + (index - 232) * 10;
+            rgb(v, v, v)
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum ColourSel {
+    Indexed(u8),
+    Rgb(Colour)
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum ParseState {
+    Ground,
+    Escape,
+    Csi,
+    // Operating system command: ignored until terminated.
+    Osc,
+    OscEscape
+}
+
+// Graphic rendition and position state saved with ESC 7 / CSI s.
+#[derive(Copy, Clone)]
+struct SavedCursor {
+    x: usize,
+    y: usize,
+    fg: ColourSel,
+    bg: ColourSel,
+    bold: bool,
+    inverse: bool
+}
+
+// Terminal state machine. Feed it bytes along with the grid to write to.
+pub struct AnsiTerminal {
+    state: ParseState,
+    params: Vec<u32>,
+    current_param: Option<u32>,
+    private: bool,
+
+    utf8: Vec<u8>,
+    utf8_len: usize,
+
+    x: usize,
+    y: usize,
+    wrap_pending: bool,
+    saved: Option<SavedCursor>,
+
+    // Scrolling region: rows [scroll_top, scroll_bottom).
+    scroll_top: usize,
+    scroll_bottom: Option<usize>,
+
+    fg: ColourSel,
+    bg: ColourSel,
+    bold: bool,
+    inverse: bool,
+    cursor_visible: bool
+}
+
+impl AnsiTerminal {
+    pub fn new() -> Self {
+        AnsiTerminal {
+            state: ParseState::Ground,
+            params: Vec::new(),
+            current_param: None,
+            private: false,
+
+            utf8: Vec::new(),
+            utf8_len: 0,
+
+            x: 0,
+            y: 0,
+            wrap_pending: false,
+            saved: None,
+
+            scroll_top: 0,
+            scroll_bottom: None,
+
+            fg: ColourSel::Indexed(DEFAULT_FG),
+            bg: ColourSel::Indexed(DEFAULT_BG),
+            bold: false,
+            inverse: false,
+            cursor_visible: true
+        }
+    }
+
+    // Process a stream of bytes, updating the grid.
+    pub fn feed(&mut self, grid: &mut TextGrid, bytes: &[u8]) {
+        self.apply_colours(grid);
+        for b in bytes {
+            self.feed_byte(grid, *b);
+        }
+        grid.set_cursor(self.x, self.y);
+        grid.show_cursor(self.cursor_visible);
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.x, self.y)
+    }
+
+    // Reset all state and clear the grid.
+    pub fn reset(&mut self, grid: &mut TextGrid) {
+        *self = AnsiTerminal::new();
+        self.apply_colours(grid);
+        grid.clear();
+    }
+}
+
+// Parsing
+impl AnsiTerminal {
+    fn feed_byte(&mut self, grid: &mut TextGrid, b: u8) {
+        match self.state {
+            ParseState::Ground => self.ground(grid, b),
+            ParseState::Escape => self.escape(grid, b),
+            ParseState::Csi => self.csi(grid, b),
+            ParseState::Osc => match b {
+                BEL => self.state = ParseState::Ground,
+                ESC => self.state = ParseState::OscEscape,
+                _ => {}
+            },
+            ParseState::OscEscape => self.state = if b == b'\\' { ParseState::Ground } else { ParseState::Osc }
+        }
+    }
+
+    fn ground(&mut self, grid: &mut TextGrid, b: u8) {
+        // Continue a multi-byte UTF-8 character.
+        if self.utf8_len > 0 {
+            if (b & 0xC0) == 0x80 {
+                self.utf8.push(b);
+                if self.utf8.len() == self.utf8_len {
+                    let ch = std::str::from_utf8(&self.utf8).ok().and_then(|s| s.chars().next()).unwrap_or('\u{FFFD}');
+                    self.utf8.clear();
+                    self.utf8_len = 0;
+                    self.print(grid, ch);
+                }
+                return;
+            }
+            self.utf8.clear();
+            self.utf8_len = 0;
+            self.print(grid, '\u{FFFD}');
+        }
+
+        match b {
+            ESC => self.state = ParseState::Escape,
+            0x00..=0x1F => self.execute(grid, b),
+            0x7F => {},
+            0x20..=0x7E => self.print(grid, b as char),
+            0xC0..=0xDF => self.start_utf8(b, 2),
+            0xE0..=0xEF => self.start_utf8(b, 3),
+            0xF0..=0xF7 => self.start_utf8(b, 4),
+            _ => self.print(grid, '\u{FFFD}')
+        }
+    }
+
+    // Run a C0 control character. Unsupported ones are ignored.
+    fn execute(&mut self, grid: &mut TextGrid, b: u8) {
+        match b {
+            b'\n' | 0x0B | 0x0C => self.line_feed(grid),
+            b'\r' => self.carriage_return(),
+            0x08 => {
+                self.x = self.x.saturating_sub(1);
+                self.wrap_pending = false;
+            },
+            b'\t' => {
+                let next = (self.x / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.x = next.min(grid.width().saturating_sub(1));
+                self.wrap_pending = false;
+            },
+            _ => {}
+        }
+    }
+
+    fn start_utf8(&mut self, b: u8, len: usize) {
+        self.utf8.push(b);
+        self.utf8_len = len;
+    }
+
+    fn escape(&mut self, grid: &mut TextGrid, b: u8) {
+        self.state = ParseState::Ground;
+        match b {
+            b'[' => {
+                self.params.clear();
+                self.current_param = None;
+                self.private = false;
+                self.state = ParseState::Csi;
+            },
+            b']' => self.state = ParseState::Osc,
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(grid),
+            b'D' => self.line_feed(grid),
+            b'E' => {
+                self.carriage_return();
+                self.line_feed(grid);
+            },
+            b'M' => self.reverse_line_feed(grid),
+            b'c' => self.reset(grid),
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, grid: &mut TextGrid, b: u8) {
+        match b {
+            b'0'..=b'9' => {
+                let digit = u32::from(b - b'0');
+                self.current_param = Some(self.current_param.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+            },
+            b';' => {
+                self.params.push(self.current_param.take().unwrap_or(0));
+            },
+            b'?' => self.private = true,
+            0x20..=0x2F | b'<' | b'=' | b'>' => {},
+            0x40..=0x7E => {
+                if let Some(p) = self.current_param.take() {
+                    self.params.push(p);
+                }
+                self.state = ParseState::Ground;
+                self.dispatch_csi(grid, b);
+            },
+            ESC => self.state = ParseState::Escape,
+            // CAN and SUB cancel the sequence. Other control characters are run as usual, without ending it.
+            0x18 | 0x1A => self.state = ParseState::Ground,
+            0x00..=0x1F => self.execute(grid, b),
+            0x7F => {},
+            _ => self.state = ParseState::Ground
+        }
+    }
+
+    // Get a parameter, or the default if it is missing or zero.
+    fn param(&self, n: usize, default: u32) -> usize {
+        match self.params.get(n) {
+            Some(0) | None => default as usize,
+            Some(p) => *p as usize
+        }
+    }
+
+    fn dispatch_csi(&mut self, grid: &mut TextGrid, b: u8) {
+        let width = grid.width();
+        let height = grid.height();
+        if (width == 0) || (height == 0) {
+            return;
+        }
+
+        if self.private {
+            // Only cursor visibility is supported.
+            if self.params.contains(&25) {
+                match b {
+                    b'h' => self.cursor_visible = true,
+                    b'l' => self.cursor_visible = false,
+                    _ => {}
+                }
+            }
+            return;
+        }
+
+        self.wrap_pending = false;
+        match b {
+            b'A' => self.y = self.y.saturating_sub(self.param(0, 1)).max(self.region_top_for_cursor()),
+            b'B' => self.y = (self.y + self.param(0, 1)).min(self.region_bottom_for_cursor(grid) - 1),
+            b'C' => self.x = (self.x + self.param(0, 1)).min(width - 1),
+            b'D' => self.x = self.x.saturating_sub(self.param(0, 1)),
+            b'E' => {
+                self.y = (self.y + self.param(0, 1)).min(height - 1);
+                self.x = 0;
+            },
+            b'F' => {
+                self.y = self.y.saturating_sub(self.param(0, 1));
+                self.x = 0;
+            },
+            b'G' => self.x = (self.param(0, 1) - 1).min(width - 1),
+            b'd' => self.y = (self.param(0, 1) - 1).min(height - 1),
+            b'H' | b'f' => {
+                self.y = (self.param(0, 1) - 1).min(height - 1);
+                self.x = (self.param(1, 1) - 1).min(width - 1);
+            },
+            b'J' => match self.params.first().cloned().unwrap_or(0) {
+                0 => {
+                    self.erase_in_line(grid, self.x, width);
+                    for y in (self.y + 1)..height {
+                        grid.clear_row(y);
+                    }
+                },
+                1 => {
+                    for y in 0..self.y {
+                        grid.clear_row(y);
+                    }
+                    self.erase_in_line(grid, 0, self.x + 1);
+                },
+                _ => grid.clear()
+            },
+            b'K' => match self.params.first().cloned().unwrap_or(0) {
+                0 => self.erase_in_line(grid, self.x, width),
+                1 => self.erase_in_line(grid, 0, self.x + 1),
+                _ => grid.clear_row(self.y)
+            },
+            b'S' => grid.scroll_up(self.scroll_top, self.scroll_bottom(grid), self.param(0, 1)),
+            b'T' => grid.scroll_down(self.scroll_top, self.scroll_bottom(grid), self.param(0, 1)),
+            b'L' if self.in_scroll_region(grid) => grid.scroll_down(self.y, self.scroll_bottom(grid), self.param(0, 1)),
+            b'M' if self.in_scroll_region(grid) => grid.scroll_up(self.y, self.scroll_bottom(grid), self.param(0, 1)),
+            b'r' => {
+                let top = self.param(0, 1) - 1;
+                let bottom = self.param(1, height as u32).min(height);
+                if top + 1 < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = Some(bottom);
+                } else {
+                    self.scroll_top = 0;
+                    self.scroll_bottom = None;
+                }
+                self.x = 0;
+                self.y = 0;
+            },
+            b's' => self.save_cursor(),
+            b'u' => self.restore_cursor(grid),
+            b'm' => self.select_graphic_rendition(grid),
+            _ => {}
+        }
+    }
+}
+
+// Actions
+impl AnsiTerminal {
+    fn print(&mut self, grid: &mut TextGrid, ch: char) {
+        let width = grid.width();
+        if (width == 0) || (grid.height() == 0) {
+            return;
+        }
+
+        if self.wrap_pending {
+            self.carriage_return();
+            self.line_feed(grid);
+        }
+
+        grid.put_char(self.x, self.y, ch);
+
+        if self.x + 1 >= width {
+            self.wrap_pending = true;
+        } else {
+            self.x += 1;
+        }
+    }
+
+    fn carriage_return(&mut self) {
+        self.x = 0;
+        self.wrap_pending = false;
+    }
+
+    // Move down a line, scrolling the region if at the bottom of it.
+    fn line_feed(&mut self, grid: &mut TextGrid) {
+        self.wrap_pending = false;
+        let bottom = self.scroll_bottom(grid);
+        if self.y + 1 == bottom {
+            grid.scroll_up(self.scroll_top, bottom, 1);
+        } else if self.y + 1 < grid.height() {
+            self.y += 1;
+        }
+    }
+
+    // Move up a line, scrolling the region if at the top of it.
+    fn reverse_line_feed(&mut self, grid: &mut TextGrid) {
+        self.wrap_pending = false;
+        if self.y == self.scroll_top {
+            grid.scroll_down(self.scroll_top, self.scroll_bottom(grid), 1);
+        } else {
+            self.y = self.y.saturating_sub(1);
+        }
+    }
+
+    // Erase cells in the current line between [start, end).
+    fn erase_in_line(&mut self, grid: &mut TextGrid, start: usize, end: usize) {
+        for x in start..end.min(grid.width()) {
+            grid.put_char(x, self.y, ' ');
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = Some(SavedCursor {
+            x: self.x,
+            y: self.y,
+            fg: self.fg,
+            bg: self.bg,
+            bold: self.bold,
+            inverse: self.inverse
+        });
+    }
+
+    fn restore_cursor(&mut self, grid: &mut TextGrid) {
+        if let Some(s) = self.saved {
+            self.x = s.x.min(grid.width().saturating_sub(1));
+            self.y = s.y.min(grid.height().saturating_sub(1));
+            self.fg = s.fg;
+            self.bg = s.bg;
+            self.bold = s.bold;
+            self.inverse = s.inverse;
+            self.wrap_pending = false;
+            self.apply_colours(grid);
+        }
+    }
+
+    fn scroll_bottom(&self, grid: &TextGrid) -> usize {
+        self.scroll_bottom.unwrap_or(grid.height()).min(grid.height())
+    }
+
+    fn in_scroll_region(&self, grid: &TextGrid) -> bool {
+        (self.y >= self.scroll_top) && (self.y < self.scroll_bottom(grid))
+    }
+
+    // Relative cursor movement stops at the scrolling region margins if the cursor is inside it.
+    fn region_top_for_cursor(&self) -> usize {
+        if self.y >= self.scroll_top { self.scroll_top } else { 0 }
+    }
+
+    fn region_bottom_for_cursor(&self, grid: &TextGrid) -> usize {
+        let bottom = self.scroll_bottom(grid);
+        if self.y < bottom { bottom } else { grid.height() }
+    }
+
+    fn select_graphic_rendition(&mut self, grid: &mut TextGrid) {
+        if self.params.is_empty() {
+            self.params.push(0);
+        }
+
+        let params = std::mem::take(&mut self.params);
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => {
+                    self.fg = ColourSel::Indexed(DEFAULT_FG);
+                    self.bg = ColourSel::Indexed(DEFAULT_BG);
+                    self.bold = false;
+                    self.inverse = false;
+                },
+                1 => self.bold = true,
+                22 => self.bold = false,
+                7 => self.inverse = true,
+                27 => self.inverse = false,
+                p @ 30..=37 => self.fg = ColourSel::Indexed((p - 30) as u8),
+                39 => self.fg = ColourSel::Indexed(DEFAULT_FG),
+                p @ 40..=47 => self.bg = ColourSel::Indexed((p - 40) as u8),
+                49 => self.bg = ColourSel::Indexed(DEFAULT_BG),
+                p @ 90..=97 => self.fg = ColourSel::Indexed((p - 90 + 8) as u8),
+                p @ 100..=107 => self.bg = ColourSel::Indexed((p - 100 + 8) as u8),
+                p @ 38 | p @ 48 => {
+                    let (colour, used) = extended_colour(&params[(i + 1)..]);
+                    if let Some(c) = colour {
+                        if p == 38 { self.fg = c; } else { self.bg = c; }
+                    }
+                    i += used;
+                },
+                _ => {}
+            }
+            i += 1;
+        }
+
+        self.params = params;
+        self.apply_colours(grid);
+    }
+
+    // Set the grid's colours for subsequent writes from the current rendition.
+    fn apply_colours(&self, grid: &mut TextGrid) {
+        let fg = match self.fg {
+            // Bold brightens the standard colours.
+            ColourSel::Indexed(i) if self.bold && (i < 8) => xterm_colour(i + 8),
+            ColourSel::Indexed(i) => xterm_colour(i),
+            ColourSel::Rgb(c) => c
+        };
+        let bg = match self.bg {
+            ColourSel::Indexed(i) => xterm_colour(i),
+            ColourSel::Rgb(c) => c
+        };
+
+        if self.inverse {
+            grid.set_colours(bg, fg);
+        } else {
+            grid.set_colours(fg, bg);
+        }
+    }
+}
+
+impl Default for AnsiTerminal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Parse the parameters following an extended colour SGR (38 or 48).
+// Returns the colour, and the number of parameters used.
+fn extended_colour(params: &[u32]) -> (Option<ColourSel>, usize) {
+    match params {
+        [5, n, ..] => (Some(ColourSel::Indexed((*n).min(255) as u8)), 2),
+        [2, r, g, b, ..] => {
+            let c = |v: u32| v.min(255) as u8;
+            (Some(ColourSel::Rgb(rgb(c(*r), c(*g), c(*b)))), 4)
+        },
+        [5] => (None, 1),
+        [2, rest @ ..] => (None, 1 + rest.len()),
+        _ => (None, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feed text to a new terminal on a grid of some size.
+    fn run(width: usize, height: usize, text: &str) -> (AnsiTerminal, TextGrid) {
+        let mut terminal = AnsiTerminal::new();
+        let mut grid = TextGrid::new(width, height, 16);
+        terminal.feed(&mut grid, text.as_bytes());
+        (terminal, grid)
+    }
+
+    fn rows(grid: &TextGrid) -> Vec<String> {
+        (0..grid.height()).map(|y| grid.row_text(y)).collect()
+    }
+
+    #[test]
+    fn text_wraps_and_scrolls() {
+        let (terminal, grid) = run(4, 2, "abcdef\r\nxy");
+        assert_eq!(rows(&grid), ["ef  ", "xy  "]);
+        assert_eq!(terminal.cursor(), (2, 1));
+    }
+
+    #[test]
+    fn cursor_movement() {
+        let (terminal, grid) = run(6, 4, "\x1b[2;3Hx\x1b[Ay\x1b[3Bz\x1b[5Dw\x1b[GW\x1b[2Cv");
+        assert_eq!(rows(&grid), ["   y  ", "  x   ", "      ", "W  vz "]);
+        assert_eq!(terminal.cursor(), (4, 3));
+
+        // Moves stop at the edges.
+        let (terminal, _) = run(6, 4, "\x1b[9;9H\x1b[20A\x1b[20D");
+        assert_eq!(terminal.cursor(), (0, 0));
+    }
+
+    #[test]
+    fn sgr_16_colours() {
+        let (_, grid) = run(4, 1, "\x1b[31mR\x1b[0mN\x1b[1;32mG\x1b[44;22mB");
+        assert!(grid.cell(0, 0).unwrap().fg == xterm_colour(1));
+        assert!(grid.cell(1, 0).unwrap().fg == xterm_colour(DEFAULT_FG));
+        assert!(grid.cell(1, 0).unwrap().bg == xterm_colour(DEFAULT_BG));
+        // Bold brightens the standard colours.
+        assert!(grid.cell(2, 0).unwrap().fg == xterm_colour(10));
+        assert!(grid.cell(3, 0).unwrap().fg == xterm_colour(2));
+        assert!(grid.cell(3, 0).unwrap().bg == xterm_colour(4));
+    }
+
+    #[test]
+    fn sgr_256_and_rgb_colours() {
+        let (_, grid) = run(4, 1, "\x1b[38;5;196mX\x1b[48;5;232mY\x1b[38;2;10;20;30mZ\x1b[7mI");
+        assert!(grid.cell(0, 0).unwrap().fg == xterm_colour(196));
+        assert!(grid.cell(0, 0).unwrap().fg == rgb(255, 0, 0));
+        assert!(grid.cell(1, 0).unwrap().bg == rgb(8, 8, 8));
+        assert!(grid.cell(2, 0).unwrap().fg == rgb(10, 20, 30));
+        // Inverse swaps the colours.
+        assert!(grid.cell(3, 0).unwrap().fg == rgb(8, 8, 8));
+        assert!(grid.cell(3, 0).unwrap().bg == rgb(10, 20, 30));
+    }
+
+    #[test]
+    fn erase_in_display_and_line() {
+        let fill = "abcd\r\nefgh\r\nijkl";
+        let (_, grid) = run(4, 3, &format!("{}\x1b[2;2H\x1b[K", fill));
+        assert_eq!(rows(&grid), ["abcd", "e   ", "ijkl"]);
+        let (_, grid) = run(4, 3, &format!("{}\x1b[2;2H\x1b[1K", fill));
+        assert_eq!(rows(&grid), ["abcd", "  gh", "ijkl"]);
+        let (_, grid) = run(4, 3, &format!("{}\x1b[2;2H\x1b[2K", fill));
+        assert_eq!(rows(&grid), ["abcd", "    ", "ijkl"]);
+        let (_, grid) = run(4, 3, &format!("{}\x1b[2;2H\x1b[J", fill));
+        assert_eq!(rows(&grid), ["abcd", "e   ", "    "]);
+        let (_, grid) = run(4, 3, &format!("{}\x1b[2;2H\x1b[1J", fill));
+        assert_eq!(rows(&grid), ["    ", "  gh", "ijkl"]);
+        let (_, grid) = run(4, 3, &format!("{}\x1b[2J", fill));
+        assert_eq!(rows(&grid), ["    ", "    ", "    "]);
+    }
+
+    #[test]
+    fn scrolling_region() {
+        let fill = "a\r\nb\r\nc\r\nd";
+        // A line feed at the bottom of the region scrolls only the region.
+        let (_, grid) = run(2, 4, &format!("{}\x1b[2;3r\x1b[3;1H\nx", fill));
+        assert_eq!(rows(&grid), ["a ", "c ", "x ", "d "]);
+        // A reverse line feed at the top scrolls it down.
+        let (_, grid) = run(2, 4, &format!("{}\x1b[2;3r\x1b[2;1H\x1bMx", fill));
+        assert_eq!(rows(&grid), ["a ", "x ", "b ", "d "]);
+        // Scroll up and down by commands.
+        let (_, grid) = run(2, 4, &format!("{}\x1b[2;3r\x1b[S", fill));
+        assert_eq!(rows(&grid), ["a ", "c ", "  ", "d "]);
+        let (_, grid) = run(2, 4, &format!("{}\x1b[2;3r\x1b[T", fill));
+        assert_eq!(rows(&grid), ["a ", "  ", "b ", "d "]);
+        // Resetting the region scrolls the whole grid again.
+        let (_, grid) = run(2, 4, &format!("{}\x1b[2;3r\x1b[r\x1b[4;1H\nx", fill));
+        assert_eq!(rows(&grid), ["b ", "c ", "d ", "x "]);
+    }
+
+    #[test]
+    fn control_characters_inside_csi() {
+        // The carriage return runs without ending the sequence.
+        let (_, grid) = run(8, 1, "abc\x1b[2\rCx");
+        assert_eq!(rows(&grid), ["abx     "]);
+        let (_, grid) = run(8, 2, "ab\x1b[\n2Dx");
+        assert_eq!(rows(&grid), ["ab      ", "x       "]);
+        // CAN cancels it.
+        let (_, grid) = run(8, 1, "\x1b[2\x18Cx");
+        assert_eq!(rows(&grid), ["Cx      "]);
+    }
+}
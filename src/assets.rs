@@ -0,0 +1,155 @@
+// Asset files for a scene: a Tiled map and a palette file.
+// With watching enabled, changed files are re-imported. Import errors are logged and the previous assets are kept.
+use super::{
+    imagegen::TextureAtlas,
+    palette::Colour,
+    palettefile::{
+        self,
+        PaletteError
+    },
+    tiled::{
+        self,
+        TiledError,
+        TiledMap
+    },
+    tilesheet::ImportOptions,
+    watch::FileWatcher
+};
+
+use std::{
+    path::{
+        Path,
+        PathBuf
+    },
+    time::Duration
+};
+
+// A map, and the atlas its tilesets were loaded into.
+pub struct Level {
+    pub atlas: TextureAtlas,
+    pub map: TiledMap
+}
+
+// An asset that has been re-imported.
+pub enum AssetUpdate {
+    Level(Level),
+    Colours(Vec<Colour>)
+}
+
+pub struct Assets {
+    map_path: Option<PathBuf>,
+    palette_path: Option<PathBuf>,
+    // Size of the atlas that levels are loaded into, in textures.
+    atlas_size: usize,
+    // Size of a texture in texels.
+    tex_size: usize,
+    options: ImportOptions,
+    // Files the current level was loaded from.
+    map_files: Vec<PathBuf>,
+    watcher: Option<FileWatcher>
+}
+
+impl Assets {
+    pub fn new(map_path: Option<PathBuf>, palette_path: Option<PathBuf>, atlas_size: usize, tex_size: usize) -> Self {
+        Assets {
+            map_path: map_path,
+            palette_path: palette_path,
+            atlas_size: atlas_size,
+            tex_size: tex_size,
+            options: ImportOptions::default(),
+            map_files: Vec::new(),
+            watcher: None
+        }
+    }
+
+    pub fn map_path(&self) -> Option<&Path> {
+        self.map_path.as_deref()
+    }
+
+    pub fn palette_path(&self) -> Option<&Path> {
+        self.palette_path.as_deref()
+    }
+
+    // Load the map into a new atlas, if there is one.
+    pub fn load_level(&mut self) -> Result<Option<Level>, TiledError> {
+        let path = match &self.map_path {
+            Some(p) => p.clone(),
+            None => return Ok(None)
+        };
+
+        let mut atlas = TextureAtlas::new(self.atlas_size, self.tex_size);
+        let map = tiled::load(&path, &mut atlas, &self.options)?;
+
+        // The set of tilesets may have changed.
+        if let Some(watcher) = &self.watcher {
+            for file in self.map_files.iter() {
+                watcher.unwatch(file);
+            }
+            for file in map.files.iter() {
+                watcher.watch(file);
+            }
+        }
+        self.map_files = map.files.clone();
+
+        Ok(Some(Level {
+            atlas: atlas,
+            map: map
+        }))
+    }
+
+    // Load the colours in the palette file, if there is one.
+    pub fn load_colours(&self) -> Result<Option<Vec<Colour>>, PaletteError> {
+        match &self.palette_path {
+            Some(path) => {
+                let data = std::fs::read(path)?;
+                let format = palettefile::PaletteFormat::detect(path, &data);
+                palettefile::read_colours(&data, format).map(Some)
+            },
+            None => Ok(None)
+        }
+    }
+
+    // Start watching the asset files for changes, polling every interval.
+    // Wakeup is called from another thread when a change is found.
+    pub fn watch<F>(&mut self, interval: Duration, wakeup: F)
+        where F: Fn() + Send + 'static {
+        let watcher = FileWatcher::new(interval, wakeup);
+        for file in self.map_files.iter().chain(self.palette_path.iter()) {
+            watcher.watch(file);
+        }
+        self.watcher = Some(watcher);
+    }
+
+    // Re-import any assets whose files have changed.
+    pub fn poll(&mut self) -> Vec<AssetUpdate> {
+        let changed = match &self.watcher {
+            Some(w) => w.changed(),
+            None => return Vec::new()
+        };
+
+        let mut updates = Vec::new();
+        if changed.iter().any(|f| self.map_files.contains(f)) {
+            match self.load_level() {
+                Ok(Some(level)) => {
+                    println!("Reloaded {}", level.map.files[0].display());
+                    updates.push(AssetUpdate::Level(level));
+                },
+                Ok(None) => {},
+                Err(e) => println!("Couldn't reload map: {}", e)
+            }
+        }
+
+        if self.palette_path.as_ref().is_some_and(|p| changed.contains(p)) {
+            match self.load_colours() {
+                Ok(Some(colours)) => {
+                    println!("Reloaded palette file");
+                    updates.push(AssetUpdate::Colours(colours));
+                },
+                Ok(None) => {},
+                Err(e) => println!("Couldn't reload palette file: {}", e)
+            }
+        }
+
+        updates
+    }
+}
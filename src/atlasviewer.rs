@@ -0,0 +1,199 @@
+use vulkano::{
+    buffer::cpu_pool::CpuBufferPoolChunk,
+    command_buffer::{
+        AutoCommandBuffer,
+        CommandBufferExecFuture
+    },
+    device::{
+        Device,
+        Queue
+    },
+    format::R8Uint,
+    image::immutable::ImmutableImage,
+    memory::pool::StdMemoryPool,
+    pipeline::viewport::Viewport,
+    sync::NowFuture
+};
+
+use tile_test::{
+    error::TileError,
+    imagegen::TextureAtlas,
+    vertexgrid::VertexGrid,
+    Vertex
+};
+
+use std::sync::Arc;
+
+// 3x5 digit glyphs, one row per byte with the leftmost texel in bit 2.
+pub const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b011, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111]
+];
+// The digit atlas holds the 10 digits then a blank texture, each with a border around the glyph.
+const DIGIT_ATLAS_SIZE: usize = 4;
+const DIGIT_SIZE: usize = 7;
+const BLANK: usize = 10;
+// Digits in each slot label.
+const LABEL_LEN: usize = 4;
+// Smallest slot size in pixels that labels are drawn at.
+const MIN_LABEL_SLOT: f32 = 32.0;
+
+// Viewport and vertex buffer of a strip of labels.
+type LabelBuffer = (Viewport, CpuBufferPoolChunk<Vertex, Arc<StdMemoryPool>>);
+
+// The whole texture atlas drawn as a grid of slots through one palette, beside the main view.
+// Each slot is labelled with its number along the top, if there is room.
+pub struct AtlasViewer {
+    // One tile per atlas slot.
+    vertex_grid: VertexGrid,
+    // A strip of digits for each row of slots.
+    labels: Vec<VertexGrid>,
+    digits: Arc<ImmutableImage<R8Uint>>,
+    // Viewport of the whole window.
+    window: Viewport,
+    atlas_size: usize,
+    palette: u32
+}
+
+impl AtlasViewer {
+    // Colours: colours in each palette. Digits are drawn in the last colour, on the first colour.
+    pub fn new(device: &Arc<Device>, queue: Arc<Queue>, window: &Viewport, atlas_size: usize, colours: usize) -> Result<(Self, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>), TileError> {
+        let mut digit_atlas = TextureAtlas::new(DIGIT_ATLAS_SIZE, DIGIT_SIZE);
+        let foreground = colours.saturating_sub(1) as u8;
+        for (d, glyph) in DIGITS.iter().enumerate() {
+            let (tex_x, tex_y) = (d % DIGIT_ATLAS_SIZE, d / DIGIT_ATLAS_SIZE);
+            for (y, row) in glyph.iter().enumerate() {
+                for x in 0..3 {
+                    if (row >> (2 - x)) & 1 == 1 {
+                        digit_atlas.set_texel(tex_x, tex_y, x + 2, y + 1, foreground);
+                    }
+                }
+            }
+        }
+        let (digits, future) = digit_atlas.make_image(queue)?;
+
+        let mut viewer = AtlasViewer {
+            vertex_grid: VertexGrid::new(device, 0, 0, 1),
+            labels: Vec::new(),
+            digits: digits,
+            window: window.clone(),
+            atlas_size: 0,
+            palette: 0
+        };
+        viewer.set_atlas_size(device, atlas_size);
+        Ok((viewer, future))
+    }
+
+    // Remake the grid for an atlas of a different size.
+    pub fn set_atlas_size(&mut self, device: &Arc<Device>, atlas_size: usize) {
+        if atlas_size == self.atlas_size {
+            return;
+        }
+        self.atlas_size = atlas_size;
+
+        self.vertex_grid = VertexGrid::new(device, atlas_size, atlas_size, atlas_size);
+        for y in 0..atlas_size {
+            for x in 0..atlas_size {
+                self.vertex_grid.set_tile_texture(x, y, x, y);
+                self.vertex_grid.set_tile_palette(x, y, self.palette);
+            }
+        }
+
+        self.labels = (0..atlas_size).map(|y| {
+            let mut strip = VertexGrid::new(device, atlas_size * LABEL_LEN, 1, DIGIT_ATLAS_SIZE);
+            for x in 0..atlas_size {
+                let number = (y * atlas_size + x).to_string();
+                for i in 0..LABEL_LEN {
+                    let digit = number.chars().nth(i).and_then(|c| c.to_digit(10)).map(|d| d as usize).unwrap_or(BLANK);
+                    strip.set_tile_texture(x * LABEL_LEN + i, 0, digit % DIGIT_ATLAS_SIZE, digit / DIGIT_ATLAS_SIZE);
+                    strip.set_tile_palette(x * LABEL_LEN + i, 0, self.palette);
+                }
+            }
+            strip
+        }).collect();
+    }
+
+    // Draw the atlas through a palette.
+    pub fn show(&mut self, palette: u32) {
+        if palette == self.palette {
+            return;
+        }
+        self.palette = palette;
+
+        for y in 0..self.atlas_size {
+            for x in 0..self.atlas_size {
+                self.vertex_grid.set_tile_palette(x, y, palette);
+            }
+        }
+        for strip in self.labels.iter_mut() {
+            for x in 0..strip.width() {
+                strip.set_tile_palette(x, 0, palette);
+            }
+        }
+    }
+
+    // The right half of the window, as a square.
+    pub fn viewport(&self) -> Viewport {
+        let [width, height] = self.window.dimensions;
+        let side = (width / 2.0).min(height);
+        Viewport {
+            origin: [self.window.origin[0] + width - side, self.window.origin[1] + (height - side) / 2.0],
+            dimensions: [side, side],
+            depth_range: self.window.depth_range.clone()
+        }
+    }
+
+    // Viewport for the main view beside the viewer: the rest of the window.
+    pub fn main_viewport(&self) -> Viewport {
+        let mut viewport = self.window.clone();
+        viewport.dimensions[0] -= self.viewport().dimensions[0];
+        viewport
+    }
+
+    // Size of the atlas in textures.
+    pub fn atlas_size(&self) -> usize {
+        self.atlas_size
+    }
+
+    // Digit texture atlas for the labels.
+    pub fn digits(&self) -> Arc<ImmutableImage<R8Uint>> {
+        self.digits.clone()
+    }
+
+    pub fn get_vertex_buffer(&mut self, frame: usize) -> Result<CpuBufferPoolChunk<Vertex, Arc<StdMemoryPool>>, TileError> {
+        self.vertex_grid.get_vertex_buffer(frame)
+    }
+
+    // Viewport and vertex buffer of each label strip. There are none if the slots are too small to label.
+    pub fn get_label_buffers(&mut self, frame: usize) -> Result<Vec<LabelBuffer>, TileError> {
+        let viewport = self.viewport();
+        let slot = viewport.dimensions[0] / self.atlas_size as f32;
+        if slot < MIN_LABEL_SLOT {
+            return Ok(Vec::new());
+        }
+
+        // Each digit is square, so the strip is as tall as one digit.
+        let digit = slot / LABEL_LEN as f32;
+        self.labels.iter_mut().enumerate().map(|(y, strip)| {
+            let strip_viewport = Viewport {
+                origin: [viewport.origin[0], viewport.origin[1] + y as f32 * slot],
+                dimensions: [viewport.dimensions[0], digit],
+                depth_range: viewport.depth_range.clone()
+            };
+            Ok((strip_viewport, strip.get_vertex_buffer(frame)?))
+        }).collect()
+    }
+
+    // Bytes of vertex data uploaded since the last call, for the slots and labels.
+    pub fn take_uploaded_bytes(&mut self) -> usize {
+        self.vertex_grid.take_uploaded_bytes() + self.labels.iter_mut().map(|strip| strip.take_uploaded_bytes()).sum::<usize>()
+    }
+}
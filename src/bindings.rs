@@ -0,0 +1,499 @@
+// Key bindings for the demo, loaded from a config file.
+//
+// Each line binds an action to one or more keys, separated by commas:
+//   select_tile 0 0 = a
+//   undo = ctrl+z, ctrl+backspace
+// Binding an action in the file replaces its default keys. Blank lines and lines starting with '#' are ignored.
+use winit::{
+    ModifiersState,
+    VirtualKeyCode
+};
+
+use tile_test::imagegen::Generator;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs,
+    io,
+    path::Path
+};
+
+// Names of keys that can be bound.
+const KEY_NAMES: [(&str, VirtualKeyCode); 75] = {
+    use winit::VirtualKeyCode::*;
+    [
+        ("1", Key1), ("2", Key2), ("3", Key3), ("4", Key4), ("5", Key5),
+        ("6", Key6), ("7", Key7), ("8", Key8), ("9", Key9), ("0", Key0),
+        ("a", A), ("b", B), ("c", C), ("d", D), ("e", E), ("f", F), ("g", G), ("h", H), ("i", I),
+        ("j", J), ("k", K), ("l", L), ("m", M), ("n", N), ("o", O), ("p", P), ("q", Q), ("r", R),
+        ("s", S), ("t", T), ("u", U), ("v", V), ("w", W), ("x", X), ("y", Y), ("z", Z),
+        ("f1", F1), ("f2", F2), ("f3", F3), ("f4", F4), ("f5", F5), ("f6", F6),
+        ("f7", F7), ("f8", F8), ("f9", F9), ("f10", F10), ("f11", F11), ("f12", F12),
+        ("escape", Escape), ("enter", Return), ("space", Space), ("backspace", Back), ("tab", Tab),
+        ("insert", Insert), ("delete", Delete), ("home", Home), ("end", End),
+        ("pageup", PageUp), ("pagedown", PageDown),
+        ("left", Left), ("right", Right), ("up", Up), ("down", Down),
+        ("minus", Minus), ("equals", Equals), ("comma", Comma), ("period", Period), ("slash", Slash),
+        ("backslash", Backslash), ("semicolon", Semicolon), ("apostrophe", Apostrophe), ("grave", Grave),
+        ("lbracket", LBracket), ("rbracket", RBracket), ("numpadenter", NumpadEnter)
+    ]
+};
+
+#[derive(Debug)]
+pub enum BindingError {
+    Io(io::Error),
+    Parse{ line: usize, message: String },
+    // A key is bound to two actions that can be used at the same time.
+    Conflict{ key: KeyChord, first: Action, second: Action }
+}
+
+impl fmt::Display for BindingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindingError::Io(e)                         => write!(f, "IO error: {}", e),
+            BindingError::Parse{ line, message }        => write!(f, "Invalid key binding on line {}: {}", line, message),
+            BindingError::Conflict{ key, first, second } => write!(f, "Key '{}' is bound to both '{}' and '{}'", key, first, second)
+        }
+    }
+}
+
+impl std::error::Error for BindingError {}
+
+impl From<io::Error> for BindingError {
+    fn from(e: io::Error) -> Self {
+        BindingError::Io(e)
+    }
+}
+
+// A key with modifiers.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct KeyChord {
+    pub key: VirtualKeyCode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool
+}
+
+impl KeyChord {
+    pub fn new(key: VirtualKeyCode, modifiers: ModifiersState) -> Self {
+        KeyChord {
+            key: key,
+            ctrl: modifiers.ctrl,
+            shift: modifiers.shift,
+            alt: modifiers.alt
+        }
+    }
+
+    // Parse a key name with optional modifiers, e.g. "ctrl+shift+z".
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split('+').map(|p| p.trim().to_lowercase()).collect::<Vec<_>>();
+        let name = parts.pop()?;
+        let key = KEY_NAMES.iter().find(|(n, _)| *n == name)?.1;
+
+        let mut chord = KeyChord{ key: key, ctrl: false, shift: false, alt: false };
+        for modifier in parts.iter() {
+            match modifier.as_str() {
+                "ctrl" => chord.ctrl = true,
+                "shift" => chord.shift = true,
+                "alt" => chord.alt = true,
+                _ => return None
+            }
+        }
+        Some(chord)
+    }
+
+    fn plain(key: VirtualKeyCode) -> Self {
+        KeyChord{ key: key, ctrl: false, shift: false, alt: false }
+    }
+
+    fn ctrl(key: VirtualKeyCode) -> Self {
+        KeyChord{ key: key, ctrl: true, shift: false, alt: false }
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "shift+")?;
+        }
+        if self.alt {
+            write!(f, "alt+")?;
+        }
+        let name = KEY_NAMES.iter().find(|(_, k)| *k == self.key).map(|(n, _)| *n).unwrap_or("?");
+        write!(f, "{}", name)
+    }
+}
+
+// Names of colour channels, as used by adjust_colour.
+const CHANNEL_NAMES: [&str; 3] = ["red", "green", "blue"];
+
+// Input state in which an action can be used.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Context {
+    // Nothing selected.
+    Neutral,
+    // A tile is selected.
+    Tile,
+    // A texture is selected.
+    Texture
+}
+
+// What a typed number selects.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum IndexTarget {
+    // Grid tile, numbered in row-major order.
+    Tile,
+    // Atlas texture, numbered in row-major order.
+    Texture,
+    Palette
+}
+
+impl IndexTarget {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndexTarget::Tile       => "tile",
+            IndexTarget::Texture    => "texture",
+            IndexTarget::Palette    => "palette"
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [IndexTarget::Tile, IndexTarget::Texture, IndexTarget::Palette].iter().find(|t| t.name() == name).cloned()
+    }
+}
+
+// Something a key can do.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Action {
+    // Move the cursor by a number of tiles.
+    MoveCursor(isize, isize),
+    // Change the current texture, by a number of textures or by a number of atlas rows.
+    StepTexture(isize),
+    StepTextureRow(isize),
+    // Change the current palette.
+    StepPalette(isize),
+    // Assign the current texture or palette to the tile under the cursor.
+    PaintTexture,
+    PaintPalette,
+    // Regenerate the current texture.
+    GenerateCurrent(Generator),
+    // Start typing the index of a tile, texture or palette.
+    TypeIndex(IndexTarget),
+    // Open or close the texel editor for the current texture.
+    EditTexels,
+    // Change the colour index used by the texel editor, which is also the palette entry being edited.
+    StepColour(isize),
+    // Open or close the palette editor.
+    EditPalettes,
+    // Change a channel (0 to 2 for red, green and blue) of the current palette entry, in 5-bit steps.
+    AdjustColour(usize, isize),
+    // Turn rounding of edited colours to 15-bit console colours on or off.
+    ToggleQuantise,
+    // Show or hide the atlas viewer.
+    ViewAtlas,
+    // Show or hide the frame statistics overlay.
+    ViewStats,
+    // Select a grid tile.
+    SelectTile(usize, usize),
+    // Select a texture, or assign it to the selected tile.
+    Texture(usize, usize),
+    // Assign a palette to the selected tile.
+    Palette(u32),
+    // Regenerate the selected texture.
+    Generate(Generator),
+    Export,
+    Save,
+    Load,
+    Undo,
+    Redo
+}
+
+impl Action {
+    // Input states the action can be used in.
+    pub fn contexts(&self) -> &'static [Context] {
+        match self {
+            Action::Texture(..)         => &[Context::Neutral, Context::Tile],
+            Action::Palette(_)          => &[Context::Tile],
+            Action::Generate(_)         => &[Context::Texture],
+            Action::Undo | Action::Redo => &[Context::Neutral, Context::Tile, Context::Texture],
+            _                           => &[Context::Neutral]
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let words = s.split_whitespace().collect::<Vec<_>>();
+        let num = |i: usize| words.get(i).and_then(|w| w.parse::<usize>().ok());
+        let signed = |i: usize| words.get(i).and_then(|w| w.parse::<isize>().ok());
+        match (words.first().cloned()?, words.len()) {
+            ("move_cursor", 3)      => Some(Action::MoveCursor(signed(1)?, signed(2)?)),
+            ("step_texture", 2)     => Some(Action::StepTexture(signed(1)?)),
+            ("step_texture_row", 2) => Some(Action::StepTextureRow(signed(1)?)),
+            ("step_palette", 2)     => Some(Action::StepPalette(signed(1)?)),
+            ("paint_texture", 1)    => Some(Action::PaintTexture),
+            ("paint_palette", 1)    => Some(Action::PaintPalette),
+            ("generate_current", 2) => Generator::from_name(words[1]).map(Action::GenerateCurrent),
+            ("type", 2)             => IndexTarget::from_name(words[1]).map(Action::TypeIndex),
+            ("edit_texels", 1)      => Some(Action::EditTexels),
+            ("step_colour", 2)      => Some(Action::StepColour(signed(1)?)),
+            ("edit_palettes", 1)    => Some(Action::EditPalettes),
+            ("adjust_colour", 3)    => Some(Action::AdjustColour(CHANNEL_NAMES.iter().position(|n| *n == words[1])?, signed(2)?)),
+            ("quantise", 1)         => Some(Action::ToggleQuantise),
+            ("view_atlas", 1)       => Some(Action::ViewAtlas),
+            ("view_stats", 1)       => Some(Action::ViewStats),
+            ("select_tile", 3)      => Some(Action::SelectTile(num(1)?, num(2)?)),
+            ("texture", 3)          => Some(Action::Texture(num(1)?, num(2)?)),
+            ("palette", 2)          => Some(Action::Palette(num(1)? as u32)),
+            ("generate", 2)         => Generator::from_name(words[1]).map(Action::Generate),
+            ("export", 1)           => Some(Action::Export),
+            ("save", 1)             => Some(Action::Save),
+            ("load", 1)             => Some(Action::Load),
+            ("undo", 1)             => Some(Action::Undo),
+            ("redo", 1)             => Some(Action::Redo),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::MoveCursor(x, y)    => write!(f, "move_cursor {} {}", x, y),
+            Action::StepTexture(n)      => write!(f, "step_texture {}", n),
+            Action::StepTextureRow(n)   => write!(f, "step_texture_row {}", n),
+            Action::StepPalette(n)      => write!(f, "step_palette {}", n),
+            Action::PaintTexture        => write!(f, "paint_texture"),
+            Action::PaintPalette        => write!(f, "paint_palette"),
+            Action::GenerateCurrent(g)  => write!(f, "generate_current {}", g.name()),
+            Action::TypeIndex(t)        => write!(f, "type {}", t.name()),
+            Action::EditTexels          => write!(f, "edit_texels"),
+            Action::StepColour(n)       => write!(f, "step_colour {}", n),
+            Action::EditPalettes        => write!(f, "edit_palettes"),
+            Action::AdjustColour(c, n)  => write!(f, "adjust_colour {} {}", CHANNEL_NAMES[*c], n),
+            Action::ToggleQuantise      => write!(f, "quantise"),
+            Action::ViewAtlas           => write!(f, "view_atlas"),
+            Action::ViewStats           => write!(f, "view_stats"),
+            Action::SelectTile(x, y)    => write!(f, "select_tile {} {}", x, y),
+            Action::Texture(x, y)       => write!(f, "texture {} {}", x, y),
+            Action::Palette(p)          => write!(f, "palette {}", p),
+            Action::Generate(g)         => write!(f, "generate {}", g.name()),
+            Action::Export              => write!(f, "export"),
+            Action::Save                => write!(f, "save"),
+            Action::Load                => write!(f, "load"),
+            Action::Undo                => write!(f, "undo"),
+            Action::Redo                => write!(f, "redo")
+        }
+    }
+}
+
+pub struct KeyBindings {
+    // Bindings in the order they were made.
+    bindings: Vec<(KeyChord, Action)>,
+    lookup: HashMap<KeyChord, Vec<Action>>
+}
+
+impl KeyBindings {
+    // Load bindings from a file, on top of the defaults.
+    pub fn load(path: &Path) -> Result<Self, BindingError> {
+        KeyBindings::parse(&fs::read_to_string(path)?)
+    }
+
+    // Parse bindings, on top of the defaults.
+    pub fn parse(text: &str) -> Result<Self, BindingError> {
+        let mut file_bindings = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let parse_err = |message: String| BindingError::Parse{ line: i + 1, message: message };
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (action, keys) = match line.find('=') {
+                Some(pos) => (&line[..pos], &line[(pos + 1)..]),
+                None => return Err(parse_err("expected '<action> = <keys>'".to_string()))
+            };
+            let action = Action::parse(action).ok_or_else(|| parse_err(format!("unknown action '{}'", action.trim())))?;
+            for key in keys.split(',') {
+                let chord = KeyChord::parse(key).ok_or_else(|| parse_err(format!("unknown key '{}'", key.trim())))?;
+                file_bindings.push((chord, action));
+            }
+        }
+
+        // Actions in the file lose their default keys.
+        let mut bindings = KeyBindings::default_bindings();
+        bindings.retain(|(_, action)| !file_bindings.iter().any(|(_, a)| a == action));
+        bindings.extend(file_bindings);
+        KeyBindings::new(bindings)
+    }
+
+    // Make bindings, checking that no key does two things in the same context.
+    pub fn new(bindings: Vec<(KeyChord, Action)>) -> Result<Self, BindingError> {
+        let mut lookup: HashMap<KeyChord, Vec<Action>> = HashMap::new();
+        for (key, action) in bindings.iter() {
+            let actions = lookup.entry(*key).or_default();
+            if let Some(other) = actions.iter().find(|a| *a != action && a.contexts().iter().any(|c| action.contexts().contains(c))) {
+                return Err(BindingError::Conflict{ key: *key, first: *other, second: *action });
+            }
+            if !actions.contains(action) {
+                actions.push(*action);
+            }
+        }
+
+        Ok(KeyBindings {
+            bindings: bindings,
+            lookup: lookup
+        })
+    }
+
+    // Actions bound to a key.
+    pub fn actions(&self, key: KeyChord) -> &[Action] {
+        self.lookup.get(&key).map(|a| a.as_slice()).unwrap_or(&[])
+    }
+
+    // The default layout: tiles on 1234/qwer/asdf/zxcv, palettes on tyui and textures on ghjk.
+    // For larger grids and atlases, the arrow keys move a cursor, and the current texture and palette
+    // are changed with [ ] (pageup and pagedown for a row at a time) and , . respectively.
+    // Tab opens the texel editor, and - = change its colour. ` opens the palette editor, where ctrl+r/g/b
+    // raise a channel of the current colour and ctrl+shift+r/g/b lower it. \ shows the atlas viewer.
+    fn default_bindings() -> Vec<(KeyChord, Action)> {
+        use winit::VirtualKeyCode::*;
+        let tile_keys = [[Key1, Key2, Key3, Key4], [Q, W, E, R], [A, S, D, F], [Z, X, C, V]];
+        let palette_keys = [T, Y, U, I];
+        let texture_keys = [(G, 0, 0), (H, 1, 0), (J, 0, 1), (K, 1, 1)];
+        let generator_keys = [
+            (Return, Generator::Random),
+            (Key1, Generator::Checkerboard),
+            (Key2, Generator::Gradient),
+            (Key3, Generator::Noise),
+            (Key4, Generator::Dither),
+            (Key5, Generator::Glyph)
+        ];
+
+        let mut bindings = Vec::new();
+        for (y, row) in tile_keys.iter().enumerate() {
+            for (x, key) in row.iter().enumerate() {
+                bindings.push((KeyChord::plain(*key), Action::SelectTile(x, y)));
+            }
+        }
+        for (p, key) in palette_keys.iter().enumerate() {
+            bindings.push((KeyChord::plain(*key), Action::Palette(p as u32)));
+        }
+        for (key, x, y) in texture_keys.iter() {
+            bindings.push((KeyChord::plain(*key), Action::Texture(*x, *y)));
+        }
+        for (key, generator) in generator_keys.iter() {
+            bindings.push((KeyChord::plain(*key), Action::Generate(*generator)));
+        }
+        bindings.push((KeyChord::plain(Left), Action::MoveCursor(-1, 0)));
+        bindings.push((KeyChord::plain(Right), Action::MoveCursor(1, 0)));
+        bindings.push((KeyChord::plain(Up), Action::MoveCursor(0, -1)));
+        bindings.push((KeyChord::plain(Down), Action::MoveCursor(0, 1)));
+        bindings.push((KeyChord::plain(LBracket), Action::StepTexture(-1)));
+        bindings.push((KeyChord::plain(RBracket), Action::StepTexture(1)));
+        bindings.push((KeyChord::plain(PageUp), Action::StepTextureRow(-1)));
+        bindings.push((KeyChord::plain(PageDown), Action::StepTextureRow(1)));
+        bindings.push((KeyChord::plain(Comma), Action::StepPalette(-1)));
+        bindings.push((KeyChord::plain(Period), Action::StepPalette(1)));
+        bindings.push((KeyChord::plain(Space), Action::PaintTexture));
+        bindings.push((KeyChord::plain(Return), Action::PaintPalette));
+        bindings.push((KeyChord{ key: Return, ctrl: false, shift: true, alt: false }, Action::GenerateCurrent(Generator::Random)));
+        bindings.push((KeyChord::plain(B), Action::TypeIndex(IndexTarget::Tile)));
+        bindings.push((KeyChord::plain(N), Action::TypeIndex(IndexTarget::Texture)));
+        bindings.push((KeyChord::plain(M), Action::TypeIndex(IndexTarget::Palette)));
+        bindings.push((KeyChord::plain(Tab), Action::EditTexels));
+        bindings.push((KeyChord::plain(Minus), Action::StepColour(-1)));
+        bindings.push((KeyChord::plain(Equals), Action::StepColour(1)));
+        bindings.push((KeyChord::plain(Grave), Action::EditPalettes));
+        for (channel, key) in [R, G, B].iter().enumerate() {
+            bindings.push((KeyChord::ctrl(*key), Action::AdjustColour(channel, 1)));
+            bindings.push((KeyChord{ key: *key, ctrl: true, shift: true, alt: false }, Action::AdjustColour(channel, -1)));
+        }
+        bindings.push((KeyChord::ctrl(Q), Action::ToggleQuantise));
+        bindings.push((KeyChord::plain(Backslash), Action::ViewAtlas));
+        bindings.push((KeyChord::plain(F3), Action::ViewStats));
+        bindings.push((KeyChord::plain(P), Action::Export));
+        bindings.push((KeyChord::plain(O), Action::Save));
+        bindings.push((KeyChord::plain(L), Action::Load));
+        bindings.push((KeyChord::ctrl(Z), Action::Undo));
+        bindings.push((KeyChord{ key: Z, ctrl: true, shift: true, alt: false }, Action::Redo));
+        bindings.push((KeyChord::ctrl(Y), Action::Redo));
+        bindings
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings::new(KeyBindings::default_bindings()).expect("Default key bindings conflict")
+    }
+}
+
+// Written in the config file format.
+impl fmt::Display for KeyBindings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut actions: Vec<(Action, Vec<KeyChord>)> = Vec::new();
+        for (key, action) in self.bindings.iter() {
+            match actions.iter_mut().find(|(a, _)| a == action) {
+                Some((_, keys)) => keys.push(*key),
+                None => actions.push((*action, vec![*key]))
+            }
+        }
+
+        for (action, keys) in actions.iter() {
+            let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+            writeln!(f, "{} = {}", action, keys.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::VirtualKeyCode::*;
+
+    #[test]
+    fn default_bindings_have_no_conflicts() {
+        assert!(KeyBindings::new(KeyBindings::default_bindings()).is_ok());
+        let bindings = KeyBindings::default();
+        assert_eq!(bindings.actions(KeyChord::ctrl(Z)), &[Action::Undo]);
+        // Keys can do different things in different contexts.
+        assert_eq!(bindings.actions(KeyChord::plain(Key1)), &[Action::SelectTile(0, 0), Action::Generate(Generator::Checkerboard)]);
+    }
+
+    #[test]
+    fn key_chords() {
+        assert_eq!(KeyChord::parse("ctrl+shift+z"), Some(KeyChord{ key: Z, ctrl: true, shift: true, alt: false }));
+        assert_eq!(KeyChord::parse(" Alt + PageUp "), Some(KeyChord{ key: PageUp, ctrl: false, shift: false, alt: true }));
+        assert_eq!(KeyChord::parse("hyper+z"), None);
+        assert_eq!(KeyChord::parse("ctrl+"), None);
+        assert_eq!(KeyChord::parse("nothing"), None);
+        let chord = KeyChord{ key: F3, ctrl: true, shift: false, alt: true };
+        assert_eq!(KeyChord::parse(&chord.to_string()), Some(chord));
+    }
+
+    #[test]
+    fn file_bindings_replace_defaults() {
+        let bindings = KeyBindings::parse("# comment\n\nundo = f5, ctrl+u\n").unwrap();
+        assert_eq!(bindings.actions(KeyChord::plain(F5)), &[Action::Undo]);
+        assert_eq!(bindings.actions(KeyChord::ctrl(U)), &[Action::Undo]);
+        assert!(bindings.actions(KeyChord::ctrl(Z)).is_empty());
+        assert_eq!(bindings.actions(KeyChord::ctrl(Y)), &[Action::Redo]);
+
+        // The printed bindings read back the same.
+        let text = bindings.to_string();
+        assert_eq!(KeyBindings::parse(&text).unwrap().to_string(), text);
+    }
+
+    #[test]
+    fn bad_bindings_are_rejected() {
+        assert!(matches!(KeyBindings::parse("save = f5\nload = f5"), Err(BindingError::Conflict{ .. })));
+        // Export is only used with nothing selected, but redo can be used at any time.
+        assert!(matches!(KeyBindings::parse("export = ctrl+y"), Err(BindingError::Conflict{ .. })));
+        assert!(matches!(KeyBindings::parse("save f5"), Err(BindingError::Parse{ line: 1, .. })));
+        assert!(matches!(KeyBindings::parse("\nfly = f5"), Err(BindingError::Parse{ line: 2, .. })));
+        assert!(matches!(KeyBindings::parse("save = hyper"), Err(BindingError::Parse{ line: 1, .. })));
+    }
+}
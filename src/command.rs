@@ -0,0 +1,121 @@
+// Commands that alter the scene, with a one line text form for command logs.
+use super::imagegen::Generator;
+
+use std::{
+    convert::TryFrom,
+    fmt,
+    str::FromStr
+};
+
+// Command to alter visual data.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Command {
+    ModifyTilePalette{
+        palette: u32,
+        x: usize,
+        y: usize
+    },
+    ModifyTileTexture{
+        tex_x: usize,
+        tex_y: usize,
+        x: usize,
+        y: usize
+    },
+    GenerateTexture{
+        tex_x: usize,
+        tex_y: usize,
+        generator: Generator
+    },
+    SetTexel{
+        tex_x: usize,
+        tex_y: usize,
+        x: usize,
+        y: usize,
+        value: u8
+    },
+    // Set the RGB of a palette colour. Alpha is kept.
+    SetColour{
+        palette: usize,
+        index: usize,
+        rgb: [u8; 3]
+    },
+    ExportImages,
+    SaveProject,
+    LoadProject,
+    Undo,
+    Redo
+}
+
+impl Command {
+    // Whether the command reads or writes files in the working directory. These are skipped during replays.
+    pub fn uses_files(&self) -> bool {
+        matches!(self, Command::ExportImages | Command::SaveProject | Command::LoadProject)
+    }
+}
+
+// Written as a name followed by the arguments, e.g. "palette 1 2 3" to set tile (1, 2) to palette 3.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Command::*;
+        match self {
+            ModifyTilePalette{ palette, x, y }         => write!(f, "palette {} {} {}", x, y, palette),
+            ModifyTileTexture{ tex_x, tex_y, x, y }    => write!(f, "texture {} {} {} {}", x, y, tex_x, tex_y),
+            GenerateTexture{ tex_x, tex_y, generator } => write!(f, "generate {} {} {}", tex_x, tex_y, generator.name()),
+            SetTexel{ tex_x, tex_y, x, y, value }      => write!(f, "texel {} {} {} {} {}", tex_x, tex_y, x, y, value),
+            SetColour{ palette, index, rgb }           => write!(f, "colour {} {} {} {} {}", palette, index, rgb[0], rgb[1], rgb[2]),
+            ExportImages                               => write!(f, "export"),
+            SaveProject                                => write!(f, "save"),
+            LoadProject                                => write!(f, "load"),
+            Undo                                       => write!(f, "undo"),
+            Redo                                       => write!(f, "redo")
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseCommandError(String);
+
+impl fmt::Display for ParseCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid command: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseCommandError {}
+
+impl FromStr for Command {
+    type Err = ParseCommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::Command::*;
+        let err = || ParseCommandError(s.to_string());
+        let mut words = s.split_whitespace().collect::<Vec<_>>();
+        let name = words.first().cloned().ok_or_else(err)?;
+
+        // The generator is the last argument of "generate". Older logs leave it out.
+        let generator = if name == "generate" && words.len() == 4 {
+            Generator::from_name(words.pop().unwrap()).ok_or_else(err)?
+        } else {
+            Generator::Random
+        };
+
+        let args = words[1..].iter().map(|w| w.parse::<usize>()).collect::<Result<Vec<_>, _>>().map_err(|_| err())?;
+
+        match (name, args.as_slice()) {
+            ("palette", [x, y, palette])        => Ok(ModifyTilePalette{ palette: *palette as u32, x: *x, y: *y }),
+            ("texture", [x, y, tex_x, tex_y])   => Ok(ModifyTileTexture{ tex_x: *tex_x, tex_y: *tex_y, x: *x, y: *y }),
+            ("generate", [tex_x, tex_y])        => Ok(GenerateTexture{ tex_x: *tex_x, tex_y: *tex_y, generator: generator }),
+            ("texel", [tex_x, tex_y, x, y, v])  => Ok(SetTexel{ tex_x: *tex_x, tex_y: *tex_y, x: *x, y: *y, value: u8::try_from(*v).map_err(|_| err())? }),
+            ("colour", [palette, index, r, g, b]) => {
+                let channel = |c: usize| u8::try_from(c).map_err(|_| err());
+                Ok(SetColour{ palette: *palette, index: *index, rgb: [channel(*r)?, channel(*g)?, channel(*b)?] })
+            },
+            ("export", [])                      => Ok(ExportImages),
+            ("save", [])                        => Ok(SaveProject),
+            ("load", [])                        => Ok(LoadProject),
+            ("undo", [])                        => Ok(Undo),
+            ("redo", [])                        => Ok(Redo),
+            _ => Err(err())
+        }
+    }
+}
@@ -0,0 +1,372 @@
+// Settings for the demo, from the command line and an optional config file.
+//
+// Each line of a config file sets one value:
+//   grid_width = 16
+//   present_mode = mailbox
+// Every setting can also be given on the command line, e.g. "--grid-width 16", which overrides the file.
+// Blank lines and lines starting with '#' are ignored.
+use vulkano::swapchain::PresentMode;
+
+use super::frameloop::{
+    self,
+    LoopMode
+};
+
+use tile_test::{
+    device::DeviceSelection,
+    palettefile
+};
+
+use std::{
+    fmt,
+    fs,
+    io,
+    path::{
+        Path,
+        PathBuf
+    }
+};
+
+pub const USAGE: &str = "\
+Usage: tile_test [options] [map] [palette file]
+
+Options:
+  --config <file>         Read settings from a file. Options on the command line override it.
+  --grid-width <n>        Width of the random grid in tiles. (4)
+  --grid-height <n>       Height of the random grid in tiles. (4)
+  --tile-size <n>         Size of a tile texture in texels. (8)
+  --atlas-size <n>        Size of the random atlas in textures. (2)
+  --bit-depth <n>         Bits per texel, 1 or 2. (2)
+  --scale <n>             Window pixels per texel. (16)
+  --present-mode <mode>   fifo, mailbox, immediate or relaxed. (fifo)
+  --loop <mode>           redraw, to draw only when something changes, or fixed, to draw at the tick rate. (redraw)
+  --tick-rate <rate>      Ticks per second for the fixed loop, or gb (59.73) or nes (60.0988). (60)
+  --frame-stats           Print frame timing statistics every few seconds.
+  --stats-csv <file>      Write the CPU time and upload size of every frame to a CSV file, or - for stdout.
+  --frames-in-flight <n>  Frames the CPU can record ahead of the GPU, from 1 to 3. (2)
+  --device <device>       Device to draw with: an index or part of a name from --list-devices,
+                          or the type to prefer: discrete, integrated, virtual or cpu. (discrete)
+  --list-devices          Print the available devices and exit.
+  --seed <n>              Seed for the random number generator.
+  --scene <file>          Project file to start with, instead of a random scene.
+  --bindings <file>       Key bindings file.
+  --print-bindings        Print the key bindings and exit.
+  --record <log>          Write every command to a log.
+  --replay <log>          Play back a command log.
+  --help                  Print this message and exit.
+";
+
+// Largest grid side, in tiles.
+const MAX_GRID_SIZE: usize = 256;
+// Largest atlas side, in texels. Vulkan devices must support 2D images at least this big.
+const MAX_ATLAS_WIDTH: usize = 4096;
+// Palettes in the shader hold 4 colours.
+const MAX_BIT_DEPTH: u32 = 2;
+const MAX_SCALE: u32 = 64;
+const MAX_FRAMES_IN_FLIGHT: usize = 3;
+const MAX_TICK_RATE: f64 = 1000.0;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse{ line: usize, message: String },
+    Argument(String),
+    // A setting is out of range, or settings conflict.
+    Invalid(String)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e)                  => write!(f, "Couldn't read config file: {}", e),
+            ConfigError::Parse{ line, message } => write!(f, "Invalid setting on line {} of config file: {}", line, message),
+            ConfigError::Argument(message)      => write!(f, "Invalid argument: {}", message),
+            ConfigError::Invalid(message)       => write!(f, "Invalid config: {}", message)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+pub struct Config {
+    // Size of the random grid in tiles.
+    pub grid_width: usize,
+    pub grid_height: usize,
+    // Size of a tile texture in texels.
+    pub tile_size: usize,
+    // Size of the random atlas in textures.
+    pub atlas_size: usize,
+    // Bits per texel, which sets the colours in each palette.
+    pub bit_depth: u32,
+    // Window pixels per texel.
+    pub scale: u32,
+    pub present_mode: PresentMode,
+    pub loop_mode: LoopMode,
+    // Ticks per second in the fixed rate loop.
+    pub tick_rate: f64,
+    // Print frame timing statistics.
+    pub frame_stats: bool,
+    // CSV file to log the profile of every frame to. "-" is stdout.
+    pub stats_csv: Option<PathBuf>,
+    // Frames that can be drawing at once.
+    pub frames_in_flight: usize,
+    pub device: DeviceSelection,
+    // Seed for the random number generator.
+    pub seed: Option<u64>,
+    // Project file to start with.
+    pub scene: Option<PathBuf>,
+    // Key bindings file.
+    pub bindings: Option<PathBuf>,
+    // Map and palette files.
+    pub files: Vec<PathBuf>,
+    // Command log to write.
+    pub record: Option<PathBuf>,
+    // Command log to play back.
+    pub replay: Option<PathBuf>,
+    // Print the key bindings and exit.
+    pub print_bindings: bool,
+    // Print the available devices and exit.
+    pub list_devices: bool,
+    // Print the usage and exit.
+    pub help: bool
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            grid_width: 4,
+            grid_height: 4,
+            tile_size: 8,
+            atlas_size: 2,
+            bit_depth: 2,
+            scale: 16,
+            present_mode: PresentMode::Fifo,
+            loop_mode: LoopMode::Redraw,
+            tick_rate: 60.0,
+            frame_stats: false,
+            stats_csv: None,
+            frames_in_flight: 2,
+            device: DeviceSelection::default(),
+            seed: None,
+            scene: None,
+            bindings: None,
+            files: Vec::new(),
+            record: None,
+            replay: None,
+            print_bindings: false,
+            list_devices: false,
+            help: false
+        }
+    }
+}
+
+impl Config {
+    // Read the command line arguments, after the program name.
+    pub fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Self, ConfigError> {
+        let mut config = Config::default();
+        let mut config_path = None;
+        // Settings are applied after the config file, so they override it.
+        let mut settings = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| ConfigError::Argument(format!("{} needs a value", name)));
+            match arg.as_str() {
+                "--config"          => config_path = Some(PathBuf::from(value(&arg)?)),
+                "--record"          => config.record = Some(value(&arg)?.into()),
+                "--replay"          => config.replay = Some(value(&arg)?.into()),
+                "--print-bindings"  => config.print_bindings = true,
+                "--list-devices"    => config.list_devices = true,
+                "--frame-stats"     => config.frame_stats = true,
+                "--help"            => config.help = true,
+                _ if arg.starts_with("--") => {
+                    let name = arg[2..].replace('-', "_");
+                    settings.push((arg.clone(), name, value(&arg)?));
+                },
+                _ => config.files.push(arg.into())
+            }
+        }
+
+        if let Some(path) = config_path {
+            config.load(&path)?;
+        }
+        for (arg, name, value) in settings {
+            config.set(&name, &value).map_err(|message| ConfigError::Argument(format!("{}: {}", arg, message)))?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Apply settings from a config file.
+    pub fn load(&mut self, path: &Path) -> Result<(), ConfigError> {
+        self.parse(&fs::read_to_string(path)?)
+    }
+
+    // Apply settings from the text of a config file.
+    pub fn parse(&mut self, text: &str) -> Result<(), ConfigError> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let parse_err = |message: String| ConfigError::Parse{ line: i + 1, message: message };
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = match line.find('=') {
+                Some(pos) => (line[..pos].trim(), line[(pos + 1)..].trim()),
+                None => return Err(parse_err("expected '<setting> = <value>'".to_string()))
+            };
+            self.set(name, value).map_err(|message| parse_err(format!("{}: {}", name, message)))?;
+        }
+        Ok(())
+    }
+
+    // Set one setting by name.
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "grid_width"    => self.grid_width = number(value)?,
+            "grid_height"   => self.grid_height = number(value)?,
+            "tile_size"     => self.tile_size = number(value)?,
+            "atlas_size"    => self.atlas_size = number(value)?,
+            "bit_depth"     => self.bit_depth = number(value)?,
+            "scale"         => self.scale = number(value)?,
+            "present_mode"  => self.present_mode = present_mode_from_name(value).ok_or_else(|| format!("unknown present mode '{}'", value))?,
+            "loop"          => self.loop_mode = frameloop::loop_mode_from_name(value).ok_or_else(|| format!("unknown loop mode '{}'", value))?,
+            "tick_rate"     => self.tick_rate = frameloop::tick_rate_from_name(value).ok_or_else(|| format!("'{}' is not a tick rate", value))?,
+            "frame_stats"   => self.frame_stats = flag(value)?,
+            "stats_csv"     => self.stats_csv = Some(value.into()),
+            "frames_in_flight" => self.frames_in_flight = number(value)?,
+            "device"        => self.device = DeviceSelection::parse(value),
+            "seed"          => self.seed = Some(number(value)?),
+            "scene"         => self.scene = Some(value.into()),
+            "bindings"      => self.bindings = Some(value.into()),
+            _ => return Err("unknown setting".to_string())
+        }
+        Ok(())
+    }
+
+    // Check that the settings are in range.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+        if (self.grid_width == 0) || (self.grid_width > MAX_GRID_SIZE) || (self.grid_height == 0) || (self.grid_height > MAX_GRID_SIZE) {
+            return invalid(format!("the grid must be from 1 to {} tiles on each side", MAX_GRID_SIZE));
+        }
+        if (self.tile_size == 0) || (self.atlas_size == 0) {
+            return invalid("the tile and atlas sizes must be at least 1".to_string());
+        }
+        if self.tile_size.checked_mul(self.atlas_size).is_none_or(|width| width > MAX_ATLAS_WIDTH) {
+            return invalid(format!("an atlas of {} tiles of {} texels is wider than {} texels", self.atlas_size, self.tile_size, MAX_ATLAS_WIDTH));
+        }
+        if (self.bit_depth == 0) || (self.bit_depth > MAX_BIT_DEPTH) {
+            return invalid(format!("the bit depth must be from 1 to {}", MAX_BIT_DEPTH));
+        }
+        if (self.scale == 0) || (self.scale > MAX_SCALE) {
+            return invalid(format!("the scale must be from 1 to {}", MAX_SCALE));
+        }
+        if !(self.tick_rate > 0.0 && self.tick_rate <= MAX_TICK_RATE) {
+            return invalid(format!("the tick rate must be above 0 and at most {} per second", MAX_TICK_RATE));
+        }
+        if (self.frames_in_flight == 0) || (self.frames_in_flight > MAX_FRAMES_IN_FLIGHT) {
+            return invalid(format!("frames in flight must be from 1 to {}", MAX_FRAMES_IN_FLIGHT));
+        }
+        if self.scene.is_some() && self.files.iter().any(|p| !palettefile::is_palette_file(p)) {
+            return invalid("a scene and a map can't both be loaded".to_string());
+        }
+        Ok(())
+    }
+
+    // Colours in each palette.
+    pub fn colours(&self) -> usize {
+        1 << self.bit_depth
+    }
+}
+
+pub fn present_mode_name(mode: PresentMode) -> &'static str {
+    match mode {
+        PresentMode::Immediate  => "immediate",
+        PresentMode::Mailbox    => "mailbox",
+        PresentMode::Fifo       => "fifo",
+        PresentMode::Relaxed    => "relaxed"
+    }
+}
+
+fn present_mode_from_name(name: &str) -> Option<PresentMode> {
+    [PresentMode::Immediate, PresentMode::Mailbox, PresentMode::Fifo, PresentMode::Relaxed].iter()
+        .find(|m| present_mode_name(**m) == name)
+        .cloned()
+}
+
+fn flag(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on"   => Ok(true),
+        "false" | "no" | "off"  => Ok(false),
+        _ => Err(format!("'{}' is not true or false", value))
+    }
+}
+
+fn number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("'{}' is not a valid number", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Config, ConfigError> {
+        Config::from_args(line.split_whitespace().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn parse_rejects_bad_lines() {
+        let mut config = Config::default();
+        assert!(config.parse("# comment\n\ngrid_width = 8\nscale=4\n").is_ok());
+        assert_eq!((config.grid_width, config.scale), (8, 4));
+        assert!(matches!(config.parse("grid_width 8"), Err(ConfigError::Parse{ line: 1, .. })));
+        assert!(matches!(config.parse("scale = 2\ncolour = red"), Err(ConfigError::Parse{ line: 2, .. })));
+        assert!(matches!(config.parse("scale = big"), Err(ConfigError::Parse{ line: 1, .. })));
+    }
+
+    #[test]
+    fn command_line_overrides_file() {
+        let path = std::env::temp_dir().join(format!("tile_test_config_{}.txt", std::process::id()));
+        fs::write(&path, "grid_width = 8\ngrid_height = 6\npresent_mode = mailbox\n").unwrap();
+        let config = args(&format!("--grid-width 12 --config {}", path.display()));
+        fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!((config.grid_width, config.grid_height), (12, 6));
+        assert_eq!(config.present_mode, PresentMode::Mailbox);
+        assert!(matches!(args("--colour red"), Err(ConfigError::Argument(_))));
+        assert!(matches!(args("--scale"), Err(ConfigError::Argument(_))));
+    }
+
+    #[test]
+    fn settings_out_of_range_are_invalid() {
+        assert!(args("").is_ok());
+        for line in [
+            "--grid-width 0",
+            "--grid-height 257",
+            "--tile-size 0",
+            "--atlas-size 0",
+            "--tile-size 64 --atlas-size 65",
+            "--tile-size 4294967296 --atlas-size 4294967296",
+            "--bit-depth 0",
+            "--bit-depth 3",
+            "--scale 0",
+            "--scale 65",
+            "--tick-rate 0",
+            "--tick-rate 1001",
+            "--frames-in-flight 0",
+            "--frames-in-flight 4",
+            "--scene scene.tile map.tmx"
+        ].iter() {
+            assert!(matches!(args(line), Err(ConfigError::Invalid(_))), "'{}' was accepted", line);
+        }
+        assert!(args("--tile-size 64 --atlas-size 64 --scene scene.tile colours.gpl").is_ok());
+    }
+}
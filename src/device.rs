@@ -0,0 +1,192 @@
+// Choosing a physical device to render with, and describing the devices available.
+use vulkano::{
+    device::{
+        Device,
+        DeviceExtensions,
+        Features
+    },
+    format::R8Uint,
+    image::{
+        Dimensions,
+        ImageCreationError,
+        ImageLayout,
+        ImageUsage,
+        MipmapsCount,
+        immutable::ImmutableImage
+    },
+    instance::{
+        Instance,
+        PhysicalDevice,
+        PhysicalDeviceType,
+        Version
+    }
+};
+
+use super::error::TileError;
+
+use std::{
+    fmt,
+    sync::Arc
+};
+
+// Kinds of device, in the order they are preferred by default.
+const DEVICE_TYPES: [PhysicalDeviceType; 5] = [
+    PhysicalDeviceType::DiscreteGpu,
+    PhysicalDeviceType::IntegratedGpu,
+    PhysicalDeviceType::VirtualGpu,
+    PhysicalDeviceType::Cpu,
+    PhysicalDeviceType::Other
+];
+
+// How to pick a device.
+#[derive(Clone, PartialEq, Debug)]
+pub enum DeviceSelection {
+    // The first device of a type, or of the next best type if there is none.
+    Prefer(PhysicalDeviceType),
+    // A device by its index in the list of devices.
+    Index(usize),
+    // The first device whose name contains some text, ignoring case.
+    Name(String)
+}
+
+impl Default for DeviceSelection {
+    fn default() -> Self {
+        DeviceSelection::Prefer(PhysicalDeviceType::DiscreteGpu)
+    }
+}
+
+impl DeviceSelection {
+    // Parse a device type, a device index or part of a device name.
+    pub fn parse(s: &str) -> Self {
+        if let Some(ty) = device_type_from_name(s) {
+            return DeviceSelection::Prefer(ty);
+        }
+        match s.parse() {
+            Ok(index) => DeviceSelection::Index(index),
+            Err(_) => DeviceSelection::Name(s.to_string())
+        }
+    }
+
+    // Pick a device that can draw.
+    pub fn select<'a>(&self, instance: &'a Arc<Instance>) -> Result<PhysicalDevice<'a>, TileError> {
+        let device = match self {
+            DeviceSelection::Prefer(ty) => {
+                // The preferred type first, then the rest in the default order. Ties go to the first device.
+                let rank = |p: &PhysicalDevice| if p.ty() == *ty { 0 } else { 1 + DEVICE_TYPES.iter().position(|t| *t == p.ty()).unwrap_or(DEVICE_TYPES.len()) };
+                return PhysicalDevice::enumerate(instance)
+                    .filter(has_graphics_queue)
+                    .min_by_key(rank)
+                    .ok_or(TileError::NoDevice);
+            },
+            DeviceSelection::Index(index) => PhysicalDevice::from_index(instance, *index),
+            DeviceSelection::Name(name) => {
+                let name = name.to_lowercase();
+                PhysicalDevice::enumerate(instance).find(|p| p.name().to_lowercase().contains(&name))
+            }
+        };
+
+        match device {
+            Some(device) if has_graphics_queue(&device) => Ok(device),
+            Some(device) => Err(TileError::DeviceNotFound(format!("{} has no graphics queue", device.name()))),
+            None => Err(TileError::DeviceNotFound(self.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for DeviceSelection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceSelection::Prefer(ty)     => write!(f, "{} device", device_type_name(*ty)),
+            DeviceSelection::Index(index)   => write!(f, "device {}", index),
+            DeviceSelection::Name(name)     => write!(f, "device named '{}'", name)
+        }
+    }
+}
+
+pub fn device_type_name(ty: PhysicalDeviceType) -> &'static str {
+    match ty {
+        PhysicalDeviceType::DiscreteGpu     => "discrete",
+        PhysicalDeviceType::IntegratedGpu   => "integrated",
+        PhysicalDeviceType::VirtualGpu      => "virtual",
+        PhysicalDeviceType::Cpu             => "cpu",
+        PhysicalDeviceType::Other           => "other"
+    }
+}
+
+pub fn device_type_from_name(name: &str) -> Option<PhysicalDeviceType> {
+    DEVICE_TYPES.iter().find(|t| device_type_name(**t) == name).cloned()
+}
+
+// Device features the renderer uses. It needs nothing beyond core Vulkan.
+pub fn required_features() -> Features {
+    Features::none()
+}
+
+// A summary of a device, for choosing between them.
+pub struct DeviceInfo {
+    pub index: usize,
+    pub name: String,
+    pub ty: PhysicalDeviceType,
+    pub api_version: Version,
+    pub driver_version: u32,
+    pub graphics: bool,
+    // Whether R8_UINT images, used for texture atlases, can be sampled. None if it couldn't be checked.
+    pub sampled_r8_uint: Option<bool>,
+    pub max_image_size: u32
+}
+
+impl DeviceInfo {
+    pub fn new(physical: PhysicalDevice) -> Self {
+        DeviceInfo {
+            index: physical.index(),
+            name: physical.name(),
+            ty: physical.ty(),
+            api_version: physical.api_version(),
+            driver_version: physical.driver_version(),
+            graphics: has_graphics_queue(&physical),
+            sampled_r8_uint: supports_sampled_r8_uint(physical).ok(),
+            max_image_size: physical.limits().max_image_dimension_2d()
+        }
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        writeln!(f, "{}: {} ({})", self.index, self.name, device_type_name(self.ty))?;
+        writeln!(f, "    Vulkan {}, driver version {:#x}", self.api_version, self.driver_version)?;
+        writeln!(f, "    Graphics queue: {}", yes_no(self.graphics))?;
+        writeln!(f, "    Sampled R8_UINT images: {}", self.sampled_r8_uint.map(yes_no).unwrap_or("unknown"))?;
+        write!(f, "    Largest 2D image: {}", self.max_image_size)
+    }
+}
+
+fn has_graphics_queue(physical: &PhysicalDevice) -> bool {
+    physical.queue_families().any(|q| q.supports_graphics())
+}
+
+// Check whether a device can sample R8_UINT images, by trying to make one.
+pub fn supports_sampled_r8_uint(physical: PhysicalDevice) -> Result<bool, TileError> {
+    let queue_family = physical.queue_families().next().ok_or(TileError::NoDevice)?;
+    let (device, _) = Device::new(physical, &required_features(), &DeviceExtensions::none(), [(queue_family, 0.5)].iter().cloned())?;
+    let usage = ImageUsage {
+        transfer_destination: true,
+        sampled: true,
+        .. ImageUsage::none()
+    };
+    let image = ImmutableImage::uninitialized(
+        device,
+        Dimensions::Dim2d { width: 1, height: 1 },
+        R8Uint,
+        MipmapsCount::One,
+        usage,
+        ImageLayout::ShaderReadOnlyOptimal,
+        Some(queue_family)
+    );
+    match image {
+        Ok(_) => Ok(true),
+        Err(ImageCreationError::FormatNotSupported) |
+        Err(ImageCreationError::UnsupportedUsage) => Ok(false),
+        Err(e) => Err(e.into())
+    }
+}
@@ -0,0 +1,228 @@
+// Errors from setting up Vulkan, rendering with it, and addressing tiles and textures.
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilderContextError,
+        BeginRenderPassError,
+        BuildError,
+        CommandBufferExecError,
+        DrawError
+    },
+    descriptor::descriptor_set::{
+        PersistentDescriptorSetBuildError,
+        PersistentDescriptorSetError
+    },
+    device::DeviceCreationError,
+    format::Format,
+    framebuffer::{
+        FramebufferCreationError,
+        RenderPassCreationError
+    },
+    image::ImageCreationError,
+    instance::InstanceCreationError,
+    memory::DeviceMemoryAllocError,
+    pipeline::GraphicsPipelineCreationError,
+    sampler::SamplerCreationError,
+    swapchain::{
+        AcquireError,
+        CapabilitiesError,
+        PresentMode,
+        SwapchainCreationError
+    },
+    sync::FlushError,
+    OomError
+};
+
+use super::{
+    palettefile::PaletteError,
+    project::ProjectError,
+    record::RecordError,
+    tiled::TiledError
+};
+
+use std::{
+    fmt,
+    io
+};
+
+#[derive(Debug)]
+pub enum TileError {
+    Instance(InstanceCreationError),
+    // No device has a queue family that can draw.
+    NoDevice,
+    // The requested device doesn't exist or can't draw.
+    DeviceNotFound(String),
+    Device(DeviceCreationError),
+    Window(String),
+    Surface(CapabilitiesError),
+    // A format the renderer needs can't be used, e.g. for sampled images.
+    UnsupportedFormat(Format),
+    UnsupportedPresentMode(PresentMode),
+    Swapchain(SwapchainCreationError),
+    // A tile is outside the grid.
+    TileOutOfRange{ x: usize, y: usize, width: usize, height: usize },
+    // A texture is outside the atlas.
+    TextureOutOfRange{ tex_x: usize, tex_y: usize, atlas_size: usize },
+    // A texel is outside its texture.
+    TexelOutOfRange{ x: usize, y: usize, tex_size: usize },
+    // A palette can't be drawn with, e.g. past the palettes the shader holds.
+    PaletteOutOfRange{ palette: usize, palettes: usize },
+    // A colour is outside its palette.
+    ColourOutOfRange{ index: usize, colours: usize },
+    // Memory couldn't be allocated for a buffer or image.
+    Allocation(DeviceMemoryAllocError),
+    // An image couldn't be made or uploaded.
+    Upload(ImageCreationError),
+    // The render pass, framebuffers, shaders or pipeline couldn't be made.
+    Pipeline(String),
+    // A frame couldn't be acquired, recorded, submitted or presented.
+    Frame(String),
+    // A Tiled map couldn't be loaded.
+    Map(TiledError),
+    // A palette file couldn't be loaded.
+    PaletteFile(PaletteError),
+    // A command log couldn't be written or played back.
+    CommandLog(RecordError),
+    // A saved project couldn't be loaded.
+    Project(ProjectError),
+    // Another file couldn't be read or written, e.g. the statistics log.
+    Io(io::Error)
+}
+
+impl fmt::Display for TileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TileError::Instance(e)                  => write!(f, "Couldn't create Vulkan instance: {}", e),
+            TileError::NoDevice                     => write!(f, "No device with a graphics queue is available"),
+            TileError::DeviceNotFound(e)            => write!(f, "No usable {}", e),
+            TileError::Device(e)                    => write!(f, "Couldn't create device: {}", e),
+            TileError::Window(e)                    => write!(f, "Couldn't create window: {}", e),
+            TileError::Surface(e)                   => write!(f, "Couldn't get surface capabilities: {}", e),
+            TileError::UnsupportedFormat(format)    => write!(f, "Format {:?} isn't supported", format),
+            TileError::UnsupportedPresentMode(mode) => write!(f, "Present mode {:?} isn't supported", mode),
+            TileError::Swapchain(e)                 => write!(f, "Couldn't create swapchain: {}", e),
+            TileError::TileOutOfRange{ x, y, width, height } => write!(f, "Tile ({}, {}) is outside the {}x{} grid", x, y, width, height),
+            TileError::TextureOutOfRange{ tex_x, tex_y, atlas_size } => write!(f, "Texture ({}, {}) is outside the {}x{} atlas", tex_x, tex_y, atlas_size, atlas_size),
+            TileError::TexelOutOfRange{ x, y, tex_size } => write!(f, "Texel ({}, {}) is outside the {}x{} texture", x, y, tex_size, tex_size),
+            TileError::PaletteOutOfRange{ palette, palettes } => write!(f, "Palette {} is outside the {} palettes available", palette, palettes),
+            TileError::ColourOutOfRange{ index, colours } => write!(f, "Colour {} is outside the palette of {} colours", index, colours),
+            TileError::Allocation(e)                => write!(f, "Couldn't allocate memory: {}", e),
+            TileError::Upload(e)                    => write!(f, "Couldn't upload image: {}", e),
+            TileError::Pipeline(e)                  => write!(f, "Couldn't create pipeline: {}", e),
+            TileError::Frame(e)                     => write!(f, "Couldn't draw frame: {}", e),
+            TileError::Map(e)                       => write!(f, "Couldn't load map: {}", e),
+            TileError::PaletteFile(e)               => write!(f, "Couldn't load palette file: {}", e),
+            TileError::CommandLog(e)                => write!(f, "Command log error: {}", e),
+            TileError::Project(e)                   => write!(f, "Couldn't load project: {}", e),
+            TileError::Io(e)                        => write!(f, "IO error: {}", e)
+        }
+    }
+}
+
+impl std::error::Error for TileError {}
+
+impl From<InstanceCreationError> for TileError {
+    fn from(e: InstanceCreationError) -> Self {
+        TileError::Instance(e)
+    }
+}
+
+impl From<DeviceCreationError> for TileError {
+    fn from(e: DeviceCreationError) -> Self {
+        TileError::Device(e)
+    }
+}
+
+impl From<CapabilitiesError> for TileError {
+    fn from(e: CapabilitiesError) -> Self {
+        TileError::Surface(e)
+    }
+}
+
+impl From<SwapchainCreationError> for TileError {
+    fn from(e: SwapchainCreationError) -> Self {
+        TileError::Swapchain(e)
+    }
+}
+
+impl From<DeviceMemoryAllocError> for TileError {
+    fn from(e: DeviceMemoryAllocError) -> Self {
+        TileError::Allocation(e)
+    }
+}
+
+impl From<OomError> for TileError {
+    fn from(e: OomError) -> Self {
+        TileError::Allocation(e.into())
+    }
+}
+
+impl From<ImageCreationError> for TileError {
+    fn from(e: ImageCreationError) -> Self {
+        match e {
+            ImageCreationError::AllocError(e) => TileError::Allocation(e),
+            e => TileError::Upload(e)
+        }
+    }
+}
+
+impl From<TiledError> for TileError {
+    fn from(e: TiledError) -> Self {
+        TileError::Map(e)
+    }
+}
+
+impl From<PaletteError> for TileError {
+    fn from(e: PaletteError) -> Self {
+        TileError::PaletteFile(e)
+    }
+}
+
+impl From<RecordError> for TileError {
+    fn from(e: RecordError) -> Self {
+        TileError::CommandLog(e)
+    }
+}
+
+impl From<ProjectError> for TileError {
+    fn from(e: ProjectError) -> Self {
+        TileError::Project(e)
+    }
+}
+
+impl From<io::Error> for TileError {
+    fn from(e: io::Error) -> Self {
+        TileError::Io(e)
+    }
+}
+
+// Errors from making the pipeline and from drawing frames are only reported, so they are kept as messages.
+macro_rules! from_message {
+    ($variant:ident: $($error:ty),*) => {
+        $(
+            impl From<$error> for TileError {
+                fn from(e: $error) -> Self {
+                    TileError::$variant(e.to_string())
+                }
+            }
+        )*
+    };
+}
+
+from_message!(Pipeline:
+    SamplerCreationError,
+    RenderPassCreationError,
+    FramebufferCreationError,
+    GraphicsPipelineCreationError
+);
+
+from_message!(Frame:
+    AcquireError,
+    PersistentDescriptorSetError,
+    PersistentDescriptorSetBuildError,
+    BeginRenderPassError,
+    DrawError,
+    AutoCommandBufferBuilderContextError,
+    BuildError,
+    CommandBufferExecError,
+    FlushError
+);
@@ -0,0 +1,195 @@
+// Export the atlas, palettes and tilemaps to PNG images for debugging.
+use super::{
+    imagegen::TextureAtlas,
+    palette::{
+        self,
+        Palette
+    },
+    tilemap::TileMap,
+    vertexgrid::AtlasSlot
+};
+
+use std::{
+    fmt,
+    fs::File,
+    io::{
+        self,
+        BufWriter
+    },
+    path::Path
+};
+
+// Colour for texels that can't be drawn.
+const MISSING: [u8; 4] = [255, 0, 255, 255];
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(io::Error),
+    Encode(png::EncodingError)
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::Io(e)      => write!(f, "IO error: {}", e),
+            ExportError::Encode(e)  => write!(f, "PNG encode error: {}", e)
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for ExportError {
+    fn from(e: png::EncodingError) -> Self {
+        ExportError::Encode(e)
+    }
+}
+
+// Write the atlas as an indexed PNG.
+// If no palette is provided, a greyscale ramp covering the texel values in the atlas is used.
+pub fn export_atlas(path: &Path, atlas: &TextureAtlas, palette: Option<&Palette>) -> Result<(), ExportError> {
+    let width = (atlas.atlas_size() * atlas.tex_size()) as u32;
+
+    let ramp;
+    let colours = match palette {
+        Some(p) => p,
+        None => {
+            ramp = greyscale_ramp(atlas);
+            &ramp
+        }
+    };
+
+    // Texels that index past the end of the palette are clamped to the last entry.
+    let max_index = colours.len().clamp(1, 256) - 1;
+    let texels = atlas.textures.iter().map(|t| (*t as usize).min(max_index) as u8).collect::<Vec<_>>();
+
+    let mut plte = Vec::new();
+    let mut trns = Vec::new();
+    for c in colours.iter().take(256) {
+        let rgba = palette::to_rgba8(*c);
+        plte.extend_from_slice(&rgba[0..3]);
+        trns.push(rgba[3]);
+    }
+    if plte.is_empty() {
+        plte.extend_from_slice(&[0, 0, 0]);
+        trns.push(255);
+    }
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, width);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_chunk(*b"PLTE", &plte)?;
+    if trns.iter().any(|a| *a != 255) {
+        writer.write_chunk(*b"tRNS", &trns)?;
+    }
+    writer.write_image_data(&texels)?;
+    Ok(())
+}
+
+// Write every palette as a strip of swatches, one row per palette.
+pub fn export_palettes(path: &Path, palettes: &[Palette], swatch_size: usize) -> Result<(), ExportError> {
+    let columns = palettes.iter().map(|p| p.len()).max().unwrap_or(0).max(1);
+    let rows = palettes.len().max(1);
+    let width = columns * swatch_size;
+    let height = rows * swatch_size;
+
+    let mut data = vec![0; width * height * 4];
+    for (row, p) in palettes.iter().enumerate() {
+        for (column, colour) in p.iter().enumerate() {
+            let rgba = palette::to_rgba8(*colour);
+            for y in (row * swatch_size)..((row + 1) * swatch_size) {
+                for x in (column * swatch_size)..((column + 1) * swatch_size) {
+                    let i = (y * width + x) * 4;
+                    data[i..(i + 4)].copy_from_slice(&rgba);
+                }
+            }
+        }
+    }
+
+    write_rgba(path, width, height, &data)
+}
+
+// Write a tilemap, with each tile's texture flipped and drawn through its palette.
+pub fn export_tilemap(path: &Path, tilemap: &TileMap, atlas: &TextureAtlas, palettes: &[Palette]) -> Result<(), ExportError> {
+    let tex_size = atlas.tex_size();
+    let data = render_tilemap(tilemap, atlas, palettes);
+    write_rgba(path, tilemap.width * tex_size, tilemap.height * tex_size, &data)
+}
+
+// Draw a tilemap as RGBA8 pixels, one texel per pixel, the way export_tilemap writes it.
+pub fn render_tilemap(tilemap: &TileMap, atlas: &TextureAtlas, palettes: &[Palette]) -> Vec<u8> {
+    let tex_size = atlas.tex_size();
+    let width = tilemap.width * tex_size;
+    let height = tilemap.height * tex_size;
+
+    let mut data = vec![0; width * height * 4];
+    for tile_y in 0..tilemap.height {
+        for tile_x in 0..tilemap.width {
+            let tile = tilemap.tile(tile_x, tile_y);
+            let palette = palettes.get(tile.palette as usize);
+            let in_atlas = AtlasSlot::new(tile.tex_x, tile.tex_y).check(atlas.atlas_size()).is_ok();
+            for y in 0..tex_size {
+                for x in 0..tex_size {
+                    // Textures outside the atlas, and missing palettes or colours, are shown as magenta.
+                    let colour = if in_atlas {
+                        let (tex_x, tex_y) = tile.flip.apply_texel(x, y, tex_size);
+                        let texel = atlas.texel(tile.tex_x, tile.tex_y, tex_x, tex_y) as usize;
+                        palette.and_then(|p| p.get(texel))
+                    } else {
+                        None
+                    };
+                    let rgba = colour.map(|c| palette::to_rgba8(*c)).unwrap_or(MISSING);
+
+                    let i = ((tile_y * tex_size + y) * width + (tile_x * tex_size + x)) * 4;
+                    data[i..(i + 4)].copy_from_slice(&rgba);
+                }
+            }
+        }
+    }
+
+    data
+}
+
+fn write_rgba(path: &Path, width: usize, height: usize, data: &[u8]) -> Result<(), ExportError> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    Ok(())
+}
+
+// Make a greyscale ramp with enough entries for every texel value in the atlas, rounded up to a power of two.
+fn greyscale_ramp(atlas: &TextureAtlas) -> Palette {
+    let max_texel = atlas.textures.iter().cloned().max().unwrap_or(0) as usize;
+    let entries = (max_texel + 1).next_power_of_two().max(2);
+    (0..entries).map(|i| {
+        let v = ((i * 255) / (entries - 1)) as u8;
+        palette::from_rgba8([v, v, v, 255])
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::MapTile;
+
+    use cgmath::Vector4;
+
+    #[test]
+    fn missing_textures_and_palettes_are_magenta() {
+        let atlas = TextureAtlas::new(1, 1);
+        let palettes = vec![vec![Vector4::new(0.0, 0.0, 0.0, 1.0)]];
+        let mut tilemap = TileMap::new(3, 1);
+        tilemap.set_tile(1, 0, MapTile{ tex_x: 1, ..MapTile::default() });
+        tilemap.set_tile(2, 0, MapTile{ palette: 1, ..MapTile::default() });
+        assert_eq!(render_tilemap(&tilemap, &atlas, &palettes), [[0, 0, 0, 255], MISSING, MISSING].concat());
+    }
+}
@@ -0,0 +1,441 @@
+// Load bitmap fonts (BDF, PSF1 and PSF2) into a texture atlas.
+// Each glyph is rasterised into its own atlas slot as a 1bpp texture: 0 for background, 1 for foreground.
+use super::imagegen::TextureAtlas;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs,
+    io,
+    path::Path
+};
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TAB: u8 = 0x02;
+const PSF1_MODE_SEQ: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xFFFF;
+const PSF1_START_SEQ: u16 = 0xFFFE;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xFF;
+const PSF2_START_SEQ: u8 = 0xFE;
+
+// Largest glyph width or height. Each glyph gets a square texture, so larger glyphs would make huge atlases.
+const MAX_GLYPH_SIZE: usize = 256;
+
+#[derive(Debug)]
+pub enum FontError {
+    Io(io::Error),
+    Format(String)
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::Io(e)        => write!(f, "IO error: {}", e),
+            FontError::Format(s)    => write!(f, "Invalid font: {}", s)
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+impl From<io::Error> for FontError {
+    fn from(e: io::Error) -> Self {
+        FontError::Io(e)
+    }
+}
+
+fn format_err<T>(msg: &str) -> Result<T, FontError> {
+    Err(FontError::Format(msg.to_string()))
+}
+
+// A font rasterised into a texture atlas.
+pub struct Font {
+    pub atlas: TextureAtlas,
+    // Map of codepoint to atlas slot (x, y).
+    pub glyphs: HashMap<char, (usize, usize)>,
+    pub glyph_width: usize,
+    pub glyph_height: usize
+}
+
+// A single glyph before rasterisation.
+// Rows are stored top to bottom, each row as a bitmap with the MSB of the first byte as the leftmost pixel.
+struct RawGlyph {
+    codepoints: Vec<char>,
+    width: usize,
+    height: usize,
+    x_offset: usize,
+    y_offset: usize,
+    bytes_per_row: usize,
+    bitmap: Vec<u8>
+}
+
+impl Font {
+    // Load a font file. The format is detected from the file contents.
+    pub fn load(path: &Path) -> Result<Self, FontError> {
+        let data = fs::read(path)?;
+        Self::from_bytes(&data)
+    }
+
+    // Load a font from memory. The format is detected from the data.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, FontError> {
+        if data.starts_with(&PSF2_MAGIC) || data.starts_with(&PSF1_MAGIC) {
+            Self::from_psf(data)
+        } else if data.starts_with(b"STARTFONT") {
+            Self::from_bdf(data)
+        } else {
+            format_err("unrecognised font format")
+        }
+    }
+
+    // Load a PSF1 or PSF2 console font.
+    pub fn from_psf(data: &[u8]) -> Result<Self, FontError> {
+        if data.starts_with(&PSF2_MAGIC) {
+            let (glyphs, width, height) = parse_psf2(data)?;
+            Self::rasterise(glyphs, width, height)
+        } else if data.starts_with(&PSF1_MAGIC) {
+            let (glyphs, width, height) = parse_psf1(data)?;
+            Self::rasterise(glyphs, width, height)
+        } else {
+            format_err("missing PSF magic number")
+        }
+    }
+
+    // Load a BDF font.
+    pub fn from_bdf(data: &[u8]) -> Result<Self, FontError> {
+        let text = std::str::from_utf8(data).or_else(|_| format_err("BDF is not valid text"))?;
+        let (glyphs, width, height) = parse_bdf(text)?;
+        Self::rasterise(glyphs, width, height)
+    }
+
+    // Place glyphs into the atlas. The atlas is sized to fit all glyphs,
+    // and each texture is large enough to fit the font's bounding box.
+    fn rasterise(glyphs: Vec<RawGlyph>, glyph_width: usize, glyph_height: usize) -> Result<Self, FontError> {
+        let tex_size = std::cmp::max(glyph_width, glyph_height);
+        if tex_size > MAX_GLYPH_SIZE {
+            return format_err(&format!("glyphs are larger than {} pixels", MAX_GLYPH_SIZE));
+        }
+        let mut atlas_size = 1;
+        while atlas_size * atlas_size < glyphs.len() {
+            atlas_size += 1;
+        }
+        let side = atlas_size * tex_size;
+        if side.checked_mul(side).is_none() {
+            return format_err("too many glyphs");
+        }
+
+        let mut font = Font {
+            atlas: TextureAtlas::new(atlas_size, tex_size),
+            glyphs: HashMap::new(),
+            glyph_width: glyph_width,
+            glyph_height: glyph_height
+        };
+
+        for (slot, glyph) in glyphs.iter().enumerate() {
+            let (tex_x, tex_y) = (slot % atlas_size, slot / atlas_size);
+
+            for row in 0..glyph.height {
+                let y = glyph.y_offset + row;
+                for col in 0..glyph.width {
+                    let x = glyph.x_offset + col;
+                    let byte = glyph.bitmap[row * glyph.bytes_per_row + (col / 8)];
+                    let bit = (byte >> (7 - (col % 8))) & 1;
+                    if (bit != 0) && (x < tex_size) && (y < tex_size) {
+                        font.atlas.set_texel(tex_x, tex_y, x, y, 1);
+                    }
+                }
+            }
+
+            for c in glyph.codepoints.iter() {
+                font.glyphs.entry(*c).or_insert((tex_x, tex_y));
+            }
+        }
+
+        Ok(font)
+    }
+}
+
+// Read a little-endian u32 from a header.
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, FontError> {
+    match data.get(offset..(offset + 4)) {
+        Some(b) => Ok(u32::from(b[0]) | (u32::from(b[1]) << 8) | (u32::from(b[2]) << 16) | (u32::from(b[3]) << 24)),
+        None => format_err("header is truncated")
+    }
+}
+
+// Returns the glyphs, glyph width and glyph height.
+fn parse_psf1(data: &[u8]) -> Result<(Vec<RawGlyph>, usize, usize), FontError> {
+    if data.len() < 4 {
+        return format_err("header is truncated");
+    }
+    let mode = data[2];
+    let height = data[3] as usize;
+    let count = if (mode & PSF1_MODE_512) != 0 { 512 } else { 256 };
+
+    let bitmaps_start = 4;
+    let bitmaps_end = bitmaps_start + count * height;
+    if data.len() < bitmaps_end {
+        return format_err("glyph data is truncated");
+    }
+
+    let mut glyphs = (0..count).map(|i| {
+        let start = bitmaps_start + i * height;
+        RawGlyph {
+            codepoints: Vec::new(),
+            width: 8,
+            height: height,
+            x_offset: 0,
+            y_offset: 0,
+            bytes_per_row: 1,
+            bitmap: data[start..(start + height)].to_vec()
+        }
+    }).collect::<Vec<_>>();
+
+    if (mode & (PSF1_MODE_HAS_TAB | PSF1_MODE_SEQ)) != 0 {
+        // Unicode table: for each glyph, a list of u16 codepoints, then optional sequences, then a separator.
+        let mut entries = data[bitmaps_end..].chunks_exact(2).map(|b| u16::from(b[0]) | (u16::from(b[1]) << 8));
+        for glyph in glyphs.iter_mut() {
+            let mut in_sequence = false;
+            loop {
+                match entries.next() {
+                    Some(PSF1_SEPARATOR) | None => break,
+                    Some(PSF1_START_SEQ) => in_sequence = true,
+                    Some(c) => if !in_sequence {
+                        if let Some(c) = std::char::from_u32(u32::from(c)) {
+                            glyph.codepoints.push(c);
+                        }
+                    }
+                }
+            }
+        }
+    } else {
+        for (i, glyph) in glyphs.iter_mut().enumerate() {
+            glyph.codepoints.extend(std::char::from_u32(i as u32));
+        }
+    }
+
+    Ok((glyphs, 8, height))
+}
+
+// Returns the glyphs, glyph width and glyph height.
+fn parse_psf2(data: &[u8]) -> Result<(Vec<RawGlyph>, usize, usize), FontError> {
+    let header_size = read_u32(data, 8)? as usize;
+    let flags = read_u32(data, 12)?;
+    let count = read_u32(data, 16)? as usize;
+    let char_size = read_u32(data, 20)? as usize;
+    let height = read_u32(data, 24)? as usize;
+    let width = read_u32(data, 28)? as usize;
+
+    let bytes_per_row = width.div_ceil(8);
+    let bitmap_size = bytes_per_row.checked_mul(height).ok_or_else(|| FontError::Format("glyph size is too large".to_string()))?;
+    if (width == 0) || (height == 0) || (char_size < bitmap_size) {
+        return format_err("inconsistent glyph size");
+    }
+    if (width > MAX_GLYPH_SIZE) || (height > MAX_GLYPH_SIZE) {
+        return format_err(&format!("glyphs are larger than {} pixels", MAX_GLYPH_SIZE));
+    }
+
+    let bitmaps_end = count.checked_mul(char_size)
+        .and_then(|s| s.checked_add(header_size))
+        .filter(|end| *end <= data.len())
+        .ok_or_else(|| FontError::Format("glyph data is truncated".to_string()))?;
+
+    let mut glyphs = (0..count).map(|i| {
+        let start = header_size + i * char_size;
+        RawGlyph {
+            codepoints: Vec::new(),
+            width: width,
+            height: height,
+            x_offset: 0,
+            y_offset: 0,
+            bytes_per_row: bytes_per_row,
+            bitmap: data[start..(start + bitmap_size)].to_vec()
+        }
+    }).collect::<Vec<_>>();
+
+    if (flags & PSF2_HAS_UNICODE_TABLE) != 0 {
+        // Unicode table: for each glyph, UTF-8 codepoints, then optional sequences, then a separator.
+        let mut table = data[bitmaps_end..].split(|b| *b == PSF2_SEPARATOR);
+        for glyph in glyphs.iter_mut() {
+            let entry = table.next().unwrap_or(&[]);
+            let singles = entry.split(|b| *b == PSF2_START_SEQ).next().unwrap_or(&[]);
+            decode_utf8(singles, &mut glyph.codepoints);
+        }
+    } else {
+        for (i, glyph) in glyphs.iter_mut().enumerate() {
+            glyph.codepoints.extend(std::char::from_u32(i as u32));
+        }
+    }
+
+    Ok((glyphs, width, height))
+}
+
+// Decode UTF-8 characters, skipping invalid sequences.
+fn decode_utf8(mut bytes: &[u8], chars: &mut Vec<char>) {
+    while !bytes.is_empty() {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => {
+                chars.extend(s.chars());
+                break;
+            },
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                chars.extend(std::str::from_utf8(valid).into_iter().flat_map(|s| s.chars()));
+                bytes = &rest[e.error_len().unwrap_or(rest.len())..];
+            }
+        }
+    }
+}
+
+// Parse whitespace-separated integers after a BDF keyword.
+fn bdf_ints(line: &str) -> Result<Vec<i32>, FontError> {
+    line.split_whitespace()
+        .skip(1)
+        .map(|s| s.parse::<i32>().or_else(|_| format_err(&format!("bad number in line '{}'", line))))
+        .collect()
+}
+
+// Returns the glyphs, glyph width and glyph height.
+// Glyphs are positioned within the font bounding box using their own bounding boxes, so they share a baseline.
+fn parse_bdf(text: &str) -> Result<(Vec<RawGlyph>, usize, usize), FontError> {
+    let mut font_bbx: Option<(i32, i32, i32, i32)> = None;
+    let mut glyphs = Vec::new();
+
+    let mut encoding = None;
+    let mut bbx = None;
+    let mut bitmap: Option<Vec<u8>> = None;
+
+    for line in text.lines().map(|l| l.trim()) {
+        let keyword = line.split_whitespace().next().unwrap_or("");
+
+        if let Some(rows) = bitmap.as_mut() {
+            if keyword == "ENDCHAR" {
+                let (_, fh, fx, fy) = font_bbx.ok_or_else(|| FontError::Format("missing FONTBOUNDINGBOX".to_string()))?;
+                let (w, h, x, y): (i32, i32, i32, i32) = bbx.ok_or_else(|| FontError::Format("glyph missing BBX".to_string()))?;
+                let bytes_per_row = (w.max(0) as usize).div_ceil(8);
+                if rows.len() < bytes_per_row * (h.max(0) as usize) {
+                    return format_err("glyph bitmap is truncated");
+                }
+
+                // Glyphs with negative encodings have no codepoint, but still take a slot to keep ordering stable.
+                let codepoints = encoding.and_then(|e: i32| std::char::from_u32(e as u32)).into_iter().collect();
+                let x_offset = (x - fx).max(0) as usize;
+                let y_offset = ((fh + fy) - (h + y)).max(0) as usize;
+
+                glyphs.push(RawGlyph {
+                    codepoints: codepoints,
+                    width: w.max(0) as usize,
+                    height: h.max(0) as usize,
+                    x_offset: x_offset,
+                    y_offset: y_offset,
+                    bytes_per_row: bytes_per_row,
+                    bitmap: bitmap.take().unwrap()
+                });
+                encoding = None;
+                bbx = None;
+            } else {
+                // Each row is left-aligned hex, padded with zeros or cut to the glyph's bytes per row.
+                let (w, _, _, _) = bbx.ok_or_else(|| FontError::Format("glyph missing BBX".to_string()))?;
+                let bytes_per_row = (w.max(0) as usize).div_ceil(8);
+                let digits = line.chars()
+                    .map(|c| c.to_digit(16).map(|d| d as u8))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| FontError::Format(format!("bad bitmap row '{}'", line)))?;
+                rows.extend((0..bytes_per_row).map(|i| {
+                    let digit = |n: usize| digits.get(n).cloned().unwrap_or(0);
+                    (digit(i * 2) << 4) | digit(i * 2 + 1)
+                }));
+            }
+            continue;
+        }
+
+        match keyword {
+            "FONTBOUNDINGBOX" => match bdf_ints(line)?.as_slice() {
+                [w, h, x, y] if (*w > 0) && (*h > 0) => font_bbx = Some((*w, *h, *x, *y)),
+                _ => return format_err("bad FONTBOUNDINGBOX")
+            },
+            "STARTCHAR" => {
+                encoding = None;
+                bbx = None;
+            },
+            "ENCODING" => encoding = bdf_ints(line)?.first().cloned().filter(|e| *e >= 0),
+            "BBX" => match bdf_ints(line)?.as_slice() {
+                [w, h, x, y] if (*w <= MAX_GLYPH_SIZE as i32) && (*h <= MAX_GLYPH_SIZE as i32) => bbx = Some((*w, *h, *x, *y)),
+                _ => return format_err("bad BBX")
+            },
+            "BITMAP" => bitmap = Some(Vec::new()),
+            _ => {}
+        }
+    }
+
+    let (width, height, _, _) = font_bbx.ok_or_else(|| FontError::Format("missing FONTBOUNDINGBOX".to_string()))?;
+    if glyphs.is_empty() {
+        return format_err("font has no glyphs");
+    }
+
+    Ok((glyphs, width as usize, height as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bdf_rows_are_left_aligned_and_padded() {
+        // A 12 pixel wide glyph with rows written with odd, short and long runs of hex digits.
+        let bdf = "STARTFONT 2.1\nFONTBOUNDINGBOX 12 3 0 0\nSTARTCHAR A\nENCODING 65\nBBX 12 3 0 0\nBITMAP\nFFF\n8\n8010FF\nENDCHAR\nENDFONT\n";
+        let font = Font::from_bdf(bdf.as_bytes()).unwrap();
+        let (tex_x, tex_y) = font.glyphs[&'A'];
+        let row = |y: usize| (0..12).map(|x| font.atlas.texel(tex_x, tex_y, x, y)).collect::<Vec<_>>();
+        assert_eq!(row(0), vec![1; 12]);
+        assert_eq!(row(1), vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(row(2), vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+        let bad = bdf.replace("FFF", "FFG");
+        assert!(Font::from_bdf(bad.as_bytes()).is_err());
+    }
+
+    // A PSF2 font with a Unicode table.
+    fn psf2(width: u32, height: u32, glyphs: &[&[u8]], table: &[u8]) -> Vec<u8> {
+        let char_size = (width as usize).div_ceil(8) * height as usize;
+        let mut data = PSF2_MAGIC.to_vec();
+        for value in [0, 32, PSF2_HAS_UNICODE_TABLE, glyphs.len() as u32, char_size as u32, height, width].iter() {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for glyph in glyphs.iter() {
+            let mut bitmap = glyph.to_vec();
+            bitmap.resize(char_size, 0);
+            data.extend_from_slice(&bitmap);
+        }
+        data.extend_from_slice(table);
+        data
+    }
+
+    #[test]
+    fn psf2_unicode_table_keeps_the_replacement_character() {
+        // Glyph 0 is mapped to U+FFFD after an invalid byte, and glyph 1 to 'A'.
+        let table = [&[0xC0][..], "\u{FFFD}".as_bytes(), &[PSF2_SEPARATOR], b"A", &[PSF2_SEPARATOR]].concat();
+        let font = Font::from_psf(&psf2(8, 2, &[&[0xFF], &[0x81]], &table)).unwrap();
+        assert_eq!(font.glyphs.len(), 2);
+        assert_eq!(font.glyphs.get(&'\u{FFFD}'), Some(&(0, 0)));
+        assert_eq!(font.glyphs.get(&'A'), Some(&(1, 0)));
+    }
+
+    #[test]
+    fn wide_psf2_glyphs_are_errors() {
+        let data = psf2(100_000, 1, &[&[0xFF]], &[PSF2_SEPARATOR]);
+        assert!(matches!(Font::from_psf(&data), Err(FontError::Format(_))));
+    }
+
+    #[test]
+    fn huge_psf2_glyphs_are_errors() {
+        let mut data = PSF2_MAGIC.to_vec();
+        for value in [0, 32, 0, 1, u32::MAX, u32::MAX, u32::MAX].iter() {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        assert!(matches!(Font::from_psf(&data), Err(FontError::Format(_))));
+    }
+}
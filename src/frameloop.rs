@@ -0,0 +1,150 @@
+// Pacing for the main loop, and statistics on how long frames take.
+use std::{
+    fmt,
+    thread,
+    time::{
+        Duration,
+        Instant
+    }
+};
+
+// Refresh rates of consoles, in Hz, for the fixed rate loop.
+pub const TICK_RATES: [(&str, f64); 2] = [
+    ("gb", 59.73),
+    ("nes", 60.0988)
+];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LoopMode {
+    // Draw only when something has changed, and sleep until the next event otherwise. Suits the editors.
+    Redraw,
+    // Draw on every tick of a fixed rate clock, polling for events between ticks, like an emulator.
+    Fixed
+}
+
+pub fn loop_mode_name(mode: LoopMode) -> &'static str {
+    match mode {
+        LoopMode::Redraw    => "redraw",
+        LoopMode::Fixed     => "fixed"
+    }
+}
+
+pub fn loop_mode_from_name(name: &str) -> Option<LoopMode> {
+    [LoopMode::Redraw, LoopMode::Fixed].iter()
+        .find(|m| loop_mode_name(**m) == name)
+        .cloned()
+}
+
+// Parse a tick rate in Hz, or the name of a console.
+pub fn tick_rate_from_name(name: &str) -> Option<f64> {
+    TICK_RATES.iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, rate)| *rate)
+        .or_else(|| name.parse().ok())
+}
+
+// A clock ticking at a fixed rate. Ticks are timed from the start rather than from each other, so the rate doesn't drift.
+pub struct TickClock {
+    start: Instant,
+    // Seconds per tick.
+    period: f64,
+    tick: u64
+}
+
+impl TickClock {
+    // Rate: ticks per second.
+    pub fn new(rate: f64) -> Self {
+        TickClock {
+            start: Instant::now(),
+            period: 1.0 / rate,
+            tick: 0
+        }
+    }
+
+    // Sleep until the next tick. If it has already passed, ticks that were missed entirely are skipped
+    // rather than run back to back. Returns the number of ticks skipped.
+    pub fn wait(&mut self) -> u64 {
+        self.tick += 1;
+        let deadline = self.start + Duration::from_secs_f64(self.tick as f64 * self.period);
+        let now = Instant::now();
+        if now < deadline {
+            thread::sleep(deadline - now);
+            return 0;
+        }
+
+        let missed = ((now - deadline).as_secs_f64() / self.period) as u64;
+        self.tick += missed;
+        missed
+    }
+}
+
+// Timing of frames drawn since the last report.
+pub struct FrameStats {
+    start: Instant,
+    last_frame: Option<Instant>,
+    frames: u32,
+    // Time from one frame to the next.
+    min_interval: Option<Duration>,
+    max_interval: Duration,
+    // CPU time spent recording and submitting frames.
+    total_draw: Duration,
+    max_draw: Duration,
+    missed_ticks: u64
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        FrameStats {
+            start: Instant::now(),
+            last_frame: None,
+            frames: 0,
+            min_interval: None,
+            max_interval: Duration::from_secs(0),
+            total_draw: Duration::from_secs(0),
+            max_draw: Duration::from_secs(0),
+            missed_ticks: 0
+        }
+    }
+
+    // Count a frame that took some CPU time to draw.
+    pub fn frame(&mut self, draw: Duration) {
+        let now = Instant::now();
+        if let Some(last) = self.last_frame {
+            let interval = now - last;
+            self.min_interval = Some(self.min_interval.map_or(interval, |min| min.min(interval)));
+            self.max_interval = self.max_interval.max(interval);
+        }
+        self.last_frame = Some(now);
+        self.frames += 1;
+        self.total_draw += draw;
+        self.max_draw = self.max_draw.max(draw);
+    }
+
+    pub fn missed_ticks(&mut self, missed: u64) {
+        self.missed_ticks += missed;
+    }
+
+    // Time since the last report.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    // Start again for the next report. The last frame is kept, so the next interval is measured from it.
+    pub fn reset(&mut self) {
+        *self = FrameStats {
+            last_frame: self.last_frame,
+            .. FrameStats::new()
+        };
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let seconds = self.elapsed().as_secs_f64();
+        let fps = if seconds > 0.0 { f64::from(self.frames) / seconds } else { 0.0 };
+        let mean_draw = if self.frames > 0 { ms(self.total_draw) / f64::from(self.frames) } else { 0.0 };
+        write!(f, "{:.2} fps, frame interval {:.2}-{:.2} ms, draw {:.2} ms (max {:.2} ms), {} ticks missed",
+            fps, ms(self.min_interval.unwrap_or_default()), ms(self.max_interval), mean_draw, ms(self.max_draw), self.missed_ticks)
+    }
+}
@@ -0,0 +1,211 @@
+use vulkano::{
+    buffer::immutable::ImmutableBuffer,
+    command_buffer::{
+        AutoCommandBuffer,
+        CommandBufferExecFuture
+    },
+    descriptor::{
+        descriptor_set::{
+            DescriptorSet,
+            FixedSizeDescriptorSetsPool
+        },
+        pipeline_layout::PipelineLayoutAbstract
+    },
+    device::{
+        Device,
+        Queue
+    },
+    format::R8Uint,
+    image::immutable::ImmutableImage,
+    sampler::Sampler,
+    swapchain::{
+        PresentFuture,
+        Swapchain,
+        SwapchainAcquireFuture
+    },
+    sync::{
+        now,
+        FenceSignalFuture,
+        GpuFuture
+    }
+};
+
+use winit::Window;
+
+use tile_test::error::TileError;
+
+use std::sync::Arc;
+
+pub type FrameFuture = FenceSignalFuture<PresentFuture<CommandBufferExecFuture<Box<GpuFuture>, AutoCommandBuffer>, Window>>;
+
+type Set = Arc<DescriptorSet + Send + Sync>;
+
+// Sets keyed by the image or buffer they bind, and whether they were used in the frame being recorded.
+type SetCache<K> = Vec<(Arc<K>, Set, bool)>;
+
+// Descriptor sets used by one frame in flight. Sets are kept while their image or buffer is still drawn,
+// rather than being rebuilt every frame.
+pub struct FrameSets<L, P> {
+    image_pool: FixedSizeDescriptorSetsPool<L>,
+    palette_pool: FixedSizeDescriptorSetsPool<L>,
+    image_sets: SetCache<ImmutableImage<R8Uint>>,
+    palette_sets: SetCache<ImmutableBuffer<P>>
+}
+
+impl<L, P> FrameSets<L, P>
+    where L: PipelineLayoutAbstract + Clone + Send + Sync + 'static,
+          P: Send + Sync + 'static
+{
+    // Layout: the pipeline, with sampled images in set 0 and palettes in set 1.
+    fn new(layout: &L) -> Self {
+        FrameSets {
+            image_pool: FixedSizeDescriptorSetsPool::new(layout.clone(), 0),
+            palette_pool: FixedSizeDescriptorSetsPool::new(layout.clone(), 1),
+            image_sets: Vec::new(),
+            palette_sets: Vec::new()
+        }
+    }
+
+    // Get a set that samples an image, e.g. the texture atlas.
+    pub fn image_set(&mut self, image: &Arc<ImmutableImage<R8Uint>>, sampler: &Arc<Sampler>) -> Result<Set, TileError> {
+        let pool = &mut self.image_pool;
+        cached_set(&mut self.image_sets, image, || {
+            Ok(Arc::new(pool.next()
+                .add_sampled_image(image.clone(), sampler.clone())?
+                .build()?))
+        })
+    }
+
+    // Get a set for a palette uniform buffer.
+    pub fn palette_set(&mut self, buffer: &Arc<ImmutableBuffer<P>>) -> Result<Set, TileError> {
+        let pool = &mut self.palette_pool;
+        cached_set(&mut self.palette_sets, buffer, || {
+            Ok(Arc::new(pool.next()
+                .add_buffer(buffer.clone())?
+                .build()?))
+        })
+    }
+
+    // Drop sets that weren't used by the last frame, e.g. for a replaced atlas image.
+    fn start(&mut self) {
+        forget_unused(&mut self.image_sets);
+        forget_unused(&mut self.palette_sets);
+    }
+}
+
+// Find the set for a key, or make one.
+fn cached_set<K, F>(sets: &mut SetCache<K>, key: &Arc<K>, make: F) -> Result<Set, TileError>
+    where F: FnOnce() -> Result<Set, TileError>
+{
+    if let Some((_, set, used)) = sets.iter_mut().find(|(k, ..)| Arc::ptr_eq(k, key)) {
+        *used = true;
+        return Ok(set.clone());
+    }
+
+    let set = make()?;
+    sets.push((key.clone(), set.clone(), true));
+    Ok(set)
+}
+
+// Drop unused sets, and start tracking use again.
+fn forget_unused<K>(sets: &mut SetCache<K>) {
+    sets.retain(|(.., used)| *used);
+    for (.., used) in sets.iter_mut() {
+        *used = false;
+    }
+}
+
+struct Frame<L, P> {
+    // Signalled when the GPU has finished the last frame drawn in this slot.
+    future: Option<FrameFuture>,
+    sets: FrameSets<L, P>
+}
+
+// A ring of frames in flight. The CPU records the next frame while the GPU draws earlier ones,
+// waiting only when it comes back round to a slot whose frame hasn't finished.
+pub struct FrameRing<L, P> {
+    device: Arc<Device>,
+    frames: Vec<Frame<L, P>>,
+    // Slot of the frame being recorded.
+    current: usize,
+    // Uploads that the next frame submitted must wait for.
+    uploads: Option<Box<GpuFuture>>,
+    // Slot of the last frame that carried uploads.
+    // Images and buffers can't be used by another submission until their upload has finished.
+    upload_frame: Option<usize>
+}
+
+impl<L, P> FrameRing<L, P>
+    where L: PipelineLayoutAbstract + Clone + Send + Sync + 'static,
+          P: Send + Sync + 'static
+{
+    // Uploads: anything the first frame must wait for.
+    pub fn new<F: GpuFuture + 'static>(device: &Arc<Device>, layout: &L, frames_in_flight: usize, uploads: F) -> Self {
+        FrameRing {
+            device: device.clone(),
+            frames: (0..frames_in_flight.max(1)).map(|_| Frame {
+                future: None,
+                sets: FrameSets::new(layout)
+            }).collect(),
+            current: 0,
+            uploads: Some(Box::new(uploads)),
+            upload_frame: None
+        }
+    }
+
+    // Start recording a frame, waiting for its slot to be free. Returns the slot, for per-frame vertex buffers.
+    pub fn begin(&mut self) -> Result<usize, TileError> {
+        if let Some(slot) = self.upload_frame.take() {
+            if let Some(future) = &self.frames[slot].future {
+                future.wait(None)?;
+            }
+        }
+        for frame in self.frames.iter_mut() {
+            if let Some(future) = &mut frame.future {
+                future.cleanup_finished();
+            }
+        }
+
+        let frame = &mut self.frames[self.current];
+        if let Some(future) = frame.future.take() {
+            future.wait(None)?;
+        }
+        frame.sets.start();
+        Ok(self.current)
+    }
+
+    // Descriptor sets for the frame being recorded.
+    pub fn sets(&mut self) -> &mut FrameSets<L, P> {
+        &mut self.frames[self.current].sets
+    }
+
+    // Make the next frame submitted wait for an upload.
+    pub fn add_upload<F: GpuFuture + 'static>(&mut self, upload: F) {
+        let uploads = match self.uploads.take() {
+            Some(uploads) => Box::new(uploads.join(upload)) as Box<GpuFuture>,
+            None => Box::new(upload) as Box<GpuFuture>
+        };
+        self.uploads = Some(uploads);
+    }
+
+    // Submit the frame being recorded and present it, then move on to the next slot.
+    pub fn submit(&mut self, queue: &Arc<Queue>, swapchain: &Arc<Swapchain<Window>>, acquire_future: SwapchainAcquireFuture<Window>, image_num: usize, command_buffer: AutoCommandBuffer) -> Result<(), TileError> {
+        let uploaded = self.uploads.is_some();
+        let before = match self.uploads.take() {
+            Some(uploads) => Box::new(acquire_future.join(uploads)) as Box<GpuFuture>,
+            None => Box::new(now(self.device.clone()).join(acquire_future)) as Box<GpuFuture>
+        };
+
+        let future = before
+            .then_execute(queue.clone(), command_buffer)?
+            .then_swapchain_present(queue.clone(), swapchain.clone(), image_num)
+            .then_signal_fence_and_flush()?;
+
+        self.frames[self.current].future = Some(future);
+        if uploaded {
+            self.upload_frame = Some(self.current);
+        }
+        self.current = (self.current + 1) % self.frames.len();
+        Ok(())
+    }
+}
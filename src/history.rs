@@ -0,0 +1,330 @@
+// Undo and redo history for edits to the tile grid, atlas and palettes.
+// Each edit stores the state before and after, so it can be applied in either direction.
+use super::{
+    imagegen::TextureAtlas,
+    palette::{
+        Colour,
+        PaletteStore
+    },
+    vertexgrid::{
+        Flip,
+        TileGrid
+    }
+};
+
+use std::{
+    collections::VecDeque,
+    mem
+};
+
+// A reversible change.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Edit {
+    // Palette index of a grid tile.
+    TilePalette{
+        x: usize,
+        y: usize,
+        before: u32,
+        after: u32
+    },
+    // Atlas texture of a grid tile, as (tex_x, tex_y, flip).
+    TileTexture{
+        x: usize,
+        y: usize,
+        before: (usize, usize, Flip),
+        after: (usize, usize, Flip)
+    },
+    // All texels of a texture in the atlas.
+    Texels{
+        tex_x: usize,
+        tex_y: usize,
+        before: Vec<u8>,
+        after: Vec<u8>
+    },
+    // A palette colour.
+    Colour{
+        palette: usize,
+        index: usize,
+        before: Colour,
+        after: Colour
+    }
+}
+
+impl Edit {
+    // Change the palette of a grid tile.
+    pub fn tile_palette(grid: &dyn TileGrid, x: usize, y: usize, palette: u32) -> Self {
+        Edit::TilePalette{ x: x, y: y, before: grid.tile_palette(x, y), after: palette }
+    }
+
+    // Change the texture of a grid tile.
+    pub fn tile_texture(grid: &dyn TileGrid, x: usize, y: usize, tex_x: usize, tex_y: usize, flip: Flip) -> Self {
+        let (before_x, before_y) = grid.tile_texture(x, y);
+        Edit::TileTexture{
+            x: x,
+            y: y,
+            before: (before_x, before_y, grid.tile_flip(x, y)),
+            after: (tex_x, tex_y, flip)
+        }
+    }
+
+    // Replace the texels of an atlas texture.
+    pub fn texels(atlas: &TextureAtlas, tex_x: usize, tex_y: usize, texels: Vec<u8>) -> Self {
+        Edit::Texels{ tex_x: tex_x, tex_y: tex_y, before: atlas.tile_texels(tex_x, tex_y), after: texels }
+    }
+
+    // Set one texel of an atlas texture.
+    pub fn texel(atlas: &TextureAtlas, tex_x: usize, tex_y: usize, x: usize, y: usize, value: u8) -> Self {
+        let mut texels = atlas.tile_texels(tex_x, tex_y);
+        texels[y * atlas.tex_size() + x] = value;
+        Edit::texels(atlas, tex_x, tex_y, texels)
+    }
+
+    // Change a palette colour. A colour that doesn't exist makes an empty edit.
+    pub fn colour(palette_store: &PaletteStore, palette: usize, index: usize, colour: Colour) -> Self {
+        let before = palette_store.colour(palette, index).unwrap_or(colour);
+        Edit::Colour{ palette: palette, index: index, before: before, after: colour }
+    }
+
+    // Returns true if the edit doesn't change anything.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Edit::TilePalette{ before, after, .. }  => before == after,
+            Edit::TileTexture{ before, after, .. }  => before == after,
+            Edit::Texels{ before, after, .. }       => before == after,
+            Edit::Colour{ before, after, .. }       => before == after
+        }
+    }
+
+    // Approximate memory used by the edit, in bytes.
+    pub fn memory_size(&self) -> usize {
+        let heap = match self {
+            Edit::Texels{ before, after, .. } => before.capacity() + after.capacity(),
+            _ => 0
+        };
+        mem::size_of::<Edit>() + heap
+    }
+
+    // Apply the edit, forwards or backwards.
+    fn apply(&self, atlas: &mut TextureAtlas, grid: &mut dyn TileGrid, palette_store: &mut PaletteStore, forwards: bool) {
+        match self {
+            Edit::TilePalette{ x, y, before, after } => {
+                grid.set_tile_palette(*x, *y, if forwards { *after } else { *before });
+            },
+            Edit::TileTexture{ x, y, before, after } => {
+                let (tex_x, tex_y, flip) = if forwards { *after } else { *before };
+                grid.set_tile_texture_flipped(*x, *y, tex_x, tex_y, flip);
+            },
+            Edit::Texels{ tex_x, tex_y, before, after } => {
+                atlas.set_tile_texels(*tex_x, *tex_y, if forwards { after } else { before });
+            },
+            Edit::Colour{ palette, index, before, after } => {
+                // Only edits of existing colours are kept, so this can't fail.
+                let _ = palette_store.set_colour(*palette, *index, if forwards { *after } else { *before });
+            }
+        }
+    }
+}
+
+pub struct History {
+    // Oldest first.
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    // Maximum memory used by both stacks, in bytes.
+    budget: usize,
+    used: usize
+}
+
+impl History {
+    // Budget: maximum memory to use for edits, in bytes. The oldest edits are forgotten first,
+    // but the newest edit is always kept so it can be undone.
+    pub fn new(budget: usize) -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            budget: budget,
+            used: 0
+        }
+    }
+
+    // Apply an edit and add it to the history. Anything that could be redone is discarded.
+    pub fn apply(&mut self, edit: Edit, atlas: &mut TextureAtlas, grid: &mut dyn TileGrid, palette_store: &mut PaletteStore) {
+        if edit.is_empty() {
+            return;
+        }
+        edit.apply(atlas, grid, palette_store, true);
+
+        self.used -= self.redo.drain(..).map(|e| e.memory_size()).sum::<usize>();
+        self.used += edit.memory_size();
+        self.undo.push_back(edit);
+
+        while (self.used > self.budget) && (self.undo.len() > 1) {
+            if let Some(e) = self.undo.pop_front() {
+                self.used -= e.memory_size();
+            }
+        }
+    }
+
+    // Undo the last edit. Returns false if there is nothing to undo.
+    pub fn undo(&mut self, atlas: &mut TextureAtlas, grid: &mut dyn TileGrid, palette_store: &mut PaletteStore) -> bool {
+        match self.undo.pop_back() {
+            Some(edit) => {
+                edit.apply(atlas, grid, palette_store, false);
+                self.redo.push(edit);
+                true
+            },
+            None => false
+        }
+    }
+
+    // Redo the last undone edit. Returns false if there is nothing to redo.
+    pub fn redo(&mut self, atlas: &mut TextureAtlas, grid: &mut dyn TileGrid, palette_store: &mut PaletteStore) -> bool {
+        match self.redo.pop() {
+            Some(edit) => {
+                edit.apply(atlas, grid, palette_store, true);
+                self.undo.push_back(edit);
+                true
+            },
+            None => false
+        }
+    }
+
+    // Forget all edits, e.g. after the scene is replaced.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.used = 0;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    // Approximate memory used by the history, in bytes.
+    pub fn memory_used(&self) -> usize {
+        self.used
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::TileMap;
+
+    struct Fixture {
+        atlas: TextureAtlas,
+        grid: TileMap,
+        palette_store: PaletteStore
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            Fixture {
+                atlas: TextureAtlas::new(2, 2),
+                grid: TileMap::new(2, 2),
+                palette_store: PaletteStore::new(4)
+            }
+        }
+
+        fn apply(&mut self, history: &mut History, edit: Edit) {
+            history.apply(edit, &mut self.atlas, &mut self.grid, &mut self.palette_store);
+        }
+
+        fn undo(&mut self, history: &mut History) -> bool {
+            history.undo(&mut self.atlas, &mut self.grid, &mut self.palette_store)
+        }
+
+        fn redo(&mut self, history: &mut History) -> bool {
+            history.redo(&mut self.atlas, &mut self.grid, &mut self.palette_store)
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_in_order() {
+        let mut scene = Fixture::new();
+        let mut history = History::new(1 << 20);
+        for palette in 1..=3 {
+            let edit = Edit::tile_palette(&scene.grid, 0, 0, palette);
+            scene.apply(&mut history, edit);
+        }
+
+        assert!(scene.undo(&mut history));
+        assert_eq!(scene.grid.tile_palette(0, 0), 2);
+        assert!(scene.undo(&mut history));
+        assert_eq!(scene.grid.tile_palette(0, 0), 1);
+        assert!(scene.redo(&mut history));
+        assert_eq!(scene.grid.tile_palette(0, 0), 2);
+        assert!(scene.undo(&mut history));
+        assert!(scene.undo(&mut history));
+        assert_eq!(scene.grid.tile_palette(0, 0), 0);
+        assert!(!scene.undo(&mut history));
+
+        for palette in 1..=3 {
+            assert!(scene.redo(&mut history));
+            assert_eq!(scene.grid.tile_palette(0, 0), palette);
+        }
+        assert!(!scene.redo(&mut history));
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut scene = Fixture::new();
+        let mut history = History::new(1 << 20);
+        let edit = Edit::tile_texture(&scene.grid, 1, 0, 1, 1, Flip::default());
+        scene.apply(&mut history, edit);
+        assert!(scene.undo(&mut history));
+        assert!(history.can_redo());
+
+        let edit = Edit::texel(&scene.atlas, 1, 1, 0, 0, 3);
+        scene.apply(&mut history, edit);
+        assert!(!history.can_redo());
+        assert_eq!(scene.grid.tile_texture(1, 0), (0, 0));
+        assert_eq!(scene.atlas.texel(1, 1, 0, 0), 3);
+    }
+
+    #[test]
+    fn empty_edits_are_dropped() {
+        let mut scene = Fixture::new();
+        let mut history = History::new(1 << 20);
+        let edit = Edit::tile_palette(&scene.grid, 0, 0, 0);
+        assert!(edit.is_empty());
+        scene.apply(&mut history, edit);
+        assert!(!history.can_undo());
+        assert_eq!(history.memory_used(), 0);
+    }
+
+    #[test]
+    fn oldest_edits_are_evicted_over_budget() {
+        let mut scene = Fixture::new();
+        let edit_size = Edit::tile_palette(&scene.grid, 0, 0, 1).memory_size();
+        let mut history = History::new(edit_size * 2);
+        for palette in 1..=3 {
+            let edit = Edit::tile_palette(&scene.grid, 0, 0, palette);
+            scene.apply(&mut history, edit);
+        }
+        assert_eq!(history.memory_used(), edit_size * 2);
+
+        assert!(scene.undo(&mut history));
+        assert!(scene.undo(&mut history));
+        assert!(!scene.undo(&mut history));
+        assert_eq!(scene.grid.tile_palette(0, 0), 1);
+    }
+
+    #[test]
+    fn newest_edit_is_kept_over_budget() {
+        let mut scene = Fixture::new();
+        let mut history = History::new(0);
+        let edit = Edit::texels(&scene.atlas, 0, 0, vec![1; 4]);
+        scene.apply(&mut history, edit);
+        let edit = Edit::texels(&scene.atlas, 0, 1, vec![2; 4]);
+        scene.apply(&mut history, edit);
+
+        assert!(scene.undo(&mut history));
+        assert_eq!(scene.atlas.tile_texels(0, 1), vec![0; 4]);
+        assert!(!scene.undo(&mut history));
+        assert_eq!(scene.atlas.tile_texels(0, 0), vec![1; 4]);
+    }
+}
@@ -7,21 +7,106 @@ use vulkano::{
     device::Queue,
     image::{
         Dimensions,
+        ImageCreationError,
         immutable::ImmutableImage
     },
     format::{
+        Format,
         R8Uint
     },
     sync::NowFuture
 };
 
+use rand::Rng;
+
+use super::{
+    error::TileError,
+    vertexgrid::AtlasSlot
+};
+
 use std::sync::Arc;
 
+// An image, and the future for uploading it.
+pub type ImageUpload = (Arc<ImmutableImage<R8Uint>>, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>);
+
+// Ordered dithering thresholds, in sixteenths.
+const BAYER_4X4: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5]
+];
+
+// Procedural texture generators.
+// The random number generator picks the details (colours, sizes and directions), so each texture is different
+// but the same seed always gives the same texture.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Generator {
+    // Random texels.
+    Random,
+    // Squares of two colours, 1, 2 or 4 texels wide.
+    Checkerboard,
+    // Bands of colour across the texture, in a random direction.
+    Gradient,
+    // Smooth value noise.
+    Noise,
+    // Ordered dithering from one colour to another.
+    Dither,
+    // A few strokes between points on a 3x3 grid, like a letter or symbol.
+    Glyph
+}
+
+impl Generator {
+    pub const ALL: [Generator; 6] = [
+        Generator::Random,
+        Generator::Checkerboard,
+        Generator::Gradient,
+        Generator::Noise,
+        Generator::Dither,
+        Generator::Glyph
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Generator::Random       => "random",
+            Generator::Checkerboard => "checkerboard",
+            Generator::Gradient     => "gradient",
+            Generator::Noise        => "noise",
+            Generator::Dither       => "dither",
+            Generator::Glyph        => "glyph"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Generator::ALL.iter().find(|g| g.name() == name).cloned()
+    }
+
+    // Make the texels for a texture of size x size, in row-major order.
+    // Colours: colours in each palette. Fewer than 2 are treated as 2, as generators need a pair to contrast.
+    pub fn generate<R: Rng>(&self, size: usize, colours: u8, rng: &mut R) -> Vec<u8> {
+        if size == 0 {
+            return Vec::new();
+        }
+        let colours = colours.max(2);
+        match self {
+            Generator::Random       => (0..(size * size)).map(|_| rng.gen_range(0, colours)).collect(),
+            Generator::Checkerboard => checkerboard(size, colours, rng),
+            Generator::Gradient     => gradient(size, colours, rng),
+            Generator::Noise        => noise(size, colours, rng),
+            Generator::Dither       => dither(size, colours, rng),
+            Generator::Glyph        => glyph(size, colours, rng)
+        }
+    }
+}
+
 // Atlas of all tile textures. Must be square.
+#[derive(Clone)]
 pub struct TextureAtlas {
+    // Call mark_dirty after writing to the textures directly.
     pub textures: Vec<u8>,
     atlas_size: usize,
-    tex_size: usize
+    tex_size: usize,
+    dirty: bool
 }
 
 impl TextureAtlas {
@@ -34,33 +119,239 @@ impl TextureAtlas {
         TextureAtlas {
             textures: vec![0; area],
             atlas_size: atlas_size,
-            tex_size: tex_size
+            tex_size: tex_size,
+            dirty: true
+        }
+    }
+
+    // Size of the atlas in textures.
+    pub fn atlas_size(&self) -> usize {
+        self.atlas_size
+    }
+
+    // Size of a texture in texels.
+    pub fn tex_size(&self) -> usize {
+        self.tex_size
+    }
+
+    // Check that a slot is inside the atlas.
+    pub fn check_slot(&self, slot: AtlasSlot) -> Result<(), TileError> {
+        slot.check(self.atlas_size)
+    }
+
+    // Check that a texel is inside a texture in the atlas.
+    pub fn check_texel(&self, slot: AtlasSlot, x: usize, y: usize) -> Result<(), TileError> {
+        self.check_slot(slot)?;
+        if (x < self.tex_size) && (y < self.tex_size) {
+            Ok(())
+        } else {
+            Err(TileError::TexelOutOfRange{ x: x, y: y, tex_size: self.tex_size })
         }
     }
 
     // Generate a new tile texture in the atlas.
-    pub fn generate_tile_tex(&mut self, x: usize, y: usize) {
-        let base_x = x * self.tex_size;
-        let base_y = y * self.tex_size;
-
-        for y in base_y..(base_y + self.tex_size) {
-            let y_offset = y * self.atlas_size * self.tex_size;
-            let xy_offset = y_offset + base_x;
-            // i is the texel number.
-            for i in xy_offset..(xy_offset + self.tex_size) {
-                self.textures[i] = rand::random::<u8>() & 0b11;
-            }
+    pub fn generate_tile_tex<R: Rng>(&mut self, x: usize, y: usize, generator: Generator, colours: u8, rng: &mut R) {
+        let texels = generator.generate(self.tex_size, colours, rng);
+        self.set_tile_texels(x, y, &texels);
+    }
+
+    // Get the texels of a tile texture, in row-major order.
+    pub fn tile_texels(&self, tex_x: usize, tex_y: usize) -> Vec<u8> {
+        (0..(self.tex_size * self.tex_size))
+            .map(|i| self.texel(tex_x, tex_y, i % self.tex_size, i / self.tex_size))
+            .collect()
+    }
+
+    // Get the texels of a tile texture, checking that it is inside the atlas.
+    pub fn try_tile_texels(&self, slot: AtlasSlot) -> Result<Vec<u8>, TileError> {
+        self.check_slot(slot)?;
+        Ok(self.tile_texels(slot.x, slot.y))
+    }
+
+    // Set the texels of a tile texture, in row-major order.
+    pub fn set_tile_texels(&mut self, tex_x: usize, tex_y: usize, texels: &[u8]) {
+        for (i, texel) in texels.iter().take(self.tex_size * self.tex_size).enumerate() {
+            self.set_texel(tex_x, tex_y, i % self.tex_size, i / self.tex_size, *texel);
         }
     }
 
+    // Set the texels of a tile texture, checking that it is inside the atlas.
+    pub fn try_set_tile_texels(&mut self, slot: AtlasSlot, texels: &[u8]) -> Result<(), TileError> {
+        self.check_slot(slot)?;
+        self.set_tile_texels(slot.x, slot.y, texels);
+        Ok(())
+    }
+
+    // Get a texel from a tile texture in the atlas.
+    pub fn texel(&self, tex_x: usize, tex_y: usize, x: usize, y: usize) -> u8 {
+        self.textures[self.texel_index(tex_x, tex_y, x, y)]
+    }
+
+    // Set a texel in a tile texture in the atlas.
+    pub fn set_texel(&mut self, tex_x: usize, tex_y: usize, x: usize, y: usize, value: u8) {
+        let index = self.texel_index(tex_x, tex_y, x, y);
+        self.textures[index] = value;
+        self.dirty = true;
+    }
+
+    // Set a texel, checking that it is inside a texture in the atlas.
+    pub fn try_set_texel(&mut self, slot: AtlasSlot, x: usize, y: usize, value: u8) -> Result<(), TileError> {
+        self.check_texel(slot, x, y)?;
+        self.set_texel(slot.x, slot.y, x, y, value);
+        Ok(())
+    }
+
+    // Flag the atlas as changed, so it is uploaded again.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    // Returns true if the atlas has changed since the last call.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+
     // Make an image from the atlas.
-    pub fn make_image(&self, queue: Arc<Queue>) -> (Arc<ImmutableImage<R8Uint>>, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>) {
+    pub fn make_image(&self, queue: Arc<Queue>) -> Result<ImageUpload, TileError> {
         let width = (self.atlas_size * self.tex_size) as u32;
         ImmutableImage::from_iter(
             self.textures.clone().into_iter(),
             Dimensions::Dim2d { width: width, height: width },
             R8Uint,
             queue
-        ).expect("Couldn't create image.")
+        ).map_err(|e| match e {
+            ImageCreationError::FormatNotSupported |
+            ImageCreationError::UnsupportedUsage => TileError::UnsupportedFormat(Format::R8Uint),
+            e => e.into()
+        })
+    }
+
+    // Index into the texture data for a texel of a tile texture.
+    fn texel_index(&self, tex_x: usize, tex_y: usize, x: usize, y: usize) -> usize {
+        let width = self.atlas_size * self.tex_size;
+        let texel_y = tex_y * self.tex_size + y;
+        let texel_x = tex_x * self.tex_size + x;
+        texel_y * width + texel_x
     }
-}
\ No newline at end of file
+}
+
+// Pick two different colours.
+fn colour_pair<R: Rng>(colours: u8, rng: &mut R) -> (u8, u8) {
+    let a = rng.gen_range(0, colours);
+    (a, (a + rng.gen_range(1, colours)) % colours)
+}
+
+// Map a value from 0 to 1 to a colour.
+fn quantise(t: f32, colours: u8) -> u8 {
+    ((t * f32::from(colours)) as u8).min(colours - 1)
+}
+
+fn checkerboard<R: Rng>(size: usize, colours: u8, rng: &mut R) -> Vec<u8> {
+    let cell = [1, 2, 4][rng.gen_range(0, 3)];
+    let (a, b) = colour_pair(colours, rng);
+    (0..(size * size)).map(|i| {
+        let (x, y) = (i % size, i / size);
+        if ((x / cell) + (y / cell)).is_multiple_of(2) { a } else { b }
+    }).collect()
+}
+
+// Position of each texel along a random direction, from 0 to 1.
+fn ramp<R: Rng>(size: usize, rng: &mut R) -> Vec<f32> {
+    let direction = rng.gen_range(0, 4);
+    let reverse = rng.gen::<bool>();
+    let last = (size.max(2) - 1) as f32;
+
+    (0..(size * size)).map(|i| {
+        let (x, y) = ((i % size) as f32, (i / size) as f32);
+        let t = match direction {
+            0 => x / last,
+            1 => y / last,
+            2 => (x + y) / (2.0 * last),
+            _ => (x + last - y) / (2.0 * last)
+        };
+        if reverse { 1.0 - t } else { t }
+    }).collect()
+}
+
+fn gradient<R: Rng>(size: usize, colours: u8, rng: &mut R) -> Vec<u8> {
+    ramp(size, rng).into_iter().map(|t| quantise(t, colours)).collect()
+}
+
+fn noise<R: Rng>(size: usize, colours: u8, rng: &mut R) -> Vec<u8> {
+    // Random values on a lattice, smoothly interpolated between.
+    let spacing = (size / 2).max(1);
+    let points = size / spacing + 2;
+    let lattice = (0..(points * points)).map(|_| rng.gen::<f32>()).collect::<Vec<_>>();
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    (0..(size * size)).map(|i| {
+        let (x, y) = (i % size, i / size);
+        let (lx, ly) = (x / spacing, y / spacing);
+        let tx = smooth((x % spacing) as f32 / spacing as f32);
+        let ty = smooth((y % spacing) as f32 / spacing as f32);
+        let at = |px: usize, py: usize| lattice[py * points + px];
+        let top = lerp(at(lx, ly), at(lx + 1, ly), tx);
+        let bottom = lerp(at(lx, ly + 1), at(lx + 1, ly + 1), tx);
+        quantise(lerp(top, bottom, ty), colours)
+    }).collect()
+}
+
+fn dither<R: Rng>(size: usize, colours: u8, rng: &mut R) -> Vec<u8> {
+    let (a, b) = colour_pair(colours, rng);
+    ramp(size, rng).into_iter().enumerate().map(|(i, t)| {
+        let threshold = BAYER_4X4[(i / size) % 4][(i % size) % 4];
+        if (t * 16.0) as u8 > threshold { b } else { a }
+    }).collect()
+}
+
+fn glyph<R: Rng>(size: usize, colours: u8, rng: &mut R) -> Vec<u8> {
+    let mut texels = vec![0; size * size];
+    let colour = rng.gen_range(1, colours);
+    let mirror = rng.gen::<bool>();
+
+    // Points on a 3x3 grid, inset from the edges.
+    let margin = size / 8;
+    let span = size.saturating_sub(1 + 2 * margin);
+    let node = |n: usize| (margin + (n % 3) * span / 2, margin + (n / 3) * span / 2);
+
+    for _ in 0..rng.gen_range(2, 5) {
+        let (x0, y0) = node(rng.gen_range(0, 9));
+        let (x1, y1) = node(rng.gen_range(0, 9));
+        let steps = (x0 as isize - x1 as isize).abs().max((y0 as isize - y1 as isize).abs()).max(1) as usize;
+        for step in 0..=steps {
+            let x = (x0 * (steps - step) + x1 * step + steps / 2) / steps;
+            let y = (y0 * (steps - step) + y1 * step + steps / 2) / steps;
+            texels[y * size + x] = colour;
+            if mirror {
+                texels[y * size + (size - 1 - x)] = colour;
+            }
+        }
+    }
+    texels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{
+        rngs::StdRng,
+        SeedableRng
+    };
+
+    #[test]
+    fn generators_handle_small_inputs() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for generator in Generator::ALL.iter() {
+            assert!(generator.generate(0, 4, &mut rng).is_empty());
+            for size in 1..4 {
+                for colours in 0..3 {
+                    let texels = generator.generate(size, colours, &mut rng);
+                    assert_eq!(texels.len(), size * size);
+                    assert!(texels.iter().all(|t| *t < colours.max(2)));
+                }
+            }
+        }
+    }
+}
@@ -1,79 +1,468 @@
-use winit::VirtualKeyCode;
-
-// Command to alter visual data.
-pub enum Command {
-    ModifyTilePalette{
-        palette: u32,
-        x: usize,
-        y: usize
-    },
-    ModifyTileTexture{
-        tex_x: usize,
-        tex_y: usize,
-        x: usize,
-        y: usize
+use winit::{
+    ModifiersState,
+    VirtualKeyCode
+};
+
+use tile_test::{
+    command::Command,
+    palette::{
+        self,
+        PaletteStore
     },
-    GenerateTexture{
-        tex_x: usize,
-        tex_y: usize
+    vertexgrid::AtlasSlot
+};
+
+use super::bindings::{
+    Action,
+    Context,
+    IndexTarget,
+    KeyBindings,
+    KeyChord
+};
+
+// Input mode.
+enum Mode {
+    Neutral,
+    TileSelect(usize, usize),
+    TexSelect(usize, usize),
+    // Typing a number.
+    Entry(IndexTarget, String)
+}
+
+// Sizes of the things that can be selected.
+#[derive(Copy, Clone)]
+pub struct Limits {
+    // Grid size in tiles.
+    pub grid_width: usize,
+    pub grid_height: usize,
+    // Atlas size in textures.
+    pub atlas_size: usize,
+    pub palettes: usize,
+    // Colours in each palette.
+    pub colours: usize
+}
+
+impl Limits {
+    // Number of textures in the atlas. There is always at least one.
+    fn textures(&self) -> usize {
+        (self.atlas_size * self.atlas_size).max(1)
+    }
+
+    // Number of indices that can be typed for a target.
+    fn indices(&self, target: IndexTarget) -> usize {
+        match target {
+            IndexTarget::Tile    => self.grid_width * self.grid_height,
+            IndexTarget::Texture => self.textures(),
+            IndexTarget::Palette => self.palettes
+        }
     }
 }
 
 // State machine to track key input and react accordingly.
-pub enum KeyState {
-    Neutral,
-    TileSelect(usize, usize),
-    TexSelect(usize, usize)
+// Besides the direct tile and texture keys, there is a cursor on the grid and a current texture and palette,
+// which can be painted onto the tile under the cursor.
+// The current texture can also be opened in the texel editor, which paints texels with the current colour.
+// The current colour of the current palette is the entry changed by the palette editor.
+pub struct KeyState {
+    mode: Mode,
+    limits: Limits,
+    cursor: (usize, usize),
+    // Index of the current texture, in row-major order.
+    texture: usize,
+    palette: u32,
+    editing: bool,
+    colour: u8,
+    editing_palettes: bool,
+    // Round edited colours to 15 bits.
+    quantise: bool,
+    viewing_atlas: bool,
+    viewing_stats: bool
 }
 
 impl KeyState {
-    pub fn new() -> Self {
-        KeyState::Neutral
-    }
-
-    pub fn process_key(&mut self, k: VirtualKeyCode) -> (KeyState, Option<Command>) {
-        use self::KeyState::*;
-        use winit::VirtualKeyCode::*;
-
-        match self {
-            Neutral => match k {
-                Key1 => (TileSelect(0, 0), None),
-                Key2 => (TileSelect(1, 0), None),
-                Key3 => (TileSelect(2, 0), None),
-                Key4 => (TileSelect(3, 0), None),
-                Q => (TileSelect(0, 1), None),
-                W => (TileSelect(1, 1), None),
-                E => (TileSelect(2, 1), None),
-                R => (TileSelect(3, 1), None),
-                A => (TileSelect(0, 2), None),
-                S => (TileSelect(1, 2), None),
-                D => (TileSelect(2, 2), None),
-                F => (TileSelect(3, 2), None),
-                Z => (TileSelect(0, 3), None),
-                X => (TileSelect(1, 3), None),
-                C => (TileSelect(2, 3), None),
-                V => (TileSelect(3, 3), None),
-                G => (TexSelect(0, 0), None),
-                H => (TexSelect(1, 0), None),
-                J => (TexSelect(0, 1), None),
-                K => (TexSelect(1, 1), None),
-                _ => (Neutral, None)
-            },
-            TileSelect(x, y) => match k {
-                T => (Neutral, Some(Command::ModifyTilePalette{palette: 0, x: *x, y: *y})),
-                Y => (Neutral, Some(Command::ModifyTilePalette{palette: 1, x: *x, y: *y})),
-                U => (Neutral, Some(Command::ModifyTilePalette{palette: 2, x: *x, y: *y})),
-                I => (Neutral, Some(Command::ModifyTilePalette{palette: 3, x: *x, y: *y})),
-                G => (Neutral, Some(Command::ModifyTileTexture{tex_x: 0, tex_y: 0, x: *x, y: *y})),
-                H => (Neutral, Some(Command::ModifyTileTexture{tex_x: 1, tex_y: 0, x: *x, y: *y})),
-                J => (Neutral, Some(Command::ModifyTileTexture{tex_x: 0, tex_y: 1, x: *x, y: *y})),
-                K => (Neutral, Some(Command::ModifyTileTexture{tex_x: 1, tex_y: 1, x: *x, y: *y})),
-                _ => (Neutral, None)
-            },
-            TexSelect(x, y) => match k {
-                Return => (Neutral, Some(Command::GenerateTexture{tex_x: *x, tex_y: *y})),
-                _ => (Neutral, None)
+    pub fn new(limits: Limits) -> Self {
+        KeyState {
+            mode: Mode::Neutral,
+            limits: limits,
+            cursor: (0, 0),
+            texture: 0,
+            palette: 0,
+            editing: false,
+            colour: 0,
+            editing_palettes: false,
+            quantise: false,
+            viewing_atlas: false,
+            viewing_stats: false
+        }
+    }
+
+    // Change the sizes, e.g. after a new grid is loaded. Selections are clamped to fit.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.cursor = (self.cursor.0.min(limits.grid_width.saturating_sub(1)), self.cursor.1.min(limits.grid_height.saturating_sub(1)));
+        self.texture = self.texture.min(self.texture_count() - 1);
+        self.palette = self.palette.min((self.limits.palettes.max(1) - 1).min(u32::MAX as usize) as u32);
+        self.colour = self.colour.min((self.limits.colours.max(1) - 1).min(u8::MAX as usize) as u8);
+        self.mode = Mode::Neutral;
+    }
+
+    // Atlas position of the current texture.
+    pub fn texture(&self) -> (usize, usize) {
+        let atlas_size = self.limits.atlas_size.max(1);
+        (self.texture % atlas_size, self.texture / atlas_size)
+    }
+
+    pub fn palette(&self) -> u32 {
+        self.palette
+    }
+
+    // Index of the current colour in a palette.
+    pub fn colour(&self) -> u8 {
+        self.colour
+    }
+
+    // True while the texel editor is open.
+    pub fn editing(&self) -> bool {
+        self.editing
+    }
+
+    // True while the palette editor is open.
+    pub fn editing_palettes(&self) -> bool {
+        self.editing_palettes
+    }
+
+    // True while the atlas viewer is shown.
+    pub fn viewing_atlas(&self) -> bool {
+        self.viewing_atlas
+    }
+
+    // True while the frame statistics overlay is shown.
+    pub fn viewing_stats(&self) -> bool {
+        self.viewing_stats
+    }
+
+    // Describe the current selection, e.g. for the window title.
+    pub fn status(&self, palettes: &PaletteStore) -> String {
+        let (tex_x, tex_y) = self.texture();
+        let mut selection = format!("Cursor ({}, {}) | Texture {} ({}, {}) | Palette {}",
+            self.cursor.0, self.cursor.1, self.texture, tex_x, tex_y, self.palette);
+        if self.editing {
+            selection += &format!(" | Editing texels, colour {}", self.colour);
+        }
+        if self.editing_palettes {
+            if let Some(colour) = palettes.palette(self.palette as usize).and_then(|p| p.get(self.colour as usize)) {
+                let [r, g, b, _] = palette::to_rgba8(*colour);
+                selection += &format!(" | Colour {} #{:02x}{:02x}{:02x}", self.colour, r, g, b);
             }
+            if self.quantise {
+                selection += " (15-bit)";
+            }
+        }
+        match &self.mode {
+            Mode::Entry(target, digits) => format!("{} | Type {}: {}_", selection, target.name(), digits),
+            Mode::TileSelect(x, y)      => format!("{} | Tile ({}, {}) selected", selection, x, y),
+            Mode::TexSelect(x, y)       => format!("{} | Texture ({}, {}) selected", selection, x, y),
+            Mode::Neutral               => selection
         }
     }
-}
\ No newline at end of file
+
+    fn context(&self) -> Context {
+        match self.mode {
+            Mode::TileSelect(..)            => Context::Tile,
+            Mode::TexSelect(..)             => Context::Texture,
+            Mode::Neutral | Mode::Entry(..) => Context::Neutral
+        }
+    }
+
+    fn texture_count(&self) -> usize {
+        self.limits.textures()
+    }
+
+    fn tile_in_grid(&self, x: usize, y: usize) -> bool {
+        (x < self.limits.grid_width) && (y < self.limits.grid_height)
+    }
+
+    fn texture_in_atlas(&self, x: usize, y: usize) -> bool {
+        AtlasSlot::new(x, y).check(self.limits.atlas_size).is_ok()
+    }
+
+    pub fn process_key(&mut self, bindings: &KeyBindings, k: VirtualKeyCode, modifiers: ModifiersState, palettes: &PaletteStore) -> Option<Command> {
+        if let Mode::Entry(target, digits) = &mut self.mode {
+            let target = *target;
+            let count = self.limits.indices(target);
+            let digit = match k {
+                VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => Some('0'),
+                VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1 => Some('1'),
+                VirtualKeyCode::Key2 | VirtualKeyCode::Numpad2 => Some('2'),
+                VirtualKeyCode::Key3 | VirtualKeyCode::Numpad3 => Some('3'),
+                VirtualKeyCode::Key4 | VirtualKeyCode::Numpad4 => Some('4'),
+                VirtualKeyCode::Key5 | VirtualKeyCode::Numpad5 => Some('5'),
+                VirtualKeyCode::Key6 | VirtualKeyCode::Numpad6 => Some('6'),
+                VirtualKeyCode::Key7 | VirtualKeyCode::Numpad7 => Some('7'),
+                VirtualKeyCode::Key8 | VirtualKeyCode::Numpad8 => Some('8'),
+                VirtualKeyCode::Key9 | VirtualKeyCode::Numpad9 => Some('9'),
+                _ => None
+            };
+            match (k, digit) {
+                // Digits that would make the index out of range are refused.
+                (_, Some(d)) => {
+                    let typed = format!("{}{}", digits, d);
+                    if typed.parse::<usize>().is_ok_and(|i| i < count) {
+                        *digits = typed;
+                    }
+                },
+                (VirtualKeyCode::Back, _) => { digits.pop(); },
+                (VirtualKeyCode::Return, _) | (VirtualKeyCode::NumpadEnter, _) => {
+                    let index = digits.parse::<usize>().ok();
+                    self.mode = Mode::Neutral;
+                    if let Some(i) = index {
+                        self.select_index(target, i);
+                    }
+                },
+                // Any other key cancels.
+                _ => self.mode = Mode::Neutral
+            }
+            return None;
+        }
+
+        // Bindings never conflict within a context, so there is at most one action.
+        let context = self.context();
+        let action = bindings.actions(KeyChord::new(k, modifiers)).iter()
+            .find(|a| a.contexts().contains(&context))
+            .cloned();
+
+        let mode = std::mem::replace(&mut self.mode, Mode::Neutral);
+        match (mode, action) {
+            (_, Some(Action::Undo))                         => Some(Command::Undo),
+            (_, Some(Action::Redo))                         => Some(Command::Redo),
+            (Mode::Neutral, Some(Action::MoveCursor(dx, dy))) => {
+                let max_x = self.limits.grid_width.saturating_sub(1) as isize;
+                let max_y = self.limits.grid_height.saturating_sub(1) as isize;
+                self.cursor = (
+                    (self.cursor.0 as isize + dx).clamp(0, max_x) as usize,
+                    (self.cursor.1 as isize + dy).clamp(0, max_y) as usize
+                );
+                None
+            },
+            (Mode::Neutral, Some(Action::StepTexture(n)))   => {
+                self.texture = step(self.texture, n, self.texture_count());
+                None
+            },
+            (Mode::Neutral, Some(Action::StepTextureRow(n))) => {
+                self.texture = step(self.texture, n * self.limits.atlas_size as isize, self.texture_count());
+                None
+            },
+            (Mode::Neutral, Some(Action::StepPalette(n)))   => {
+                self.palette = step(self.palette as usize, n, self.limits.palettes.max(1)) as u32;
+                None
+            },
+            (Mode::Neutral, Some(Action::PaintTexture))     => self.paint_texture(self.cursor.0, self.cursor.1),
+            (Mode::Neutral, Some(Action::PaintPalette))     => self.paint_palette(self.cursor.0, self.cursor.1),
+            (Mode::Neutral, Some(Action::GenerateCurrent(g))) => {
+                let (tex_x, tex_y) = self.texture();
+                Some(Command::GenerateTexture{tex_x: tex_x, tex_y: tex_y, generator: g})
+            },
+            (Mode::Neutral, Some(Action::TypeIndex(target))) => {
+                self.mode = Mode::Entry(target, String::new());
+                None
+            },
+            (Mode::Neutral, Some(Action::EditTexels))       => {
+                self.editing = !self.editing;
+                None
+            },
+            (Mode::Neutral, Some(Action::StepColour(n)))    => {
+                self.colour = step(self.colour as usize, n, self.limits.colours.max(1)) as u8;
+                None
+            },
+            (Mode::Neutral, Some(Action::EditPalettes))     => {
+                self.editing_palettes = !self.editing_palettes;
+                None
+            },
+            (Mode::Neutral, Some(Action::AdjustColour(channel, n))) => self.adjust_colour(palettes, channel, n),
+            (Mode::Neutral, Some(Action::ToggleQuantise))   => {
+                self.quantise = !self.quantise;
+                None
+            },
+            (Mode::Neutral, Some(Action::ViewAtlas))        => {
+                self.viewing_atlas = !self.viewing_atlas;
+                None
+            },
+            (Mode::Neutral, Some(Action::ViewStats))        => {
+                self.viewing_stats = !self.viewing_stats;
+                None
+            },
+            // Direct keys. Tiles and textures outside the grid or atlas are ignored.
+            (Mode::Neutral, Some(Action::SelectTile(x, y))) => {
+                self.select_tile(x, y);
+                None
+            },
+            (Mode::Neutral, Some(Action::Texture(x, y)))    => {
+                if self.texture_in_atlas(x, y) {
+                    self.texture = AtlasSlot::new(x, y).index(self.limits.atlas_size);
+                    self.mode = Mode::TexSelect(x, y);
+                }
+                None
+            },
+            (Mode::Neutral, Some(Action::Export))           => Some(Command::ExportImages),
+            (Mode::Neutral, Some(Action::Save))             => Some(Command::SaveProject),
+            (Mode::Neutral, Some(Action::Load))             => Some(Command::LoadProject),
+            (Mode::TileSelect(x, y), Some(Action::Palette(p))) => {
+                if (p as usize) >= self.limits.palettes {
+                    return None;
+                }
+                self.palette = p;
+                Some(Command::ModifyTilePalette{palette: p, x: x, y: y})
+            },
+            (Mode::TileSelect(x, y), Some(Action::Texture(tex_x, tex_y))) => {
+                self.texture_in_atlas(tex_x, tex_y)
+                    .then_some(Command::ModifyTileTexture{tex_x: tex_x, tex_y: tex_y, x: x, y: y})
+            },
+            (Mode::TexSelect(x, y), Some(Action::Generate(g))) => Some(Command::GenerateTexture{tex_x: x, tex_y: y, generator: g}),
+            _ => None
+        }
+    }
+
+    // Select a tile, as if with its key. The cursor moves to the tile.
+    pub fn select_tile(&mut self, x: usize, y: usize) {
+        if self.tile_in_grid(x, y) {
+            self.cursor = (x, y);
+            self.mode = Mode::TileSelect(x, y);
+        }
+    }
+
+    // Command to give a tile the current texture.
+    pub fn paint_texture(&self, x: usize, y: usize) -> Option<Command> {
+        let (tex_x, tex_y) = self.texture();
+        self.tile_in_grid(x, y).then_some(Command::ModifyTileTexture{tex_x: tex_x, tex_y: tex_y, x: x, y: y})
+    }
+
+    // Command to give a tile the current palette.
+    pub fn paint_palette(&self, x: usize, y: usize) -> Option<Command> {
+        self.tile_in_grid(x, y).then_some(Command::ModifyTilePalette{palette: self.palette, x: x, y: y})
+    }
+
+    // Command to set a texel of the current texture to the current colour, while the texel editor is open.
+    pub fn paint_texel(&self, x: usize, y: usize) -> Option<Command> {
+        let (tex_x, tex_y) = self.texture();
+        self.editing.then_some(Command::SetTexel{tex_x: tex_x, tex_y: tex_y, x: x, y: y, value: self.colour})
+    }
+
+    // Make an atlas texture current, e.g. by clicking it in the atlas viewer.
+    pub fn select_texture(&mut self, tex_x: usize, tex_y: usize) {
+        if self.texture_in_atlas(tex_x, tex_y) {
+            self.texture = AtlasSlot::new(tex_x, tex_y).index(self.limits.atlas_size);
+        }
+    }
+
+    // Select a palette entry, e.g. by clicking its swatch.
+    pub fn select_colour(&mut self, palette: usize, index: usize) {
+        if (palette < self.limits.palettes) && (index < self.limits.colours) {
+            self.palette = palette as u32;
+            self.colour = index as u8;
+        }
+    }
+
+    // Command to change a channel of the current palette entry by a number of 5-bit steps.
+    fn adjust_colour(&self, palettes: &PaletteStore, channel: usize, n: isize) -> Option<Command> {
+        let mut colour = *palettes.palette(self.palette as usize)?.get(self.colour as usize)?;
+        let level = (colour[channel] * 31.0).round() + n as f32;
+        colour[channel] = level.clamp(0.0, 31.0) / 31.0;
+        if self.quantise {
+            colour = palette::quantise_15bit(colour);
+        }
+
+        let [r, g, b, _] = palette::to_rgba8(colour);
+        Some(Command::SetColour{palette: self.palette as usize, index: self.colour as usize, rgb: [r, g, b]})
+    }
+
+    // Select a typed index. Entry only accepts indices in range.
+    fn select_index(&mut self, target: IndexTarget, index: usize) {
+        if index >= self.limits.indices(target) {
+            return;
+        }
+        match target {
+            IndexTarget::Tile    => self.cursor = (index % self.limits.grid_width, index / self.limits.grid_width),
+            IndexTarget::Texture => self.texture = index,
+            IndexTarget::Palette => self.palette = index as u32
+        }
+    }
+}
+
+// Step through a range of indices, wrapping around at either end.
+fn step(index: usize, n: isize, count: usize) -> usize {
+    (index as isize + n).rem_euclid(count as isize) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use VirtualKeyCode::*;
+
+    fn key_state(grid_size: usize, atlas_size: usize, palettes: usize) -> KeyState {
+        KeyState::new(Limits {
+            grid_width: grid_size,
+            grid_height: grid_size,
+            atlas_size: atlas_size,
+            palettes: palettes,
+            colours: 4
+        })
+    }
+
+    fn press(state: &mut KeyState, keys: &[VirtualKeyCode]) -> Option<Command> {
+        let bindings = KeyBindings::default();
+        let palettes = PaletteStore::from_palettes(Vec::new(), 4);
+        keys.iter().fold(None, |_, &k| state.process_key(&bindings, k, ModifiersState::default(), &palettes))
+    }
+
+    #[test]
+    fn cursor_is_clamped_to_the_grid() {
+        let mut state = key_state(3, 2, 4);
+        press(&mut state, &[Left, Up]);
+        assert_eq!(state.cursor, (0, 0));
+        press(&mut state, &[Right, Right, Right, Down, Down, Down]);
+        assert_eq!(state.cursor, (2, 2));
+
+        state.set_limits(Limits{ grid_width: 2, grid_height: 1, ..state.limits });
+        assert_eq!(state.cursor, (1, 0));
+        press(&mut state, &[Down]);
+        assert_eq!(state.cursor, (1, 0));
+    }
+
+    #[test]
+    fn paging_steps_through_atlas_rows() {
+        let mut state = key_state(2, 3, 4);
+        press(&mut state, &[RBracket, PageDown]);
+        assert_eq!(state.texture(), (1, 1));
+        press(&mut state, &[PageDown]);
+        assert_eq!(state.texture(), (1, 2));
+        // Paging wraps around to the other end of the atlas.
+        press(&mut state, &[PageDown]);
+        assert_eq!(state.texture(), (1, 0));
+        press(&mut state, &[PageUp]);
+        assert_eq!(state.texture(), (1, 2));
+    }
+
+    #[test]
+    fn typed_indices_stay_in_range() {
+        let mut state = key_state(2, 2, 3);
+        press(&mut state, &[B, Key3, Return]);
+        assert_eq!(state.cursor, (1, 1));
+
+        // Digits that would go past the last tile are refused.
+        press(&mut state, &[B, Key4]);
+        assert!(state.status(&PaletteStore::from_palettes(Vec::new(), 4)).ends_with("Type tile: _"));
+        press(&mut state, &[Key2, Key0, Return]);
+        assert_eq!(state.cursor, (0, 1));
+
+        press(&mut state, &[M, Key7, Key2, Return]);
+        assert_eq!(state.palette(), 2);
+        press(&mut state, &[M, Key9, Return]);
+        assert_eq!(state.palette(), 2);
+
+        // Entering nothing, or any other key, leaves the selection alone.
+        press(&mut state, &[N, Key3, Escape, Return]);
+        assert_eq!(state.texture(), (0, 0));
+        press(&mut state, &[N, Return]);
+        assert_eq!(state.texture(), (0, 0));
+    }
+}
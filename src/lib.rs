@@ -0,0 +1,30 @@
+// Tile and palette based rendering components, shared by the demo binary.
+pub mod ansi;
+pub mod assets;
+pub mod command;
+pub mod device;
+pub mod error;
+pub mod export;
+pub mod font;
+pub mod history;
+pub mod imagegen;
+pub mod palette;
+pub mod palettefile;
+pub mod project;
+pub mod record;
+pub mod scene;
+pub mod textgrid;
+pub mod tiled;
+pub mod tilemap;
+pub mod tilesheet;
+pub mod vertexgrid;
+pub mod watch;
+
+#[derive(Default, Copy, Clone)]
+pub struct Vertex {
+    position: [f32; 2],
+    tex_coord: [f32; 2],
+    palette_index: u32
+}
+
+vulkano::impl_vertex!(Vertex, position, tex_coord, palette_index);
@@ -3,7 +3,7 @@ use vulkano::{
         Instance, PhysicalDevice
     },
     device::{
-        Device, DeviceExtensions
+        Device, DeviceExtensions, Queue
     },
     buffer::{
         BufferUsage,
@@ -16,7 +16,7 @@ use vulkano::{
         viewport::Viewport, GraphicsPipeline
     },
     command_buffer::{
-        AutoCommandBufferBuilder, DynamicState
+        AutoCommandBuffer, AutoCommandBufferBuilder, CommandBufferExecFuture, DynamicState
     },
     sampler::{
         Filter,
@@ -25,12 +25,11 @@ use vulkano::{
         SamplerAddressMode
     },
     swapchain::{
-        Swapchain, SurfaceTransform, PresentMode, acquire_next_image
+        Swapchain, SurfaceTransform, acquire_next_image
     },
     sync::{
-        now, GpuFuture
-    },
-    descriptor::descriptor_set::FixedSizeDescriptorSetsPool
+        GpuFuture, NowFuture
+    }
 };
 
 use vulkano_win::VkSurfaceBuild;
@@ -42,7 +41,8 @@ use winit::{
     KeyboardInput,
     ElementState,
     ControlFlow,
-    WindowBuilder
+    WindowBuilder,
+    dpi::LogicalSize
 };
 
 use cgmath::{
@@ -50,25 +50,119 @@ use cgmath::{
     Vector4
 };
 
-use std::sync::Arc;
+use rand::{
+    rngs::StdRng,
+    Rng,
+    SeedableRng
+};
+
+use std::{
+    mem,
+    path::{
+        Path,
+        PathBuf
+    },
+    sync::Arc,
+    time::{
+        Duration,
+        Instant
+    }
+};
+
+use tile_test::{
+    assets::{
+        Assets,
+        AssetUpdate,
+        Level
+    },
+    device::{
+        self,
+        DeviceInfo,
+        DeviceSelection
+    },
+    error::TileError,
+    export,
+    imagegen::{
+        self,
+        Generator
+    },
+    palette::{
+        Palette,
+        PaletteStore
+    },
+    palettefile::{
+        self,
+        PaletteFormat
+    },
+    project::{
+        Project,
+        ProjectFormat
+    },
+    record::{
+        Recorder,
+        Replay
+    },
+    scene::Scene,
+    tiled::TiledMap,
+    tilemap::TileMap,
+    vertexgrid,
+    Vertex
+};
 
-mod imagegen;
+mod atlasviewer;
+mod bindings;
+mod config;
+mod frameloop;
+mod frames;
 mod keystate;
-mod vertexgrid;
+mod mouse;
+mod paletteeditor;
+mod profile;
+mod statsoverlay;
+mod texeleditor;
+
+use bindings::KeyBindings;
+use config::Config;
+use frameloop::{
+    FrameStats,
+    LoopMode,
+    TickClock
+};
+use profile::{
+    FrameProfile,
+    ProfileLog
+};
 
-const TILE_SIZE: usize = 8;     // In pixels
-const ATLAS_SIZE: usize = 2;    // In tiles
+const LEVEL_ATLAS_SIZE: usize = 16; // In tiles, when loading a map
+
+const PALETTE_COUNT: usize = 4; // Palettes the shader can use
+const PALETTE_COLOURS: usize = 4; // Colours in each palette the shader can use
+
+const HISTORY_BUDGET: usize = 1 << 20; // In bytes
+
+const STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+const PROJECT_PATH: &str = "project.tproj";
+const PROJECT_JSON_PATH: &str = "project.json";
 
 #[derive(Copy, Clone)]
 struct PaletteUniformBufferObject {
-    _colours: [Matrix4<f32>; 4]
+    _colours: [Matrix4<f32>; PALETTE_COUNT]
 }
 
-#[derive(Default, Copy, Clone)]
-pub struct Vertex {
-    position: [f32; 2],
-    tex_coord: [f32; 2],
-    palette_index: u32
+impl PaletteUniformBufferObject {
+    // Pack palettes into the uniform. Missing palettes and colours are left black.
+    fn new(palettes: &[Palette]) -> Self {
+        let mut colours = [Matrix4::from_scale(0.0); PALETTE_COUNT];
+        for (matrix, palette) in colours.iter_mut().zip(palettes.iter()) {
+            for (i, colour) in palette.iter().take(PALETTE_COLOURS).enumerate() {
+                matrix[i] = *colour;
+            }
+        }
+        PaletteUniformBufferObject {
+            _colours: colours
+        }
+    }
 }
 
 mod vs {
@@ -107,32 +201,117 @@ mod fs {
             mat4 colours[4];
         } palette;
 
+        // Bounds of the hovered and selected cells in framebuffer pixels.
+        layout(push_constant) uniform Highlight {
+            vec4 hover;
+            vec4 selection;
+        } highlight;
+
+        // Distance inside the edge of a rectangle, negative if outside.
+        float edge_distance(vec2 pos, vec4 rect) {
+            return min(min(pos.x - rect.x, rect.z - pos.x), min(pos.y - rect.y, rect.w - pos.y));
+        }
+
         layout(location = 0) out vec4 outColor;
 
         void main() {
             uint texel = texture(atlas, texCoord).x;
             outColor = palette.colours[paletteIndex][texel];
+
+            // Invert a border around the hovered and selected cells.
+            vec2 pos = gl_FragCoord.xy;
+            float hover = edge_distance(pos, highlight.hover);
+            float selection = edge_distance(pos, highlight.selection);
+            if ((hover >= 0.0 && hover < 2.0) || (selection >= 0.0 && selection < 2.0)) {
+                outColor = vec4(1.0 - outColor.rgb, outColor.a);
+            }
         }"#
     }
 }
 
-vulkano::impl_vertex!(Vertex, position, tex_coord, palette_index);
-
 fn main() {
+    let config = Config::from_args(std::env::args().skip(1)).unwrap_or_else(|e| exit_with_error(&e.to_string()));
+    if config.help {
+        print!("{}", config::USAGE);
+        return;
+    }
+
+    // Key bindings, from a config file or the defaults.
+    let bindings = match &config.bindings {
+        Some(path) => KeyBindings::load(path).unwrap_or_else(|e| exit_with_error(&format!("Couldn't load key bindings: {}", e))),
+        None => KeyBindings::default()
+    };
+    if config.print_bindings {
+        print!("{}", bindings);
+        return;
+    }
+
+    if config.list_devices {
+        if let Err(e) = list_devices(&config.device) {
+            exit_with_error(&e.to_string());
+        }
+        return;
+    }
+
+    if let Err(e) = run(config, bindings) {
+        exit_with_error(&e.to_string());
+    }
+}
+
+// Print every device, marking the one the demo would draw with.
+fn list_devices(selection: &DeviceSelection) -> Result<(), TileError> {
+    let instance = Instance::new(None, &vulkano_win::required_extensions(), None)?;
+    let selected = selection.select(&instance).ok().map(|p| p.index());
+    for physical in PhysicalDevice::enumerate(&instance) {
+        let marker = if Some(physical.index()) == selected { '*' } else { ' ' };
+        println!("{} {}", marker, DeviceInfo::new(physical));
+    }
+    match selected {
+        Some(index) => println!("* Selected for {}: device {}", selection, index),
+        None => println!("No usable {}", selection)
+    }
+    Ok(())
+}
+
+// Open the window and run the demo until it is closed.
+fn run(config: Config, bindings: KeyBindings) -> Result<(), TileError> {
+
+    // Command log to play back, and the random number generator used for everything generated.
+    // Replays use the recorded seed, so the scene comes out the same.
+    let mut replay = config.replay.as_ref().map(|path| Replay::load(path)).transpose()?;
+    let seed = replay.as_ref().map(|r| r.seed).or(config.seed).unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+    println!("Seed: {}", seed);
+
+    // Load a Tiled map and a palette file if they were given on the command line.
+    let (palette_paths, map_paths): (Vec<PathBuf>, Vec<PathBuf>) = config.files.iter().cloned()
+        .partition(|p| palettefile::is_palette_file(p));
+    let mut assets = Assets::new(map_paths.into_iter().next(), palette_paths.into_iter().next(), LEVEL_ATLAS_SIZE, config.tile_size);
+
+    let level = assets.load_level()?;
+    if let Some(Level{ map, .. }) = &level {
+        if map.palettes.len() > PALETTE_COUNT {
+            println!("Map uses {} palettes: only the first {} will be shown.", map.palettes.len(), PALETTE_COUNT);
+        }
+    }
+
+    // Or a saved scene to start with.
+    let saved = config.scene.as_ref().map(|path| Project::load(path)).transpose()?;
+
     // Make instance with window extensions.
     let instance = {
         let extensions = vulkano_win::required_extensions();
-        Instance::new(None, &extensions, None).expect("Failed to create vulkan instance")
+        Instance::new(None, &extensions, None)?
     };
 
     // Get graphics device.
-    let physical = PhysicalDevice::enumerate(&instance).next()
-        .expect("No device available");
+    let physical = config.device.select(&instance)?;
+    println!("Using device {}: {}", physical.index(), physical.name());
 
     // Get graphics command queue family from graphics device.
     let queue_family = physical.queue_families()
         .find(|&q| q.supports_graphics())
-        .expect("Could not find a graphical queue family");
+        .ok_or(TileError::NoDevice)?;
 
     // Make software device and queue iterator of the graphics family.
     let (device, mut queues) = {
@@ -141,97 +320,140 @@ fn main() {
             .. DeviceExtensions::none()
         };
         
-        Device::new(physical, physical.supported_features(), &device_ext,
-                    [(queue_family, 0.5)].iter().cloned())
-            .expect("Failed to create device")
+        Device::new(physical, &device::required_features(), &device_ext,
+                    [(queue_family, 0.5)].iter().cloned())?
     };
 
     // Get a queue from the iterator.
-    let queue = queues.next().unwrap();
+    let queue = queues.next().ok_or(TileError::NoDevice)?;
 
-    // Make an events loop and a window.
+    // Make an events loop and a window, sized to show the grid at the configured scale.
     let mut events_loop = EventsLoop::new();
-    let surface = WindowBuilder::new().build_vk_surface(&events_loop, instance.clone()).unwrap();
+    let (grid_width, grid_height, tile_size) = match (&saved, &level) {
+        (Some(project), _)              => (project.tilemap.width, project.tilemap.height, project.atlas.tex_size()),
+        (None, Some(Level{ map, .. }))  => (map.width, map.height, config.tile_size),
+        (None, None)                    => (config.grid_width, config.grid_height, config.tile_size)
+    };
+    let monitor = events_loop.get_primary_monitor();
+    let window_size = window_size(grid_width * tile_size, grid_height * tile_size, config.scale, monitor.get_dimensions().to_logical(monitor.get_hidpi_factor()));
+    let surface = WindowBuilder::new()
+        .with_dimensions(window_size)
+        .build_vk_surface(&events_loop, instance.clone())
+        .map_err(|e| TileError::Window(e.to_string()))?;
 
     // Get a swapchain and images for use with the swapchain.
     let (swapchain, images) = {
-        let caps = surface.capabilities(physical)
-            .expect("Failed to get surface capabilities");
+        let caps = surface.capabilities(physical)?;
         let dimensions = caps.current_extent.unwrap_or([512, 512]);
-        let alpha = caps.supported_composite_alpha.iter().next().unwrap();
-        let format = caps.supported_formats[0].0;
+        let alpha = caps.supported_composite_alpha.iter().next()
+            .ok_or_else(|| TileError::Window("the surface has no composite alpha modes".to_string()))?;
+        let format = caps.supported_formats.first()
+            .ok_or_else(|| TileError::Window("the surface has no formats".to_string()))?.0;
+        if !caps.present_modes.supports(config.present_mode) {
+            let supported = caps.present_modes.iter().map(config::present_mode_name).collect::<Vec<_>>();
+            println!("Supported present modes: {}", supported.join(", "));
+            return Err(TileError::UnsupportedPresentMode(config.present_mode));
+        }
 
         Swapchain::new(device.clone(), surface.clone(),
             caps.min_image_count, format, dimensions, 1, caps.supported_usage_flags, &queue,
-            SurfaceTransform::Identity, alpha, PresentMode::Fifo, true, None)
-            .expect("Failed to create swapchain")
+            SurfaceTransform::Identity, alpha, config.present_mode, true, None)?
     };
 
-    // Make vertices (4 squares.)
-    let mut vertex_grid = {
-        // Triangle list with grid of 16 squares (4x4), with atlas size 2x2.
-        let mut vertex_grid = vertexgrid::VertexGrid::new(&device, 4, 4, ATLAS_SIZE);
+    // Make palettes.
+    let palettes: Vec<Palette> = if let Some(Level{ map, .. }) = &level {
+        map.palettes.clone()
+    } else {
+        vec![
+            vec![
+                Vector4::new(1.0, 0.0, 0.0, 1.0),
+                Vector4::new(0.8, 0.4, 0.1, 1.0),
+                Vector4::new(1.0, 1.0, 0.0, 1.0),
+                Vector4::new(0.8, 0.2, 0.0, 1.0)
+            ],
+            vec![
+                Vector4::new(0.0, 1.0, 0.0, 1.0),
+                Vector4::new(0.0, 0.8, 0.8, 1.0),
+                Vector4::new(0.1, 0.9, 0.3, 1.0),
+                Vector4::new(0.5, 1.0, 0.1, 1.0)
+            ],
+            vec![
+                Vector4::new(0.0, 0.0, 1.0, 1.0),
+                Vector4::new(0.3, 0.3, 0.8, 1.0),
+                Vector4::new(0.7, 0.2, 0.9, 1.0),
+                Vector4::new(0.4, 0.0, 0.9, 1.0)
+            ],
+            vec![
+                Vector4::new(1.0, 1.0, 1.0, 1.0),
+                Vector4::new(0.6, 0.6, 0.6, 1.0),
+                Vector4::new(0.3, 0.3, 0.3, 1.0),
+                Vector4::new(0.0, 0.0, 0.0, 1.0)
+            ]
+        ]
+    };
+
+    let mut palette_store = match &saved {
+        Some(project) => project.palette_store(),
+        None => PaletteStore::from_palettes(palettes, config.colours())
+    };
+    if let Some(colours) = assets.load_colours()? {
+        palette_store.set_colours(&colours);
+    }
 
-        // Pick a random tex and palette combo for each tile.
-        for y in 0..4 {
-            for x in 0..4 {
-                vertex_grid.set_tile_texture(x, y, rand::random::<usize>() & 1, rand::random::<usize>() & 1);
-                vertex_grid.set_tile_palette(x, y, rand::random::<u32>() & 0b11);
+    // Make vertices.
+    let vertex_grid = if let Some(project) = &saved {
+        let mut vertex_grid = vertexgrid::VertexGrid::new(&device, project.tilemap.width, project.tilemap.height, project.atlas.atlas_size());
+        project.tilemap.apply(&mut vertex_grid);
+        vertex_grid
+    } else if let Some(Level{ map, .. }) = &level {
+        level_grid(&device, map)
+    } else {
+        // Triangle list with a grid of squares, 4x4 with an atlas of 2x2 by default.
+        let mut vertex_grid = vertexgrid::VertexGrid::new(&device, config.grid_width, config.grid_height, config.atlas_size);
+
+        // Pick a random tex and palette combo for each tile, from the palettes the shader can show.
+        let palette_count = palette_store.len().clamp(1, PALETTE_COUNT) as u32;
+        for y in 0..config.grid_height {
+            for x in 0..config.grid_width {
+                vertex_grid.set_tile_texture(x, y, rng.gen_range(0, config.atlas_size), rng.gen_range(0, config.atlas_size));
+                vertex_grid.set_tile_palette(x, y, rng.gen_range(0, palette_count));
             }
         }
 
         vertex_grid
     };
 
+    // Reload assets when their files change, waking the event loop to show them.
+    let events_proxy = events_loop.create_proxy();
+    assets.watch(Duration::from_millis(250), move || {
+        let _ = events_proxy.wakeup();
+    });
 
     // Make palette buffer.
-    // TODO: only recreate buffer when the data has changed.
-    let (palette_buffer, palette_future) = ImmutableBuffer::from_data(
-        PaletteUniformBufferObject{
-            _colours: [
-                Matrix4::from_cols(
-                    Vector4::new(1.0, 0.0, 0.0, 1.0),
-                    Vector4::new(0.8, 0.4, 0.1, 1.0),
-                    Vector4::new(1.0, 1.0, 0.0, 1.0),
-                    Vector4::new(0.8, 0.2, 0.0, 1.0)
-                ),
-                Matrix4::from_cols(
-                    Vector4::new(0.0, 1.0, 0.0, 1.0),
-                    Vector4::new(0.0, 0.8, 0.8, 1.0),
-                    Vector4::new(0.1, 0.9, 0.3, 1.0),
-                    Vector4::new(0.5, 1.0, 0.1, 1.0)
-                ),
-                Matrix4::from_cols(
-                    Vector4::new(0.0, 0.0, 1.0, 1.0),
-                    Vector4::new(0.3, 0.3, 0.8, 1.0),
-                    Vector4::new(0.7, 0.2, 0.9, 1.0),
-                    Vector4::new(0.4, 0.0, 0.9, 1.0)
-                ),
-                Matrix4::from_cols(
-                    Vector4::new(1.0, 1.0, 1.0, 1.0),
-                    Vector4::new(0.6, 0.6, 0.6, 1.0),
-                    Vector4::new(0.3, 0.3, 0.3, 1.0),
-                    Vector4::new(0.0, 0.0, 0.0, 1.0)
-                )
-            ]
-        },
-        BufferUsage::uniform_buffer(),
-        queue.clone()
-    ).expect("Couldn't create palette buffer.");
+    let (mut palette_buffer, palette_future) = make_palette_buffer(palette_store.palettes(), queue.clone())?;
+    palette_store.take_dirty();
 
     // Make texture atlas.
-    // 2x2 textures, textures of size 8x8, texel of size 2 bits.
-    let mut texture_atlas = {
-        let mut texture_atlas = imagegen::TextureAtlas::new(ATLAS_SIZE, TILE_SIZE);
-
-        texture_atlas.generate_tile_tex(0, 0);
-        texture_atlas.generate_tile_tex(1, 0);
-        texture_atlas.generate_tile_tex(0, 1);
-        texture_atlas.generate_tile_tex(1, 1);
-
-        texture_atlas
+    // 2x2 textures of size 8x8 by default, texel of size 2 bits, filled with random textures below.
+    let (texture_atlas, random_textures) = if let Some(project) = saved {
+        (project.atlas, false)
+    } else if let Some(Level{ atlas, .. }) = level {
+        (atlas, false)
+    } else {
+        (imagegen::TextureAtlas::new(config.atlas_size, config.tile_size), true)
     };
 
+    // The scene that commands edit, with the edits that can be undone.
+    let mut scene = Scene::new(vertex_grid, texture_atlas, palette_store, HISTORY_BUDGET, PALETTE_COUNT, PALETTE_COLOURS);
+    if random_textures {
+        for y in 0..config.atlas_size {
+            for x in 0..config.atlas_size {
+                let colours = scene.generator_colours();
+                scene.atlas.generate_tile_tex(x, y, Generator::Random, colours, &mut rng);
+            }
+        }
+    }
+
     // Make sampler for texture.
     let sampler = Sampler::new(
         device.clone(),
@@ -242,7 +464,7 @@ fn main() {
         SamplerAddressMode::Repeat,
         SamplerAddressMode::Repeat,
         0.0, 1.0, 0.0, 0.0
-    ).expect("Couldn't create sampler!");
+    )?;
 
     // Make the render pass to insert into the command queue.
     let render_pass = Arc::new(vulkano::single_pass_renderpass!(device.clone(),
@@ -258,42 +480,36 @@ fn main() {
             color: [color],
             depth_stencil: {}
         }
-    ).unwrap());
-
-    // State that may change during pipeline execution (?)
-    let mut dynamic_state = DynamicState{
-        viewports: Some(vec![Viewport{
-            origin: [0.0, 0.0],
-            dimensions: [500.0, 500.0],
-            depth_range: 0.0 .. 1.0,
-        }]),
-        .. DynamicState::none()
-    };
+    )?);
 
-    // Make frame buffers from images (i.e. attach viewports).
-    let framebuffers = {
+    // The whole window.
+    let window = {
         let dimensions = images[0].dimensions();
-
-        let viewport = Viewport {
+        Viewport {
             origin: [0.0, 0.0],
             dimensions: [dimensions[0] as f32, dimensions[1] as f32],
             depth_range: 0.0 .. 1.0,
-        };
-
-        dynamic_state.viewports = Some(vec![viewport]);
+        }
+    };
 
-        images.iter().map(|image| {
-            Arc::new(
-                Framebuffer::start(render_pass.clone())
-                    .add(image.clone()).unwrap()
-                    .build().unwrap()
-            ) as Arc<FramebufferAbstract + Send + Sync>
-        }).collect::<Vec<_>>()
+    // State that may change during pipeline execution (?)
+    let mut dynamic_state = DynamicState{
+        viewports: Some(vec![window.clone()]),
+        .. DynamicState::none()
     };
 
+    // Make frame buffers from images (i.e. attach viewports).
+    let framebuffers = images.iter().map(|image| {
+        Ok(Arc::new(
+            Framebuffer::start(render_pass.clone())
+                .add(image.clone())?
+                .build()?
+        ) as Arc<FramebufferAbstract + Send + Sync>)
+    }).collect::<Result<Vec<_>, TileError>>()?;
+
     // Assemble
-    let vs = vs::Shader::load(device.clone()).expect("failed to create vertex shader");
-    let fs = fs::Shader::load(device.clone()).expect("failed to create fragment shader");
+    let vs = vs::Shader::load(device.clone())?;
+    let fs = fs::Shader::load(device.clone())?;
 
     // Make pipeline.
     let pipeline = Arc::new(GraphicsPipeline::start()
@@ -301,95 +517,537 @@ fn main() {
         .vertex_shader(vs.main_entry_point(), ())
         .viewports_dynamic_scissors_irrelevant(1)
         .fragment_shader(fs.main_entry_point(), ())
-        .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
-        .build(device.clone())
-        .unwrap());
+        .render_pass(Subpass::from(render_pass.clone(), 0).ok_or_else(|| TileError::Pipeline("the render pass has no subpass".to_string()))?)
+        .build(device.clone())?);
+
+    // Make image from the texture atlas.
+    let (mut image, image_future) = scene.atlas.make_image(queue.clone())?;
+    scene.atlas.take_dirty();
+
+    // Make the palette editor's swatch image.
+    let (mut palette_editor, swatch_future) = paletteeditor::PaletteEditor::new(
+        &device,
+        queue.clone(),
+        &window,
+        scene.palette_store.palette_size(),
+        scene.palettes()
+    )?;
 
-    // Make descriptor set pools.
-    let mut set_0_pool = FixedSizeDescriptorSetsPool::new(pipeline.clone(), 0);
-    let mut set_1_pool = FixedSizeDescriptorSetsPool::new(pipeline.clone(), 1);
+    // Make the atlas viewer's digit image.
+    let (mut atlas_viewer, digits_future) = atlasviewer::AtlasViewer::new(
+        &device,
+        queue.clone(),
+        &window,
+        scene.atlas.atlas_size(),
+        scene.palette_store.palette_size()
+    )?;
 
-    // Future foor previous frame completion.
-    let mut previous_frame_future = Box::new(now(device.clone()).join(palette_future)) as Box<GpuFuture>;
+    // Make the stats overlay's glyph image, and a palette buffer for its text colours.
+    let (mut stats_overlay, glyphs_future) = statsoverlay::StatsOverlay::new(&device, queue.clone(), &window)?;
+    let (overlay_palette_buffer, overlay_palette_future) = make_palette_buffer(&stats_overlay.palettes(), queue.clone())?;
+    let stats_overlay_state = DynamicState{
+        viewports: Some(vec![stats_overlay.viewport().clone()]),
+        .. DynamicState::none()
+    };
+
+    // Frames in flight, each with its own descriptor sets. The first frame waits for the initial uploads.
+    let mut frames = frames::FrameRing::new(&device, &pipeline, config.frames_in_flight, palette_future
+        .join(image_future)
+        .join(swatch_future)
+        .join(digits_future)
+        .join(glyphs_future)
+        .join(overlay_palette_future));
 
     // Initial command state.
-    let mut state = keystate::KeyState::new();
-
-    events_loop.run_forever(|event| {
-        // Get current framebuffer index from the swapchain.
-        let (image_num, acquire_future) = acquire_next_image(swapchain.clone(), None)
-            .expect("Didn't get next image");
-
-        // Make vertex buffer with current tex coords.
-        // TODO: investigate reducing data copies.
-        let vertex_buffer = vertex_grid.get_vertex_buffer();
-
-        // Make image with current texture.
-        // TODO: only re-create the image when the data has changed.
-        let (image, write_future) = texture_atlas.make_image(queue.clone());
-
-        // Make descriptor set to bind texture atlas.
-        let set0 = set_0_pool.next()
-            .add_sampled_image(image.clone(), sampler.clone()).unwrap()
-            .build().unwrap();
-
-        // Make descriptor set for palettes.
-        let set1 = set_1_pool.next()
-            .add_buffer(palette_buffer.clone()).unwrap()
-            .build().unwrap();
-        
-        // Make and submit command buffer using pipeline and current framebuffer.
-        let command_buffer = AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue_family).unwrap()
-            .begin_render_pass(framebuffers[image_num].clone(), false, vec![[1.0, 1.0, 1.0, 1.0].into()]).unwrap()
-            .draw(pipeline.clone(), &dynamic_state, vertex_buffer, (set0, set1), ()).unwrap()
-            .end_render_pass().unwrap()
-            .build().unwrap();
-
-        // Wait until previous frame is done.
-        let mut now_future = Box::new(now(device.clone())) as Box<GpuFuture>;
-        std::mem::swap(&mut previous_frame_future, &mut now_future);
-
-        // Wait until previous frame is done,
-        // _and_ the framebuffer has been acquired,
-        // _and_ the texture has been uploaded,
-        // _and_ the palettes have been uploaded.
-        let future = now_future.join(acquire_future)
-            .join(write_future)
-            .then_execute(queue.clone(), command_buffer).unwrap()                   // Run the commands (pipeline and render)
-            .then_swapchain_present(queue.clone(), swapchain.clone(), image_num)    // Present newly rendered image.
-            .then_signal_fence_and_flush();                                         // Signal done and flush the pipeline.
-
-        match future {
-            Ok(future) => previous_frame_future = Box::new(future) as Box<_>,
-            Err(e) => println!("Err: {:?}", e),
+    let mut state = keystate::KeyState::new(key_limits(&scene));
+    let mut title = state.status(&scene.palette_store);
+    surface.window().set_title(&title);
+    let mut mouse = mouse::MouseState::new();
+
+    // The part of the window covered by the grid.
+    let mut grid_viewport = window.clone();
+
+    // Zoomed view of the current texture, drawn over the grid while editing texels.
+    let mut texel_editor = texeleditor::TexelEditor::new(&device, &window, &scene.atlas);
+    let texel_editor_state = DynamicState{
+        viewports: Some(vec![texel_editor.viewport().clone()]),
+        .. DynamicState::none()
+    };
+
+    // Whether anything has changed since the last frame was drawn.
+    let mut needs_redraw = true;
+
+    // Clock for the fixed rate loop, which draws on every tick.
+    let mut clock = match config.loop_mode {
+        LoopMode::Fixed     => Some(TickClock::new(config.tick_rate)),
+        LoopMode::Redraw    => None
+    };
+    let mut stats = FrameStats::new();
+
+    // Where the time went in the last frame drawn, for the stats overlay, and the log of every frame.
+    let mut last_profile = FrameProfile::default();
+    let mut profile_log = config.stats_csv.as_ref().map(|path| ProfileLog::create(path)).transpose()?;
+
+    // Log of applied commands.
+    let mut recorder = config.record.as_ref().map(|path| Recorder::create(path, seed)).transpose()?;
+
+    // Number of times round the main loop. Commands are recorded against it.
+    let mut frame: u64 = 0;
+
+    let mut running = true;
+    while running {
+        if let Some(clock) = &mut clock {
+            stats.missed_ticks(clock.wait());
+            needs_redraw = true;
         }
 
-        match event {
-            Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
+        // Wait for an event if there is nothing to draw, then take every event that is waiting.
+        // Replays don't wait, so they play back as fast as frames can be drawn.
+        let mut events = Vec::new();
+        if !needs_redraw && replay.is_none() {
+            events_loop.run_forever(|event| {
+                events.push(event);
                 ControlFlow::Break
-            },
-            Event::WindowEvent {
-                // Handle keyboard input.
-                event: WindowEvent::KeyboardInput{
-                    input: KeyboardInput{
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(k),
+            });
+        }
+        events_loop.poll_events(|event| events.push(event));
+
+        // Pick up changed asset files.
+        for update in assets.poll() {
+            match update {
+                AssetUpdate::Level(level) => {
+                    scene.grid = level_grid(&device, &level.map);
+                    // A palette file takes priority over the map's palettes.
+                    if assets.palette_path().is_none() {
+                        scene.palette_store.set_palettes(level.map.palettes);
+                    }
+                    scene.atlas = level.atlas;
+                    texel_editor = texeleditor::TexelEditor::new(&device, &window, &scene.atlas);
+                    atlas_viewer.set_atlas_size(&device, scene.atlas.atlas_size());
+                    palette_editor.set_palettes(&device, scene.palettes());
+                    state.set_limits(key_limits(&scene));
+                    mouse.refresh(&layout(&grid_viewport, &scene, &texel_editor, &palette_editor, &atlas_viewer), &state);
+                    scene.history.clear();
+                    needs_redraw = true;
+                },
+                AssetUpdate::Colours(colours) => {
+                    scene.palette_store.set_colours(&colours);
+                    palette_editor.set_palettes(&device, scene.palettes());
+                    state.set_limits(key_limits(&scene));
+                    mouse.refresh(&layout(&grid_viewport, &scene, &texel_editor, &palette_editor, &atlas_viewer), &state);
+                    scene.history.clear();
+                    needs_redraw = true;
+                }
+            }
+        }
+
+        // Commands to apply this frame, from the replay or from key presses.
+        let mut commands = match &mut replay {
+            Some(r) => r.commands_for_frame(frame),
+            None => Vec::new()
+        };
+
+        for event in events {
+            match event {
+                Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
+                    running = false;
+                },
+                Event::WindowEvent {
+                    // Handle keyboard input.
+                    event: WindowEvent::KeyboardInput{
+                        input: KeyboardInput{
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(k),
+                            modifiers,
+                            .. },
                         .. },
-                    .. },
-                .. } => {
-                let (new_state, command) = state.process_key(k);
-                state = new_state;
-                if let Some(c) = command {
-                    use keystate::Command::*;
-                    match c {
-                        ModifyTilePalette{ palette: p, x, y }   => vertex_grid.set_tile_palette(x, y, p),
-                        ModifyTileTexture{ tex_x, tex_y, x, y } => vertex_grid.set_tile_texture(x, y, tex_x, tex_y),
-                        GenerateTexture{ tex_x: x, tex_y: y }   => texture_atlas.generate_tile_tex(x, y)
+                    .. } => {
+                    let command = state.process_key(&bindings, k, modifiers, &scene.palette_store);
+                    // The grid makes room for the atlas viewer while it is shown.
+                    grid_viewport = if state.viewing_atlas() { atlas_viewer.main_viewport() } else { window.clone() };
+                    dynamic_state.viewports = Some(vec![grid_viewport.clone()]);
+                    // An editor may have opened or closed.
+                    mouse.refresh(&layout(&grid_viewport, &scene, &texel_editor, &palette_editor, &atlas_viewer), &state);
+                    needs_redraw = true;
+                    // Key presses are ignored during a replay, so that it plays back as recorded.
+                    if replay.is_none() {
+                        commands.extend(command);
+                    }
+                },
+                Event::WindowEvent { event: WindowEvent::CursorMoved{ position, .. }, .. } => {
+                    let position = position.to_physical(surface.window().get_hidpi_factor());
+                    let (hover_changed, command) = mouse.moved(position.x, position.y, &layout(&grid_viewport, &scene, &texel_editor, &palette_editor, &atlas_viewer), &state);
+                    // Moving within a tile changes nothing, so only draw when the highlight moves.
+                    if hover_changed {
+                        needs_redraw = true;
                     }
+                    if replay.is_none() {
+                        commands.extend(command);
+                    }
+                },
+                Event::WindowEvent { event: WindowEvent::CursorLeft{ .. }, .. } => {
+                    mouse.left();
+                    needs_redraw = true;
+                },
+                Event::WindowEvent { event: WindowEvent::MouseInput{ state: button_state, button, modifiers, .. }, .. } => {
+                    let command = mouse.button(button, button_state, modifiers, &mut state);
+                    needs_redraw = true;
+                    if replay.is_none() {
+                        commands.extend(command);
+                    }
+                },
+                // The window was uncovered or resized.
+                Event::WindowEvent { event: WindowEvent::Refresh, .. } |
+                Event::WindowEvent { event: WindowEvent::Resized(_), .. } => {
+                    needs_redraw = true;
+                },
+                _ => ()
+            }
+        }
+
+        if !commands.is_empty() {
+            needs_redraw = true;
+        }
+
+        for c in commands {
+            if let Some(recorder) = &mut recorder {
+                if let Err(e) = recorder.record(frame, &c) {
+                    println!("Couldn't record command: {}", e);
+                }
+            }
+
+            // Replays don't touch files, so they stay deterministic and can't overwrite the user's project.
+            if replay.is_some() && c.uses_files() {
+                println!("Skipping '{}' during replay", c);
+                continue;
+            }
+
+            use tile_test::command::Command::*;
+            match c {
+                ExportImages                               => export_images(&scene.atlas, &scene.grid, &scene.palette_store),
+                SaveProject                                => save_project(&scene.atlas, &scene.grid, &scene.palette_store),
+                LoadProject                                => match Project::load(Path::new(PROJECT_PATH)) {
+                    Ok(project) => {
+                        // The grid is remade to match the size of the saved map and atlas, and the palettes keep the saved size.
+                        let palette_size_changed = project.palette_size != scene.palette_store.palette_size();
+                        scene.grid = vertexgrid::VertexGrid::new(&device, project.tilemap.width, project.tilemap.height, project.atlas.atlas_size());
+                        project.tilemap.apply(&mut scene.grid);
+                        scene.palette_store = project.palette_store();
+                        scene.atlas = project.atlas;
+                        texel_editor = texeleditor::TexelEditor::new(&device, &window, &scene.atlas);
+                        atlas_viewer.set_atlas_size(&device, scene.atlas.atlas_size());
+                        palette_editor.set_palettes(&device, scene.palettes());
+
+                        // The swatches and the atlas viewer's digits are drawn with colours that depend on the palette size.
+                        if palette_size_changed {
+                            let colours = scene.palette_store.palette_size();
+                            let remade = paletteeditor::PaletteEditor::new(&device, queue.clone(), &window, colours, scene.palettes())
+                                .and_then(|(editor, swatch_future)| {
+                                    let (viewer, digits_future) = atlasviewer::AtlasViewer::new(&device, queue.clone(), &window, scene.atlas.atlas_size(), colours)?;
+                                    Ok((editor, viewer, swatch_future.join(digits_future)))
+                                });
+                            match remade {
+                                Ok((editor, viewer, future)) => {
+                                    palette_editor = editor;
+                                    atlas_viewer = viewer;
+                                    frames.add_upload(future);
+                                },
+                                Err(e) => println!("Couldn't remake the palette editor: {}", e)
+                            }
+                        }
+                        state.set_limits(key_limits(&scene));
+                        mouse.refresh(&layout(&grid_viewport, &scene, &texel_editor, &palette_editor, &atlas_viewer), &state);
+                        scene.history.clear();
+                        println!("Loaded {}", PROJECT_PATH);
+                    },
+                    Err(e) => println!("Couldn't load {}: {}", PROJECT_PATH, e)
+                },
+                // Commands from a replay may not fit the scene.
+                c => match scene.apply(&c, &mut rng) {
+                    Ok(true) => {},
+                    Ok(false) => println!("Nothing to {}", c),
+                    Err(e) => println!("Skipping '{}': {}", c, e)
                 }
-                ControlFlow::Continue
-            },
-            _ => ControlFlow::Continue,
+            }
         }
-    });
-}
\ No newline at end of file
+
+        // Show the current selection and whatever the mouse is over in the title.
+        let new_title = match mouse.hover_info(&layout(&grid_viewport, &scene, &texel_editor, &palette_editor, &atlas_viewer)) {
+            Some(info) => format!("{} | {}", state.status(&scene.palette_store), info),
+            None => state.status(&scene.palette_store)
+        };
+        if new_title != title {
+            surface.window().set_title(&new_title);
+            title = new_title;
+        }
+
+        // Keep drawing frames until the replay is done.
+        if replay.as_ref().is_some_and(|r| r.is_finished()) {
+            println!("Replay finished");
+            replay = None;
+        } else if replay.is_some() {
+            needs_redraw = true;
+        }
+
+        // Draw a frame if anything has changed, or on every tick of the fixed rate loop.
+        // If anything fails, e.g. the swapchain is out of date, the frame is skipped.
+        if needs_redraw {
+            needs_redraw = false;
+            let mut profile = FrameProfile::new(frame);
+            let mut draw_frame = || -> Result<(), TileError> {
+                // Get current framebuffer index from the swapchain.
+                let (image_num, acquire_future) = profile::time(&mut profile.wait, || acquire_next_image(swapchain.clone(), None))?;
+
+                // Wait for the oldest frame in flight to finish with this slot's buffers.
+                let slot = profile::time(&mut profile.wait, || frames.begin())?;
+                let record_start = Instant::now();
+
+                // Make vertex buffer with current tex coords.
+                // TODO: investigate reducing data copies.
+                let vertex_buffer = profile::time(&mut profile.vertex, || scene.grid.get_vertex_buffer(slot))?;
+
+                // Remake image if the texture atlas has changed.
+                // If the upload fails it is tried again next frame.
+                if scene.atlas.take_dirty() {
+                    let (new_image, future) = profile::time(&mut profile.atlas, || scene.atlas.make_image(queue.clone()))
+                        .inspect_err(|_| scene.atlas.mark_dirty())?;
+                    profile.atlas_bytes = scene.atlas.textures.len();
+                    image = new_image;
+                    frames.add_upload(future);
+                }
+
+                // Remake palette buffer if the palettes have changed.
+                if scene.palette_store.take_dirty() {
+                    let (buffer, future) = profile::time(&mut profile.palette, || make_palette_buffer(scene.palette_store.palettes(), queue.clone()))
+                        .inspect_err(|_| scene.palette_store.mark_dirty())?;
+                    profile.palette_bytes = mem::size_of::<PaletteUniformBufferObject>();
+                    palette_buffer = buffer;
+                    frames.add_upload(future);
+                }
+
+                // Descriptor sets to bind the texture atlas and the palettes.
+                let set0 = frames.sets().image_set(&image, &sampler)?;
+                let set1 = frames.sets().palette_set(&palette_buffer)?;
+
+                // Make and submit command buffer using pipeline and current framebuffer.
+                let highlight = fs::ty::Highlight {
+                    hover: mouse.highlight(&layout(&grid_viewport, &scene, &texel_editor, &palette_editor, &atlas_viewer)),
+                    selection: [-1.0; 4]
+                };
+                let mut command_buffer = AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue_family)?
+                    .begin_render_pass(framebuffers[image_num].clone(), false, vec![[1.0, 1.0, 1.0, 1.0].into()])?
+                    .draw(pipeline.clone(), &dynamic_state, vertex_buffer, (set0.clone(), set1.clone()), highlight)?;
+
+                // Draw the atlas viewer beside the grid, with the current texture outlined.
+                if state.viewing_atlas() {
+                    atlas_viewer.show(state.palette());
+                    let atlas_viewport = atlas_viewer.viewport();
+                    let atlas_viewer_state = DynamicState{
+                        viewports: Some(vec![atlas_viewport.clone()]),
+                        .. DynamicState::none()
+                    };
+                    let (tex_x, tex_y) = state.texture();
+                    let highlight = fs::ty::Highlight {
+                        selection: mouse::cell_bounds(&atlas_viewport, atlas_viewer.atlas_size(), atlas_viewer.atlas_size(), tex_x, tex_y),
+                        .. highlight
+                    };
+                    let atlas_viewer_buffer = profile::time(&mut profile.vertex, || atlas_viewer.get_vertex_buffer(slot))?;
+                    command_buffer = command_buffer
+                        .draw(pipeline.clone(), &atlas_viewer_state, atlas_viewer_buffer, (set0.clone(), set1.clone()), highlight)?;
+
+                    let label_buffers = profile::time(&mut profile.vertex, || atlas_viewer.get_label_buffers(slot))?;
+                    if !label_buffers.is_empty() {
+                        let digits_set = frames.sets().image_set(&atlas_viewer.digits(), &sampler)?;
+                        for (label_viewport, label_buffer) in label_buffers {
+                            let label_state = DynamicState{
+                                viewports: Some(vec![label_viewport]),
+                                .. DynamicState::none()
+                            };
+                            command_buffer = command_buffer
+                                .draw(pipeline.clone(), &label_state, label_buffer, (digits_set.clone(), set1.clone()), highlight)?;
+                        }
+                    }
+                }
+
+                // Draw the texel editor over the grid.
+                if state.editing() {
+                    let (tex_x, tex_y) = state.texture();
+                    texel_editor.show(tex_x, tex_y, state.palette());
+                    let texel_editor_buffer = profile::time(&mut profile.vertex, || texel_editor.get_vertex_buffer(slot))?;
+                    command_buffer = command_buffer
+                        .draw(pipeline.clone(), &texel_editor_state, texel_editor_buffer, (set0, set1.clone()), highlight)?;
+                }
+
+                // Draw the palette editor over the grid, with the current colour outlined.
+                if state.editing_palettes() && !palette_editor.is_empty() {
+                    let swatch_set = frames.sets().image_set(&palette_editor.image(), &sampler)?;
+                    let palette_editor_state = DynamicState{
+                        viewports: Some(vec![palette_editor.viewport().clone()]),
+                        .. DynamicState::none()
+                    };
+                    let highlight = fs::ty::Highlight {
+                        selection: mouse::cell_bounds(palette_editor.viewport(), palette_editor.columns(), palette_editor.rows(), state.colour() as usize, state.palette() as usize),
+                        .. highlight
+                    };
+                    let palette_editor_buffer = profile::time(&mut profile.vertex, || palette_editor.get_vertex_buffer(slot))?;
+                    command_buffer = command_buffer
+                        .draw(pipeline.clone(), &palette_editor_state, palette_editor_buffer, (swatch_set, set1), highlight)?;
+                }
+
+                // Draw the stats for the last frame over everything, with its own glyphs and palette.
+                if state.viewing_stats() {
+                    stats_overlay.set_lines(last_profile.lines());
+                    let glyphs_set = frames.sets().image_set(&stats_overlay.glyphs(), &sampler)?;
+                    let overlay_palette_set = frames.sets().palette_set(&overlay_palette_buffer)?;
+                    let stats_overlay_buffer = profile::time(&mut profile.vertex, || stats_overlay.get_vertex_buffer(slot))?;
+                    let highlight = fs::ty::Highlight {
+                        hover: [-1.0; 4],
+                        selection: [-1.0; 4]
+                    };
+                    command_buffer = command_buffer
+                        .draw(pipeline.clone(), &stats_overlay_state, stats_overlay_buffer, (glyphs_set, overlay_palette_set), highlight)?;
+                }
+
+                let command_buffer = command_buffer
+                    .end_render_pass()?
+                    .build()?;
+                profile.record = record_start.elapsed() - (profile.vertex + profile.atlas + profile.palette);
+
+                // Run the commands once the framebuffer has been acquired and any new texture or palettes uploaded,
+                // then present the image.
+                profile::time(&mut profile.submit, || frames.submit(&queue, &swapchain, acquire_future, image_num, command_buffer))
+
+            };
+            let draw_start = Instant::now();
+            let result = draw_frame();
+            profile.total = draw_start.elapsed();
+            profile.vertex_bytes = scene.grid.take_uploaded_bytes()
+                + atlas_viewer.take_uploaded_bytes()
+                + texel_editor.take_uploaded_bytes()
+                + palette_editor.take_uploaded_bytes()
+                + stats_overlay.take_uploaded_bytes();
+            match result {
+                Ok(()) => {
+                    stats.frame(profile.total);
+                    if let Some(log) = &mut profile_log {
+                        if let Err(e) = log.write(&profile) {
+                            println!("Couldn't write frame stats: {}", e);
+                        }
+                    }
+                    last_profile = profile;
+                },
+                Err(e) => println!("{}", e)
+            }
+        }
+
+        if config.frame_stats && (stats.elapsed() >= STATS_INTERVAL) {
+            println!("{}", stats);
+            stats.reset();
+        }
+
+        frame += 1;
+    }
+
+    if config.frame_stats {
+        println!("{}", stats);
+    }
+
+    Ok(())
+}
+
+// Print a startup error and exit.
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1)
+}
+
+// Size of a window showing an image at a scale. The scale is reduced until the window fits on the monitor.
+fn window_size(width: usize, height: usize, scale: u32, monitor: LogicalSize) -> LogicalSize {
+    let (width, height) = (width as f64, height as f64);
+    let fit = (monitor.width / width).min(monitor.height / height).floor().max(1.0);
+    let scale = f64::from(scale).min(fit);
+    LogicalSize::new(width * scale, height * scale)
+}
+
+// Make a grid the size of a map, with all visible layers combined.
+// Tiles with palettes the shader doesn't hold are drawn with palette 0.
+fn level_grid(device: &Arc<Device>, map: &TiledMap) -> vertexgrid::VertexGrid {
+    let mut vertex_grid = vertexgrid::VertexGrid::new(device, map.width, map.height, LEVEL_ATLAS_SIZE);
+    let mut tilemap = map.flatten();
+    let clamped = tilemap.clamp_palettes(PALETTE_COUNT);
+    if clamped > 0 {
+        println!("{} tiles use palettes past the first {}: they are drawn with palette 0.", clamped, PALETTE_COUNT);
+    }
+    tilemap.apply(&mut vertex_grid);
+    vertex_grid
+}
+
+// Sizes of the grid, atlas and palettes that can be selected with keys.
+// Only the palettes and colours the shader can show are selectable.
+fn key_limits(scene: &Scene<vertexgrid::VertexGrid>) -> keystate::Limits {
+    keystate::Limits {
+        grid_width: scene.grid.width(),
+        grid_height: scene.grid.height(),
+        atlas_size: scene.atlas.atlas_size(),
+        palettes: scene.palettes(),
+        colours: scene.colours()
+    }
+}
+
+// What the mouse can point at: the grid in its viewport, and the editors and viewer drawn over or beside it.
+fn layout<'a>(viewport: &'a Viewport, scene: &'a Scene<vertexgrid::VertexGrid>, texel_editor: &'a texeleditor::TexelEditor, palette_editor: &'a paletteeditor::PaletteEditor, atlas_viewer: &'a atlasviewer::AtlasViewer) -> mouse::Layout<'a> {
+    mouse::Layout {
+        viewport: viewport,
+        vertex_grid: &scene.grid,
+        texel_editor: texel_editor,
+        palette_editor: palette_editor,
+        atlas_viewer: atlas_viewer
+    }
+}
+
+// A palette buffer, and the future for uploading it.
+type PaletteUpload = (Arc<ImmutableBuffer<PaletteUniformBufferObject>>, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>);
+
+// Make a uniform buffer containing the palettes.
+fn make_palette_buffer(palettes: &[Palette], queue: Arc<Queue>) -> Result<PaletteUpload, TileError> {
+    let buffer = ImmutableBuffer::from_data(
+        PaletteUniformBufferObject::new(palettes),
+        BufferUsage::uniform_buffer(),
+        queue
+    )?;
+    Ok(buffer)
+}
+
+// Dump the atlas, palettes and rendered tilemap to PNG files in the working directory.
+fn export_images(texture_atlas: &imagegen::TextureAtlas, vertex_grid: &vertexgrid::VertexGrid, palette_store: &PaletteStore) {
+    let palettes = palette_store.palettes();
+    let tilemap = TileMap::from_vertex_grid(vertex_grid);
+    let results = [
+        ("atlas.png", export::export_atlas(Path::new("atlas.png"), texture_atlas, None)),
+        ("palettes.png", export::export_palettes(Path::new("palettes.png"), palettes, 8)),
+        ("tilemap.png", export::export_tilemap(Path::new("tilemap.png"), &tilemap, texture_atlas, palettes))
+    ];
+
+    for (name, result) in results.iter() {
+        match result {
+            Ok(_) => println!("Exported {}", name),
+            Err(e) => println!("Couldn't export {}: {}", name, e)
+        }
+    }
+
+    match palette_store.save_file(Path::new("palettes.pal"), PaletteFormat::Jasc) {
+        Ok(_) => println!("Exported palettes.pal"),
+        Err(e) => println!("Couldn't export palettes.pal: {}", e)
+    }
+}
+
+// Save the scene in both the binary and JSON project formats.
+fn save_project(texture_atlas: &imagegen::TextureAtlas, vertex_grid: &vertexgrid::VertexGrid, palette_store: &PaletteStore) {
+    let project = Project::capture(texture_atlas, vertex_grid, palette_store);
+    for (name, format) in [(PROJECT_PATH, ProjectFormat::Binary), (PROJECT_JSON_PATH, ProjectFormat::Json)].iter() {
+        match project.save(Path::new(name), *format) {
+            Ok(_) => println!("Saved {}", name),
+            Err(e) => println!("Couldn't save {}: {}", name, e)
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use winit::{
+    ElementState,
+    ModifiersState,
+    MouseButton
+};
+
+use vulkano::pipeline::viewport::Viewport;
+
+use tile_test::{
+    command::Command,
+    vertexgrid::{
+        AtlasSlot,
+        VertexGrid
+    }
+};
+
+use super::{
+    atlasviewer::AtlasViewer,
+    keystate::KeyState,
+    paletteeditor::PaletteEditor,
+    texeleditor::TexelEditor
+};
+
+// Everything the pointer can be over.
+pub struct Layout<'a> {
+    // Viewport covered by the grid.
+    pub viewport: &'a Viewport,
+    pub vertex_grid: &'a VertexGrid,
+    pub texel_editor: &'a TexelEditor,
+    pub palette_editor: &'a PaletteEditor,
+    pub atlas_viewer: &'a AtlasViewer
+}
+
+// What the pointer is over.
+#[derive(Copy, Clone, PartialEq)]
+enum Hover {
+    Tile(usize, usize),
+    // A texel in the texel editor.
+    Texel(usize, usize),
+    // A colour in the palette editor, as (index, palette).
+    Swatch(usize, usize),
+    // A texture in the atlas viewer.
+    Slot(usize, usize)
+}
+
+// What a drag paints.
+#[derive(Copy, Clone)]
+enum Paint {
+    Texture,
+    Palette,
+    Texel
+}
+
+// Tracks the mouse over the tile grid and editors.
+// A left click selects the tile under the pointer. Dragging with the right button paints the current texture onto tiles,
+// or the current palette while shift is held. In the texel editor, dragging with the left button paints texels.
+// In the palette editor, a left click selects a colour, and in the atlas viewer it makes a texture current.
+pub struct MouseState {
+    // Pointer position in physical pixels, while it is over the window.
+    position: Option<(f64, f64)>,
+    hovered: Option<Hover>,
+    painting: Option<Paint>
+}
+
+impl MouseState {
+    pub fn new() -> Self {
+        MouseState {
+            position: None,
+            hovered: None,
+            painting: None
+        }
+    }
+
+    // The pointer moved to a position in physical pixels.
+    // Returns true if something different is hovered, with a command if a drag entered it.
+    pub fn moved(&mut self, x: f64, y: f64, layout: &Layout, keys: &KeyState) -> (bool, Option<Command>) {
+        self.position = Some((x, y));
+        let hovered = pick(layout, keys, x, y);
+        if hovered == self.hovered {
+            return (false, None);
+        }
+        self.hovered = hovered;
+        (true, self.paint(keys))
+    }
+
+    // The pointer left the window. A drag ends, since the button release may not be seen.
+    pub fn left(&mut self) {
+        self.position = None;
+        self.hovered = None;
+        self.painting = None;
+    }
+
+    // Pick what is hovered again, e.g. after the grid is replaced or an editor is opened.
+    pub fn refresh(&mut self, layout: &Layout, keys: &KeyState) {
+        self.hovered = self.position.and_then(|(x, y)| pick(layout, keys, x, y));
+    }
+
+    pub fn button(&mut self, button: MouseButton, state: ElementState, modifiers: ModifiersState, keys: &mut KeyState) -> Option<Command> {
+        match (button, state, self.hovered) {
+            (MouseButton::Left, ElementState::Pressed, Some(Hover::Tile(x, y))) => {
+                keys.select_tile(x, y);
+                None
+            },
+            (MouseButton::Left, ElementState::Pressed, Some(Hover::Texel(..))) => {
+                self.painting = Some(Paint::Texel);
+                self.paint(keys)
+            },
+            (MouseButton::Left, ElementState::Pressed, Some(Hover::Swatch(index, palette))) => {
+                keys.select_colour(palette, index);
+                None
+            },
+            (MouseButton::Left, ElementState::Pressed, Some(Hover::Slot(tex_x, tex_y))) => {
+                keys.select_texture(tex_x, tex_y);
+                None
+            },
+            (MouseButton::Right, ElementState::Pressed, _)  => {
+                self.painting = Some(if modifiers.shift { Paint::Palette } else { Paint::Texture });
+                self.paint(keys)
+            },
+            (MouseButton::Left, ElementState::Released, _) |
+            (MouseButton::Right, ElementState::Released, _) => {
+                self.painting = None;
+                None
+            },
+            _ => None
+        }
+    }
+
+    // Bounds of whatever is hovered in framebuffer pixels, as (left, top, right, bottom).
+    // If nothing is hovered the bounds are off screen.
+    pub fn highlight(&self, layout: &Layout) -> [f32; 4] {
+        let texel_editor = layout.texel_editor;
+        let palette_editor = layout.palette_editor;
+        match self.hovered {
+            Some(Hover::Tile(x, y))         => cell_bounds(layout.viewport, layout.vertex_grid.width(), layout.vertex_grid.height(), x, y),
+            Some(Hover::Texel(x, y))        => cell_bounds(texel_editor.viewport(), texel_editor.tex_size(), texel_editor.tex_size(), x, y),
+            Some(Hover::Swatch(x, y))       => cell_bounds(palette_editor.viewport(), palette_editor.columns(), palette_editor.rows(), x, y),
+            Some(Hover::Slot(x, y))         => cell_bounds(&layout.atlas_viewer.viewport(), layout.atlas_viewer.atlas_size(), layout.atlas_viewer.atlas_size(), x, y),
+            None                            => [-1.0; 4]
+        }
+    }
+
+    // Describe whatever is hovered, e.g. the texture and palette of a grid tile.
+    pub fn hover_info(&self, layout: &Layout) -> Option<String> {
+        let atlas_size = layout.atlas_viewer.atlas_size();
+        let info = match self.hovered? {
+            Hover::Tile(x, y)       => {
+                let (tex_x, tex_y) = layout.vertex_grid.tile_texture(x, y);
+                let flip = layout.vertex_grid.tile_flip(x, y);
+                let flips = [(flip.x, " x"), (flip.y, " y"), (flip.diagonal, " diagonal")].iter()
+                    .filter(|(f, _)| *f)
+                    .map(|(_, name)| *name)
+                    .collect::<String>();
+                format!("Tile ({}, {}): texture {} ({}, {}), palette {}{}",
+                    x, y, AtlasSlot::new(tex_x, tex_y).index(atlas_size), tex_x, tex_y, layout.vertex_grid.tile_palette(x, y),
+                    if flips.is_empty() { String::new() } else { format!(", flipped{}", flips) })
+            },
+            Hover::Texel(x, y)      => format!("Texel ({}, {})", x, y),
+            Hover::Swatch(x, y)     => format!("Palette {} colour {}", y, x),
+            Hover::Slot(x, y)       => format!("Slot {} ({}, {})", AtlasSlot::new(x, y).index(atlas_size), x, y)
+        };
+        Some(info)
+    }
+
+    fn paint(&self, keys: &KeyState) -> Option<Command> {
+        match (self.hovered?, self.painting?) {
+            (Hover::Tile(x, y), Paint::Texture)     => keys.paint_texture(x, y),
+            (Hover::Tile(x, y), Paint::Palette)     => keys.paint_palette(x, y),
+            (Hover::Texel(x, y), Paint::Texel)      => keys.paint_texel(x, y),
+            _ => None
+        }
+    }
+}
+
+// Bounds of a cell in a viewport divided into columns and rows, in framebuffer pixels.
+pub fn cell_bounds(viewport: &Viewport, columns: usize, rows: usize, x: usize, y: usize) -> [f32; 4] {
+    let width = viewport.dimensions[0] / columns as f32;
+    let height = viewport.dimensions[1] / rows as f32;
+    let left = viewport.origin[0] + x as f32 * width;
+    let top = viewport.origin[1] + y as f32 * height;
+    [left, top, left + width, top + height]
+}
+
+// Get the cell under a position in physical pixels, in a viewport divided into columns and rows.
+fn cell_at(viewport: &Viewport, columns: usize, rows: usize, x: f64, y: f64) -> Option<(usize, usize)> {
+    let (ndc_x, ndc_y) = to_ndc(viewport, x, y);
+    if (columns == 0) || (rows == 0) || !(-1.0..1.0).contains(&ndc_x) || !(-1.0..1.0).contains(&ndc_y) {
+        return None;
+    }
+    let cell_x = (((ndc_x + 1.0) / 2.0 * columns as f32) as usize).min(columns - 1);
+    let cell_y = (((ndc_y + 1.0) / 2.0 * rows as f32) as usize).min(rows - 1);
+    Some((cell_x, cell_y))
+}
+
+// Get what is under a position in physical pixels. Open editors are on top of the grid.
+fn pick(layout: &Layout, keys: &KeyState, x: f64, y: f64) -> Option<Hover> {
+    let texel_editor = layout.texel_editor;
+    let palette_editor = layout.palette_editor;
+    let texel = keys.editing()
+        .then(|| cell_at(texel_editor.viewport(), texel_editor.tex_size(), texel_editor.tex_size(), x, y))
+        .flatten()
+        .map(|(x, y)| Hover::Texel(x, y));
+    let swatch = keys.editing_palettes()
+        .then(|| cell_at(palette_editor.viewport(), palette_editor.columns(), palette_editor.rows(), x, y))
+        .flatten()
+        .map(|(x, y)| Hover::Swatch(x, y));
+    let atlas_size = layout.atlas_viewer.atlas_size();
+    let slot = keys.viewing_atlas()
+        .then(|| cell_at(&layout.atlas_viewer.viewport(), atlas_size, atlas_size, x, y))
+        .flatten()
+        .map(|(x, y)| Hover::Slot(x, y));
+
+    texel.or(swatch).or(slot).or_else(|| {
+        let (ndc_x, ndc_y) = to_ndc(layout.viewport, x, y);
+        layout.vertex_grid.tile_at(ndc_x, ndc_y).map(|(x, y)| Hover::Tile(x, y))
+    })
+}
+
+// Convert a position in physical pixels to normalised device coordinates in a viewport.
+fn to_ndc(viewport: &Viewport, x: f64, y: f64) -> (f32, f32) {
+    (
+        (x as f32 - viewport.origin[0]) / viewport.dimensions[0] * 2.0 - 1.0,
+        (y as f32 - viewport.origin[1]) / viewport.dimensions[1] * 2.0 - 1.0
+    )
+}
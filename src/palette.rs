@@ -0,0 +1,185 @@
+// Palette colours, and the store of palettes used for rendering.
+use super::error::TileError;
+
+use cgmath::Vector4;
+
+// RGBA colour, with each channel in the range 0.0 to 1.0.
+pub type Colour = Vector4<f32>;
+
+// A list of colours, indexed by texel value.
+pub type Palette = Vec<Colour>;
+
+// Make a colour from 8-bit RGBA channels.
+pub fn from_rgba8(rgba: [u8; 4]) -> Colour {
+    Vector4::new(
+        f32::from(rgba[0]) / 255.0,
+        f32::from(rgba[1]) / 255.0,
+        f32::from(rgba[2]) / 255.0,
+        f32::from(rgba[3]) / 255.0
+    )
+}
+
+// Convert a colour to 8-bit RGBA channels.
+pub fn to_rgba8(colour: Colour) -> [u8; 4] {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [channel(colour.x), channel(colour.y), channel(colour.z), channel(colour.w)]
+}
+
+// 15-bit console colour conversion.
+// BGR555 (used by the SNES, GBA and GBC) stores red in the low bits: 0bbbbbgggggrrrrr.
+// RGB555 stores blue in the low bits: 0rrrrrgggggbbbbb.
+
+fn from_5bit(r: u16, g: u16, b: u16) -> Colour {
+    Vector4::new(f32::from(r) / 31.0, f32::from(g) / 31.0, f32::from(b) / 31.0, 1.0)
+}
+
+fn to_5bit(colour: Colour) -> (u16, u16, u16) {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 31.0).round() as u16;
+    (channel(colour.x), channel(colour.y), channel(colour.z))
+}
+
+pub fn from_bgr555(c: u16) -> Colour {
+    from_5bit(c & 0x1F, (c >> 5) & 0x1F, (c >> 10) & 0x1F)
+}
+
+pub fn to_bgr555(colour: Colour) -> u16 {
+    let (r, g, b) = to_5bit(colour);
+    r | (g << 5) | (b << 10)
+}
+
+pub fn from_rgb555(c: u16) -> Colour {
+    from_5bit((c >> 10) & 0x1F, (c >> 5) & 0x1F, c & 0x1F)
+}
+
+pub fn to_rgb555(colour: Colour) -> u16 {
+    let (r, g, b) = to_5bit(colour);
+    (r << 10) | (g << 5) | b
+}
+
+// Round a colour to the nearest colour representable in 15 bits. Alpha is kept.
+pub fn quantise_15bit(colour: Colour) -> Colour {
+    let mut quantised = from_bgr555(to_bgr555(colour));
+    quantised.w = colour.w;
+    quantised
+}
+
+// Palettes used for rendering. Every palette has the same number of colours.
+pub struct PaletteStore {
+    palettes: Vec<Palette>,
+    palette_size: usize,
+    dirty: bool
+}
+
+impl PaletteStore {
+    // Palette size: number of colours in each palette.
+    pub fn new(palette_size: usize) -> Self {
+        PaletteStore {
+            palettes: Vec::new(),
+            palette_size: palette_size,
+            dirty: true
+        }
+    }
+
+    // Make a store from palettes. Palettes are padded or truncated to the palette size.
+    pub fn from_palettes(palettes: Vec<Palette>, palette_size: usize) -> Self {
+        let mut store = PaletteStore::new(palette_size);
+        store.set_palettes(palettes);
+        store
+    }
+
+    pub fn palette_size(&self) -> usize {
+        self.palette_size
+    }
+
+    pub fn len(&self) -> usize {
+        self.palettes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.palettes.is_empty()
+    }
+
+    pub fn palettes(&self) -> &[Palette] {
+        &self.palettes
+    }
+
+    pub fn palette(&self, index: usize) -> Option<&Palette> {
+        self.palettes.get(index)
+    }
+
+    // Replace all palettes. Palettes are padded or truncated to the palette size.
+    pub fn set_palettes(&mut self, palettes: Vec<Palette>) {
+        self.palettes = palettes;
+        for p in self.palettes.iter_mut() {
+            p.resize(self.palette_size, Vector4::new(0.0, 0.0, 0.0, 1.0));
+        }
+        self.dirty = true;
+    }
+
+    // Replace a palette, or add it if the index is one past the end.
+    pub fn set_palette(&mut self, index: usize, mut palette: Palette) -> Result<(), TileError> {
+        palette.resize(self.palette_size, Vector4::new(0.0, 0.0, 0.0, 1.0));
+        let palettes = self.palettes.len();
+        if index == palettes {
+            self.palettes.push(palette);
+        } else {
+            *self.palettes.get_mut(index).ok_or(TileError::PaletteOutOfRange{ palette: index, palettes: palettes })? = palette;
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    // Get a colour, or None if the palette or colour doesn't exist.
+    pub fn colour(&self, palette: usize, index: usize) -> Option<Colour> {
+        self.palettes.get(palette).and_then(|p| p.get(index)).cloned()
+    }
+
+    pub fn set_colour(&mut self, palette: usize, index: usize, colour: Colour) -> Result<(), TileError> {
+        let palettes = self.palettes.len();
+        let p = self.palettes.get_mut(palette).ok_or(TileError::PaletteOutOfRange{ palette: palette, palettes: palettes })?;
+        let colours = p.len();
+        *p.get_mut(index).ok_or(TileError::ColourOutOfRange{ index: index, colours: colours })? = colour;
+        self.dirty = true;
+        Ok(())
+    }
+
+    // All colours, palette by palette.
+    pub fn colours(&self) -> Vec<Colour> {
+        self.palettes.iter().flatten().cloned().collect()
+    }
+
+    // Replace all palettes with a flat list of colours, split into palettes.
+    // The last palette is padded if the colours don't divide evenly.
+    pub fn set_colours(&mut self, colours: &[Colour]) {
+        let size = self.palette_size.max(1);
+        self.set_palettes(colours.chunks(size).map(|c| c.to_vec()).collect());
+    }
+
+    // Flag the palettes as changed, so they are uploaded again.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    // Returns true if the palettes have changed since the last call.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_range_colours() {
+        let mut store = PaletteStore::new(2);
+        assert!(store.set_palette(0, vec![Vector4::new(1.0, 0.0, 0.0, 1.0)]).is_ok());
+        assert!(store.set_palette(2, Vec::new()).is_err());
+        assert_eq!(store.colour(0, 0), Some(Vector4::new(1.0, 0.0, 0.0, 1.0)));
+        assert_eq!(store.colour(0, 2), None);
+        assert_eq!(store.colour(1, 0), None);
+        assert!(store.set_colour(0, 2, Vector4::new(0.0, 0.0, 0.0, 1.0)).is_err());
+        assert!(store.set_colour(1, 0, Vector4::new(0.0, 0.0, 0.0, 1.0)).is_err());
+        assert!(store.set_colour(0, 1, Vector4::new(0.0, 0.0, 0.0, 1.0)).is_ok());
+    }
+}
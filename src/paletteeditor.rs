@@ -0,0 +1,112 @@
+use vulkano::{
+    buffer::cpu_pool::CpuBufferPoolChunk,
+    command_buffer::{
+        AutoCommandBuffer,
+        CommandBufferExecFuture
+    },
+    device::{
+        Device,
+        Queue
+    },
+    format::R8Uint,
+    image::immutable::ImmutableImage,
+    memory::pool::StdMemoryPool,
+    pipeline::viewport::Viewport,
+    sync::NowFuture
+};
+
+use tile_test::{
+    error::TileError,
+    imagegen::TextureAtlas,
+    vertexgrid::VertexGrid,
+    Vertex
+};
+
+use std::sync::Arc;
+
+// Panel of swatches along the bottom left of the window, one row per palette and one column per colour.
+// Each swatch is a tile drawn with the tile pipeline, using a small atlas of one texel textures where texture i is colour i.
+pub struct PaletteEditor {
+    vertex_grid: VertexGrid,
+    viewport: Viewport,
+    // Viewport of the whole window.
+    window: Viewport,
+    image: Arc<ImmutableImage<R8Uint>>,
+    palette_size: usize
+}
+
+impl PaletteEditor {
+    pub fn new(device: &Arc<Device>, queue: Arc<Queue>, window: &Viewport, palette_size: usize, palettes: usize) -> Result<(Self, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>), TileError> {
+        let mut swatches = TextureAtlas::new(palette_size, 1);
+        for i in 0..palette_size {
+            swatches.set_texel(i, 0, 0, 0, i as u8);
+        }
+        let (image, future) = swatches.make_image(queue)?;
+
+        let mut editor = PaletteEditor {
+            vertex_grid: VertexGrid::new(device, 0, 0, palette_size),
+            viewport: window.clone(),
+            window: window.clone(),
+            image: image,
+            palette_size: palette_size
+        };
+        editor.set_palettes(device, palettes);
+        Ok((editor, future))
+    }
+
+    // Resize the panel for a number of palettes.
+    pub fn set_palettes(&mut self, device: &Arc<Device>, palettes: usize) {
+        if palettes == self.rows() {
+            return;
+        }
+
+        self.vertex_grid = VertexGrid::new(device, self.palette_size, palettes, self.palette_size);
+        for palette in 0..palettes {
+            for colour in 0..self.palette_size {
+                self.vertex_grid.set_tile_texture(colour, palette, colour, 0);
+                self.vertex_grid.set_tile_palette(colour, palette, palette as u32);
+            }
+        }
+
+        let swatch = self.window.dimensions[0].min(self.window.dimensions[1]) / 2.0 / self.palette_size as f32;
+        let height = swatch * palettes as f32;
+        self.viewport = Viewport {
+            origin: [self.window.origin[0], self.window.origin[1] + self.window.dimensions[1] - height],
+            dimensions: [swatch * self.palette_size as f32, height],
+            depth_range: self.window.depth_range.clone()
+        };
+    }
+
+    pub fn viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    // Colours in each palette.
+    pub fn columns(&self) -> usize {
+        self.palette_size
+    }
+
+    // Palettes shown.
+    pub fn rows(&self) -> usize {
+        self.vertex_grid.height()
+    }
+
+    // Returns true if there are no palettes to show.
+    pub fn is_empty(&self) -> bool {
+        self.rows() == 0
+    }
+
+    // Swatch texture atlas.
+    pub fn image(&self) -> Arc<ImmutableImage<R8Uint>> {
+        self.image.clone()
+    }
+
+    pub fn get_vertex_buffer(&mut self, frame: usize) -> Result<CpuBufferPoolChunk<Vertex, Arc<StdMemoryPool>>, TileError> {
+        self.vertex_grid.get_vertex_buffer(frame)
+    }
+
+    // Bytes of vertex data uploaded since the last call.
+    pub fn take_uploaded_bytes(&mut self) -> usize {
+        self.vertex_grid.take_uploaded_bytes()
+    }
+}
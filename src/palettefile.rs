@@ -0,0 +1,323 @@
+// Read and write palette files: JASC-PAL, GIMP GPL, Adobe ACT, RIFF PAL and raw 15-bit console colour dumps.
+use super::palette::{
+    self,
+    Colour,
+    PaletteStore
+};
+
+use std::{
+    fmt,
+    fs,
+    io,
+    path::Path
+};
+
+const ACT_COLOURS: usize = 256;
+const ACT_NO_TRANSPARENCY: u16 = 0xFFFF;
+const RIFF_PALETTE_VERSION: u16 = 0x0300;
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(io::Error),
+    Format(String)
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::Io(e)     => write!(f, "IO error: {}", e),
+            PaletteError::Format(s) => write!(f, "Invalid palette file: {}", s)
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl From<io::Error> for PaletteError {
+    fn from(e: io::Error) -> Self {
+        PaletteError::Io(e)
+    }
+}
+
+fn format_err<T>(msg: &str) -> Result<T, PaletteError> {
+    Err(PaletteError::Format(msg.to_string()))
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PaletteFormat {
+    // Paint Shop Pro text palette.
+    Jasc,
+    // GIMP text palette.
+    Gimp,
+    // Adobe colour table: 256 RGB triplets, with an optional colour count and transparent index.
+    Act,
+    // Microsoft RIFF palette: a "PAL " form with a data chunk of RGB and flag bytes.
+    Riff,
+    // Little-endian 16-bit colours, red in the low bits.
+    Bgr555,
+    // Little-endian 16-bit colours, blue in the low bits.
+    Rgb555
+}
+
+impl PaletteFormat {
+    // Guess the format from the file contents and extension.
+    // An unrecognised .pal is read as JASC so that it fails on the header, rather than being decoded as raw colours.
+    // Other unrecognised data is assumed to be BGR555, the most common console format.
+    pub fn detect(path: &Path, data: &[u8]) -> Self {
+        let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+        if data.starts_with(b"JASC-PAL") {
+            PaletteFormat::Jasc
+        } else if data.starts_with(b"GIMP Palette") {
+            PaletteFormat::Gimp
+        } else if is_riff_palette(data) {
+            PaletteFormat::Riff
+        } else if extension.as_deref() == Some("pal") {
+            PaletteFormat::Jasc
+        } else if extension.as_deref() == Some("act") {
+            PaletteFormat::Act
+        } else if extension.as_deref() == Some("rgb555") {
+            PaletteFormat::Rgb555
+        } else {
+            PaletteFormat::Bgr555
+        }
+    }
+}
+
+// Returns true if the file extension is one used for palette files.
+pub fn is_palette_file(path: &Path) -> bool {
+    const EXTENSIONS: [&str; 5] = ["pal", "gpl", "act", "bgr555", "rgb555"];
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| EXTENSIONS.iter().any(|x| e.eq_ignore_ascii_case(x)))
+}
+
+// Parse colours from palette file data.
+pub fn read_colours(data: &[u8], format: PaletteFormat) -> Result<Vec<Colour>, PaletteError> {
+    match format {
+        PaletteFormat::Jasc => read_jasc(data),
+        PaletteFormat::Gimp => read_gimp(data),
+        PaletteFormat::Act => read_act(data),
+        PaletteFormat::Riff => read_riff(data),
+        PaletteFormat::Bgr555 => read_raw(data, palette::from_bgr555),
+        PaletteFormat::Rgb555 => read_raw(data, palette::from_rgb555)
+    }
+}
+
+// Write colours as palette file data.
+// Text, ACT and RIFF formats drop alpha. 15-bit formats drop alpha and round each channel to 5 bits.
+pub fn write_colours(colours: &[Colour], format: PaletteFormat) -> Vec<u8> {
+    match format {
+        PaletteFormat::Jasc => write_jasc(colours),
+        PaletteFormat::Gimp => write_gimp(colours),
+        PaletteFormat::Act => write_act(colours),
+        PaletteFormat::Riff => write_riff(colours),
+        PaletteFormat::Bgr555 => write_raw(colours, palette::to_bgr555),
+        PaletteFormat::Rgb555 => write_raw(colours, palette::to_rgb555)
+    }
+}
+
+impl PaletteStore {
+    // Replace all palettes with the colours in a file.
+    // If no format is given, it is detected from the file.
+    pub fn load_file(&mut self, path: &Path, format: Option<PaletteFormat>) -> Result<(), PaletteError> {
+        let data = fs::read(path)?;
+        let format = format.unwrap_or_else(|| PaletteFormat::detect(path, &data));
+        let colours = read_colours(&data, format)?;
+        self.set_colours(&colours);
+        Ok(())
+    }
+
+    // Write all palettes to a file, one after another.
+    pub fn save_file(&self, path: &Path, format: PaletteFormat) -> Result<(), PaletteError> {
+        fs::write(path, write_colours(&self.colours(), format))?;
+        Ok(())
+    }
+}
+
+fn text_lines(data: &[u8]) -> Result<std::str::Lines<'_>, PaletteError> {
+    std::str::from_utf8(data)
+        .map(|s| s.lines())
+        .or_else(|_| format_err("text palette is not valid UTF-8"))
+}
+
+// Parse "r g b" channels at the start of a line.
+fn parse_rgb(line: &str) -> Result<Colour, PaletteError> {
+    let channels = line.split_whitespace()
+        .take(3)
+        .map(|c| c.parse::<u8>())
+        .collect::<Result<Vec<_>, _>>()
+        .or_else(|_| format_err(&format!("bad colour '{}'", line)))?;
+
+    match channels.as_slice() {
+        [r, g, b] => Ok(palette::from_rgba8([*r, *g, *b, 255])),
+        _ => format_err(&format!("bad colour '{}'", line))
+    }
+}
+
+fn read_jasc(data: &[u8]) -> Result<Vec<Colour>, PaletteError> {
+    let mut lines = text_lines(data)?.map(|l| l.trim());
+    if lines.next() != Some("JASC-PAL") {
+        return format_err("missing JASC-PAL header");
+    }
+    // Version.
+    lines.next();
+    let count = lines.next()
+        .and_then(|l| l.parse::<usize>().ok())
+        .ok_or_else(|| PaletteError::Format("bad colour count".to_string()))?;
+
+    let colours = lines.filter(|l| !l.is_empty())
+        .take(count)
+        .map(parse_rgb)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if colours.len() < count {
+        return format_err(&format!("expected {} colours, found {}", count, colours.len()));
+    }
+    Ok(colours)
+}
+
+fn write_jasc(colours: &[Colour]) -> Vec<u8> {
+    let mut text = format!("JASC-PAL\r\n0100\r\n{}\r\n", colours.len());
+    for c in colours.iter() {
+        let [r, g, b, _] = palette::to_rgba8(*c);
+        text.push_str(&format!("{} {} {}\r\n", r, g, b));
+    }
+    text.into_bytes()
+}
+
+fn read_gimp(data: &[u8]) -> Result<Vec<Colour>, PaletteError> {
+    let mut lines = text_lines(data)?.map(|l| l.trim());
+    if lines.next() != Some("GIMP Palette") {
+        return format_err("missing GIMP Palette header");
+    }
+
+    lines.filter(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with("Name:") && !l.starts_with("Columns:"))
+        .map(parse_rgb)
+        .collect()
+}
+
+fn write_gimp(colours: &[Colour]) -> Vec<u8> {
+    let mut text = String::from("GIMP Palette\nName: tile_test\nColumns: 16\n#\n");
+    for (i, c) in colours.iter().enumerate() {
+        let [r, g, b, _] = palette::to_rgba8(*c);
+        text.push_str(&format!("{:3} {:3} {:3}\tIndex {}\n", r, g, b, i));
+    }
+    text.into_bytes()
+}
+
+fn read_act(data: &[u8]) -> Result<Vec<Colour>, PaletteError> {
+    if data.len() < ACT_COLOURS * 3 {
+        return format_err("ACT file is too short");
+    }
+
+    // Optional footer: big-endian colour count and transparent index.
+    let (count, transparent) = if data.len() >= ACT_COLOURS * 3 + 4 {
+        let count = ((data[768] as usize) << 8) | (data[769] as usize);
+        let transparent = (u16::from(data[770]) << 8) | u16::from(data[771]);
+        (count.clamp(1, ACT_COLOURS), transparent)
+    } else {
+        (ACT_COLOURS, ACT_NO_TRANSPARENCY)
+    };
+
+    Ok(data[0..(count * 3)].chunks_exact(3).enumerate().map(|(i, rgb)| {
+        let alpha = if i == transparent as usize { 0 } else { 255 };
+        palette::from_rgba8([rgb[0], rgb[1], rgb[2], alpha])
+    }).collect())
+}
+
+fn write_act(colours: &[Colour]) -> Vec<u8> {
+    let count = colours.len().min(ACT_COLOURS);
+    let mut data = vec![0; ACT_COLOURS * 3];
+    for (i, c) in colours.iter().take(count).enumerate() {
+        let [r, g, b, _] = palette::to_rgba8(*c);
+        data[(i * 3)..(i * 3 + 3)].copy_from_slice(&[r, g, b]);
+    }
+
+    // Footer with the colour count and the first fully transparent colour.
+    let transparent = colours.iter().take(count).position(|c| c.w == 0.0)
+        .map(|i| i as u16)
+        .unwrap_or(ACT_NO_TRANSPARENCY);
+    data.extend_from_slice(&(count as u16).to_be_bytes());
+    data.extend_from_slice(&transparent.to_be_bytes());
+    data
+}
+
+fn is_riff_palette(data: &[u8]) -> bool {
+    data.starts_with(b"RIFF") && (data.get(8..12) == Some(b"PAL "))
+}
+
+fn read_riff(data: &[u8]) -> Result<Vec<Colour>, PaletteError> {
+    if !is_riff_palette(data) {
+        return format_err("not a RIFF palette");
+    }
+
+    // Find the data chunk, skipping any others. Chunks are padded to an even length.
+    let mut pos = 12;
+    while data.len() - pos >= 8 {
+        let len = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+        let chunk = data[(pos + 8)..].get(..len).ok_or_else(|| PaletteError::Format("RIFF chunk is too long".to_string()))?;
+        if &data[pos..(pos + 4)] == b"data" {
+            // Version, colour count, then RGB and flags for each colour.
+            if chunk.len() < 4 {
+                return format_err("RIFF palette data is too short");
+            }
+            let count = u16::from_le_bytes([chunk[2], chunk[3]]) as usize;
+            if (chunk.len() - 4) / 4 < count {
+                return format_err("RIFF palette has fewer colours than its count");
+            }
+            return Ok(chunk[4..].chunks_exact(4).take(count).map(|c| palette::from_rgba8([c[0], c[1], c[2], 255])).collect());
+        }
+        pos = (pos + 8 + len + (len & 1)).min(data.len());
+    }
+    format_err("RIFF palette has no data chunk")
+}
+
+fn write_riff(colours: &[Colour]) -> Vec<u8> {
+    let count = colours.len().min(u16::MAX as usize);
+    let mut chunk = RIFF_PALETTE_VERSION.to_le_bytes().to_vec();
+    chunk.extend_from_slice(&(count as u16).to_le_bytes());
+    for c in colours.iter().take(count) {
+        let [r, g, b, _] = palette::to_rgba8(*c);
+        chunk.extend_from_slice(&[r, g, b, 0]);
+    }
+
+    let mut data = b"RIFF".to_vec();
+    data.extend_from_slice(&((4 + 8 + chunk.len()) as u32).to_le_bytes());
+    data.extend_from_slice(b"PAL data");
+    data.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+    data.extend_from_slice(&chunk);
+    data
+}
+
+fn read_raw(data: &[u8], convert: fn(u16) -> Colour) -> Result<Vec<Colour>, PaletteError> {
+    if !data.len().is_multiple_of(2) {
+        return format_err("15-bit palette has an odd number of bytes");
+    }
+    Ok(data.chunks_exact(2).map(|b| convert(u16::from_le_bytes([b[0], b[1]]))).collect())
+}
+
+fn write_raw(colours: &[Colour], convert: fn(Colour) -> u16) -> Vec<u8> {
+    colours.iter().flat_map(|c| convert(*c).to_le_bytes().to_vec()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn riff_round_trip() {
+        let colours = [[255, 0, 0, 255], [0, 128, 0, 255], [0, 0, 255, 255]].iter().map(|c| palette::from_rgba8(*c)).collect::<Vec<_>>();
+        let data = write_colours(&colours, PaletteFormat::Riff);
+        assert_eq!(PaletteFormat::detect(Path::new("colours.pal"), &data), PaletteFormat::Riff);
+        let read = read_colours(&data, PaletteFormat::Riff).unwrap();
+        assert_eq!(read.iter().map(|c| palette::to_rgba8(*c)).collect::<Vec<_>>(), vec![[255, 0, 0, 255], [0, 128, 0, 255], [0, 0, 255, 255]]);
+    }
+
+    #[test]
+    fn unrecognised_pal_is_an_error() {
+        let data = b"not a palette";
+        let format = PaletteFormat::detect(Path::new("colours.pal"), data);
+        assert!(read_colours(data, format).is_err());
+        assert!(read_riff(b"RIFF\x04\0\0\0PAL ").is_err());
+    }
+}
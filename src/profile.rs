@@ -0,0 +1,108 @@
+// Where the CPU time and upload bandwidth of each frame goes, for the stats overlay and the stats log.
+//
+// GPU time isn't measured: vulkano 0.13 can't write timestamps from an AutoCommandBuffer or read back query results.
+use std::{
+    fs::File,
+    io::{
+        self,
+        BufWriter,
+        Write
+    },
+    path::Path,
+    time::{
+        Duration,
+        Instant
+    }
+};
+
+#[derive(Clone, Default)]
+pub struct FrameProfile {
+    // Number of times round the main loop when the frame was drawn.
+    pub frame: u64,
+    // Waiting for a swapchain image, and for the slot's last frame in flight to finish.
+    pub wait: Duration,
+    // Copying vertices into buffer chunks.
+    pub vertex: Duration,
+    // Making the atlas image and palette buffer.
+    pub atlas: Duration,
+    pub palette: Duration,
+    // Recording the command buffer, apart from the vertex, atlas and palette uploads.
+    pub record: Duration,
+    // Submitting the command buffer and presenting.
+    pub submit: Duration,
+    pub total: Duration,
+    pub vertex_bytes: usize,
+    pub atlas_bytes: usize,
+    pub palette_bytes: usize
+}
+
+impl FrameProfile {
+    pub fn new(frame: u64) -> Self {
+        FrameProfile {
+            frame: frame,
+            .. FrameProfile::default()
+        }
+    }
+
+    // Lines of text for the stats overlay.
+    pub fn lines(&self) -> Vec<String> {
+        let ms = |d: Duration| format!("{:6.2} MS", d.as_secs_f64() * 1000.0);
+        vec![
+            format!("FRAME   {}", self.frame),
+            format!("TOTAL  {}", ms(self.total)),
+            format!("WAIT   {}", ms(self.wait)),
+            format!("VERTEX {} {}", ms(self.vertex), bytes(self.vertex_bytes)),
+            format!("ATLAS  {} {}", ms(self.atlas), bytes(self.atlas_bytes)),
+            format!("PALETTE{} {}", ms(self.palette), bytes(self.palette_bytes)),
+            format!("RECORD {}", ms(self.record)),
+            format!("SUBMIT {}", ms(self.submit)),
+            "GPU       N/A".to_string()
+        ]
+    }
+}
+
+// Time a step of a frame, adding it to a total.
+pub fn time<T, F: FnOnce() -> T>(total: &mut Duration, f: F) -> T {
+    let start = Instant::now();
+    let result = f();
+    *total += start.elapsed();
+    result
+}
+
+// A byte count in B, KB or MB, at most 6 characters wide.
+fn bytes(n: usize) -> String {
+    if n < 10_000 {
+        format!("{}B", n)
+    } else if n < 10_000_000 {
+        format!("{}KB", n / 1024)
+    } else {
+        format!("{}MB", n / (1024 * 1024))
+    }
+}
+
+// Writes a CSV row for every frame drawn.
+pub struct ProfileLog {
+    writer: Box<Write>
+}
+
+impl ProfileLog {
+    // Path: a CSV file, or "-" for stdout.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer: Box<Write> = if path == Path::new("-") {
+            Box::new(io::stdout())
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        writeln!(writer, "frame,total_ms,wait_ms,vertex_ms,atlas_ms,palette_ms,record_ms,submit_ms,vertex_bytes,atlas_bytes,palette_bytes")?;
+        Ok(ProfileLog {
+            writer: writer
+        })
+    }
+
+    pub fn write(&mut self, profile: &FrameProfile) -> io::Result<()> {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        writeln!(self.writer, "{},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{},{},{}",
+            profile.frame, ms(profile.total), ms(profile.wait), ms(profile.vertex), ms(profile.atlas), ms(profile.palette),
+            ms(profile.record), ms(profile.submit), profile.vertex_bytes, profile.atlas_bytes, profile.palette_bytes)
+    }
+}
@@ -0,0 +1,582 @@
+// Save and load a whole tile scene: the atlas, the grid's tile assignments and the palettes.
+// There is a compact binary format and a human-readable JSON format. Both are versioned:
+// files with the same major version and a newer minor version load, ignoring anything they don't understand.
+use super::{
+    imagegen::TextureAtlas,
+    palette::{
+        Colour,
+        Palette,
+        PaletteStore
+    },
+    tilemap::{
+        MapTile,
+        TileMap
+    },
+    vertexgrid::{
+        Flip,
+        VertexGrid
+    }
+};
+
+use cgmath::Vector4;
+use serde_json::{
+    json,
+    Value
+};
+
+use std::{
+    convert::TryInto,
+    fmt,
+    fs,
+    io,
+    path::Path
+};
+
+pub const VERSION_MAJOR: u16 = 1;
+pub const VERSION_MINOR: u16 = 0;
+
+const MAGIC: &[u8; 8] = b"TILEPROJ";
+const JSON_FORMAT_NAME: &str = "tile_test project";
+
+const CHUNK_ATLAS: &[u8; 4] = b"ATLS";
+const CHUNK_PALETTES: &[u8; 4] = b"PALS";
+const CHUNK_TILEMAP: &[u8; 4] = b"TMAP";
+
+// Colours in a palette that the renderer can show.
+const MAX_PALETTE_SIZE: usize = 4;
+
+const FLIP_X: u8 = 1;
+const FLIP_Y: u8 = 2;
+const FLIP_DIAGONAL: u8 = 4;
+
+#[derive(Debug)]
+pub enum ProjectError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    // The file was written by an incompatible version.
+    Version{ major: u16, minor: u16 },
+    Format(String)
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProjectError::Io(e)                 => write!(f, "IO error: {}", e),
+            ProjectError::Json(e)               => write!(f, "JSON error: {}", e),
+            ProjectError::Version{ major, minor } => write!(f, "Unsupported project version {}.{} (supported: {}.x)", major, minor, VERSION_MAJOR),
+            ProjectError::Format(s)             => write!(f, "Invalid project file: {}", s)
+        }
+    }
+}
+
+impl std::error::Error for ProjectError {}
+
+impl From<io::Error> for ProjectError {
+    fn from(e: io::Error) -> Self {
+        ProjectError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ProjectError {
+    fn from(e: serde_json::Error) -> Self {
+        ProjectError::Json(e)
+    }
+}
+
+fn format_err<T>(msg: &str) -> Result<T, ProjectError> {
+    Err(ProjectError::Format(msg.to_string()))
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ProjectFormat {
+    Binary,
+    Json
+}
+
+impl ProjectFormat {
+    // JSON for ".json" files, binary otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("json") => ProjectFormat::Json,
+            _ => ProjectFormat::Binary
+        }
+    }
+}
+
+// A snapshot of a tile scene.
+pub struct Project {
+    pub atlas: TextureAtlas,
+    pub tilemap: TileMap,
+    pub palettes: Vec<Palette>,
+    // Number of colours in each palette.
+    pub palette_size: usize
+}
+
+impl Project {
+    // Take a copy of the current scene.
+    pub fn capture(atlas: &TextureAtlas, vertex_grid: &VertexGrid, palette_store: &PaletteStore) -> Self {
+        Project {
+            atlas: atlas.clone(),
+            tilemap: TileMap::from_vertex_grid(vertex_grid),
+            palettes: palette_store.palettes().to_vec(),
+            palette_size: palette_store.palette_size()
+        }
+    }
+
+    // Write the project to a file.
+    pub fn save(&self, path: &Path, format: ProjectFormat) -> Result<(), ProjectError> {
+        let data = match format {
+            ProjectFormat::Binary => self.to_bytes(),
+            ProjectFormat::Json => serde_json::to_string_pretty(&self.to_json())?.into_bytes()
+        };
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    // Read a project from a file. The format is detected from the contents.
+    pub fn load(path: &Path) -> Result<Self, ProjectError> {
+        let data = fs::read(path)?;
+        if data.starts_with(MAGIC) {
+            Project::from_bytes(&data)
+        } else {
+            Project::from_json(&serde_json::from_slice(&data)?)
+        }
+    }
+
+    // Encode as binary: a header followed by tagged chunks.
+    // Header: magic, major version and minor version (u16).
+    // Chunk: 4 byte tag, length (u32) and data. All numbers are little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+        data.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+
+        let mut atlas = Vec::new();
+        push_u32(&mut atlas, self.atlas.atlas_size());
+        push_u32(&mut atlas, self.atlas.tex_size());
+        atlas.extend_from_slice(&self.atlas.textures);
+        push_chunk(&mut data, CHUNK_ATLAS, &atlas);
+
+        let mut palettes = Vec::new();
+        push_u32(&mut palettes, self.palettes.len());
+        push_u32(&mut palettes, self.palette_size);
+        for p in self.palettes.iter() {
+            for i in 0..self.palette_size {
+                let c = p.get(i).cloned().unwrap_or_else(|| Vector4::new(0.0, 0.0, 0.0, 0.0));
+                for channel in [c.x, c.y, c.z, c.w].iter() {
+                    palettes.extend_from_slice(&channel.to_le_bytes());
+                }
+            }
+        }
+        push_chunk(&mut data, CHUNK_PALETTES, &palettes);
+
+        let mut tilemap = Vec::new();
+        push_u32(&mut tilemap, self.tilemap.width);
+        push_u32(&mut tilemap, self.tilemap.height);
+        for tile in self.tilemap.tiles.iter() {
+            push_u32(&mut tilemap, tile.tex_x);
+            push_u32(&mut tilemap, tile.tex_y);
+            push_u32(&mut tilemap, tile.palette as usize);
+            tilemap.push(flip_bits(tile.flip));
+        }
+        push_chunk(&mut data, CHUNK_TILEMAP, &tilemap);
+
+        data
+    }
+
+    // Decode from binary. Unknown chunks are skipped.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ProjectError> {
+        if !data.starts_with(MAGIC) || data.len() < MAGIC.len() + 4 {
+            return format_err("missing header");
+        }
+        let mut reader = Reader{ data: data, pos: MAGIC.len() };
+        let major = reader.u16()?;
+        let minor = reader.u16()?;
+        check_version(major, minor)?;
+
+        let mut atlas = None;
+        let mut palettes = None;
+        let mut tilemap = None;
+        while !reader.is_empty() {
+            let tag = reader.bytes(4)?;
+            let len = reader.u32()?;
+            let mut chunk = Reader{ data: reader.bytes(len)?, pos: 0 };
+            match tag {
+                t if t == CHUNK_ATLAS => {
+                    let atlas_size = chunk.u32()?;
+                    let tex_size = chunk.u32()?;
+                    let texels = chunk.bytes(atlas_area(atlas_size, tex_size)?)?.to_vec();
+                    atlas = Some(make_atlas(atlas_size, tex_size, texels)?);
+                },
+                t if t == CHUNK_PALETTES => {
+                    let count = chunk.u32()?;
+                    let size = chunk.u32()?;
+                    // Each colour is 16 bytes. Empty palettes would take no space, so they are rejected here.
+                    if size == 0 {
+                        return format_err("palettes have no colours");
+                    }
+                    if count.checked_mul(size).and_then(|n| n.checked_mul(16)).is_none_or(|n| n > len - 8) {
+                        return format_err("colour count doesn't match the palettes size");
+                    }
+                    let mut p = Vec::new();
+                    for _ in 0..count {
+                        let mut palette = Palette::new();
+                        for _ in 0..size {
+                            palette.push(Vector4::new(chunk.f32()?, chunk.f32()?, chunk.f32()?, chunk.f32()?));
+                        }
+                        p.push(palette);
+                    }
+                    palettes = Some((p, size));
+                },
+                t if t == CHUNK_TILEMAP => {
+                    let width = chunk.u32()?;
+                    let height = chunk.u32()?;
+                    // Each tile is 13 bytes. Newer versions may add data after the tiles.
+                    if width.checked_mul(height).and_then(|n| n.checked_mul(13)).is_none_or(|n| n > len - 8) {
+                        return format_err("tile count doesn't match the tilemap size");
+                    }
+                    let mut map = TileMap::new(width, height);
+                    for tile in map.tiles.iter_mut() {
+                        *tile = MapTile {
+                            tex_x: chunk.u32()?,
+                            tex_y: chunk.u32()?,
+                            palette: chunk.u32()? as u32,
+                            flip: from_flip_bits(chunk.bytes(1)?[0])
+                        };
+                    }
+                    tilemap = Some(map);
+                },
+                _ => {}
+            }
+        }
+
+        let (palettes, palette_size) = palettes.ok_or_else(|| ProjectError::Format("missing palettes".to_string()))?;
+        Project::new_checked(
+            atlas.ok_or_else(|| ProjectError::Format("missing atlas".to_string()))?,
+            tilemap.ok_or_else(|| ProjectError::Format("missing tilemap".to_string()))?,
+            palettes,
+            palette_size
+        )
+    }
+
+    // Encode as JSON. Atlas texels are written one row per array.
+    pub fn to_json(&self) -> Value {
+        let width = self.atlas.atlas_size() * self.atlas.tex_size();
+        let rows = self.atlas.textures.chunks(width.max(1)).collect::<Vec<_>>();
+
+        let palettes = self.palettes.iter().map(|p| {
+            p.iter().map(|c| json!([c.x, c.y, c.z, c.w])).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+
+        let tiles = self.tilemap.tiles.iter().map(|t| json!({
+            "tex": [t.tex_x, t.tex_y],
+            "palette": t.palette,
+            "flip": flip_string(t.flip)
+        })).collect::<Vec<_>>();
+
+        json!({
+            "format": JSON_FORMAT_NAME,
+            "version": [VERSION_MAJOR, VERSION_MINOR],
+            "atlas": {
+                "atlas_size": self.atlas.atlas_size(),
+                "tex_size": self.atlas.tex_size(),
+                "texels": rows
+            },
+            "palette_size": self.palette_size,
+            "palettes": palettes,
+            "tilemap": {
+                "width": self.tilemap.width,
+                "height": self.tilemap.height,
+                "tiles": tiles
+            }
+        })
+    }
+
+    // Decode from JSON. Unknown fields are ignored.
+    pub fn from_json(value: &Value) -> Result<Self, ProjectError> {
+        if value.get("format").and_then(|f| f.as_str()) != Some(JSON_FORMAT_NAME) {
+            return format_err("not a project file");
+        }
+        let version = value.get("version").and_then(|v| v.as_array())
+            .ok_or_else(|| ProjectError::Format("missing version".to_string()))?;
+        let version_part = |i: usize| version.get(i).and_then(|v| v.as_u64()).unwrap_or(0) as u16;
+        check_version(version_part(0), version_part(1))?;
+
+        let atlas = field(value, "atlas")?;
+        let texels = array(atlas, "texels")?.iter()
+            .flat_map(|row| row.as_array().cloned().unwrap_or_default())
+            .map(|t| t.as_u64().and_then(|t| t.try_into().ok()).ok_or_else(|| ProjectError::Format("bad texel".to_string())))
+            .collect::<Result<Vec<u8>, _>>()?;
+        let atlas = make_atlas(number(atlas, "atlas_size")?, number(atlas, "tex_size")?, texels)?;
+
+        let palettes = array(value, "palettes")?.iter().map(|p| {
+            p.as_array().map(|p| p.iter().map(json_colour).collect::<Result<Palette, _>>())
+                .unwrap_or_else(|| format_err("bad palette"))
+        }).collect::<Result<Vec<_>, _>>()?;
+        let palette_size = number(value, "palette_size")?;
+
+        let map = field(value, "tilemap")?;
+        let (width, height) = (number(map, "width")?, number(map, "height")?);
+        let tiles = array(map, "tiles")?;
+        if width.checked_mul(height) != Some(tiles.len()) {
+            return format_err("tile count doesn't match the tilemap size");
+        }
+        let mut tilemap = TileMap::new(width, height);
+        for (tile, t) in tilemap.tiles.iter_mut().zip(tiles.iter()) {
+            let tex = array(t, "tex")?;
+            let coord = |i: usize| tex.get(i).and_then(|c| c.as_u64()).map(|c| c as usize)
+                .ok_or_else(|| ProjectError::Format("bad tile texture".to_string()));
+            *tile = MapTile {
+                tex_x: coord(0)?,
+                tex_y: coord(1)?,
+                palette: number(t, "palette")? as u32,
+                flip: from_flip_string(t.get("flip").and_then(|f| f.as_str()).unwrap_or(""))
+            };
+        }
+
+        Project::new_checked(atlas, tilemap, palettes, palette_size)
+    }
+
+    // Make a palette store with the project's palettes.
+    pub fn palette_store(&self) -> PaletteStore {
+        PaletteStore::from_palettes(self.palettes.clone(), self.palette_size)
+    }
+
+    // Check that the sizes can be drawn, and that every tile refers to a texture in the atlas and an existing palette.
+    fn new_checked(atlas: TextureAtlas, tilemap: TileMap, palettes: Vec<Palette>, palette_size: usize) -> Result<Self, ProjectError> {
+        let atlas_size = atlas.atlas_size();
+        if (atlas_size == 0) || (atlas.tex_size() == 0) {
+            return format_err("the atlas and texture sizes must be at least 1");
+        }
+        if (palette_size == 0) || (palette_size > MAX_PALETTE_SIZE) {
+            return Err(ProjectError::Format(format!("palettes must have from 1 to {} colours", MAX_PALETTE_SIZE)));
+        }
+        if tilemap.tiles.iter().any(|t| t.tex_x >= atlas_size || t.tex_y >= atlas_size) {
+            return format_err("tile texture outside of the atlas");
+        }
+        if tilemap.tiles.iter().any(|t| t.palette as usize >= palettes.len()) {
+            return format_err("tile palette doesn't exist");
+        }
+        Ok(Project {
+            atlas: atlas,
+            tilemap: tilemap,
+            palettes: palettes,
+            palette_size: palette_size
+        })
+    }
+}
+
+fn check_version(major: u16, minor: u16) -> Result<(), ProjectError> {
+    if major == VERSION_MAJOR {
+        Ok(())
+    } else {
+        Err(ProjectError::Version{ major: major, minor: minor })
+    }
+}
+
+// Number of texels in an atlas.
+fn atlas_area(atlas_size: usize, tex_size: usize) -> Result<usize, ProjectError> {
+    atlas_size.checked_mul(tex_size)
+        .and_then(|w| w.checked_mul(w))
+        .ok_or_else(|| ProjectError::Format("atlas is too large".to_string()))
+}
+
+fn make_atlas(atlas_size: usize, tex_size: usize, texels: Vec<u8>) -> Result<TextureAtlas, ProjectError> {
+    if texels.len() != atlas_area(atlas_size, tex_size)? {
+        return format_err("texel count doesn't match the atlas size");
+    }
+    let mut atlas = TextureAtlas::new(atlas_size, tex_size);
+    atlas.textures = texels;
+    Ok(atlas)
+}
+
+fn flip_bits(flip: Flip) -> u8 {
+    (if flip.x { FLIP_X } else { 0 }) | (if flip.y { FLIP_Y } else { 0 }) | (if flip.diagonal { FLIP_DIAGONAL } else { 0 })
+}
+
+fn from_flip_bits(bits: u8) -> Flip {
+    Flip{ x: bits & FLIP_X != 0, y: bits & FLIP_Y != 0, diagonal: bits & FLIP_DIAGONAL != 0 }
+}
+
+// Flips as a string of "x", "y" and "d", e.g. "xd".
+fn flip_string(flip: Flip) -> String {
+    [(flip.x, 'x'), (flip.y, 'y'), (flip.diagonal, 'd')].iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, c)| *c)
+        .collect()
+}
+
+fn from_flip_string(s: &str) -> Flip {
+    Flip{ x: s.contains('x'), y: s.contains('y'), diagonal: s.contains('d') }
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Value, ProjectError> {
+    value.get(name).ok_or_else(|| ProjectError::Format(format!("missing '{}'", name)))
+}
+
+fn array<'a>(value: &'a Value, name: &str) -> Result<&'a Vec<Value>, ProjectError> {
+    field(value, name)?.as_array().ok_or_else(|| ProjectError::Format(format!("'{}' is not an array", name)))
+}
+
+fn number(value: &Value, name: &str) -> Result<usize, ProjectError> {
+    field(value, name)?.as_u64().map(|n| n as usize).ok_or_else(|| ProjectError::Format(format!("'{}' is not a number", name)))
+}
+
+fn json_colour(value: &Value) -> Result<Colour, ProjectError> {
+    let channels = value.as_array()
+        .map(|c| c.iter().filter_map(|c| c.as_f64()).map(|c| c as f32).collect::<Vec<_>>())
+        .unwrap_or_default();
+    match channels.as_slice() {
+        [r, g, b, a] => Ok(Vector4::new(*r, *g, *b, *a)),
+        _ => format_err("bad colour")
+    }
+}
+
+fn push_u32(data: &mut Vec<u8>, n: usize) {
+    data.extend_from_slice(&(n as u32).to_le_bytes());
+}
+
+fn push_chunk(data: &mut Vec<u8>, tag: &[u8; 4], chunk: &[u8]) {
+    data.extend_from_slice(tag);
+    push_u32(data, chunk.len());
+    data.extend_from_slice(chunk);
+}
+
+// Reads little-endian values from binary data.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ProjectError> {
+        if self.data.len() - self.pos < len {
+            return format_err("unexpected end of data");
+        }
+        let bytes = &self.data[self.pos..(self.pos + len)];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, ProjectError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<usize, ProjectError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    }
+
+    fn f32(&mut self) -> Result<f32, ProjectError> {
+        let b = self.bytes(4)?;
+        Ok(f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project() -> Project {
+        let mut atlas = TextureAtlas::new(2, 3);
+        for (i, t) in atlas.textures.iter_mut().enumerate() {
+            *t = (i % 4) as u8;
+        }
+
+        let mut tilemap = TileMap::new(3, 2);
+        for (i, tile) in tilemap.tiles.iter_mut().enumerate() {
+            *tile = MapTile {
+                tex_x: i % 2,
+                tex_y: (i / 2) % 2,
+                palette: (i % 2) as u32,
+                flip: from_flip_bits(i as u8)
+            };
+        }
+
+        let palettes = vec![
+            vec![Vector4::new(0.0, 0.25, 0.5, 1.0), Vector4::new(1.0, 0.0, 0.0, 1.0)],
+            vec![Vector4::new(0.125, 0.75, 1.0, 0.5), Vector4::new(0.0, 0.0, 0.0, 0.0)]
+        ];
+
+        Project {
+            atlas: atlas,
+            tilemap: tilemap,
+            palettes: palettes,
+            palette_size: 2
+        }
+    }
+
+    fn assert_same(a: &Project, b: &Project) {
+        assert_eq!(a.atlas.atlas_size(), b.atlas.atlas_size());
+        assert_eq!(a.atlas.tex_size(), b.atlas.tex_size());
+        assert_eq!(a.atlas.textures, b.atlas.textures);
+        assert_eq!((a.tilemap.width, a.tilemap.height), (b.tilemap.width, b.tilemap.height));
+        assert!(a.tilemap.tiles == b.tilemap.tiles);
+        assert_eq!(a.palettes, b.palettes);
+        assert_eq!(a.palette_size, b.palette_size);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let original = project();
+        let loaded = Project::from_bytes(&original.to_bytes()).unwrap();
+        assert_same(&original, &loaded);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let original = project();
+        let text = serde_json::to_string_pretty(&original.to_json()).unwrap();
+        let loaded = Project::from_json(&serde_json::from_str(&text).unwrap()).unwrap();
+        assert_same(&original, &loaded);
+    }
+
+    #[test]
+    fn binary_to_json_round_trip() {
+        let original = project();
+        let from_binary = Project::from_bytes(&original.to_bytes()).unwrap();
+        let loaded = Project::from_json(&from_binary.to_json()).unwrap();
+        assert_same(&original, &loaded);
+    }
+
+    #[test]
+    fn palette_count_larger_than_chunk() {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+        data.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+        let mut palettes = Vec::new();
+        push_u32(&mut palettes, 4_000_000_000);
+        push_u32(&mut palettes, 0);
+        push_chunk(&mut data, CHUNK_PALETTES, &palettes);
+        assert!(Project::from_bytes(&data).is_err());
+
+        let mut data = project().to_bytes();
+        // Claim one more palette than the chunk holds.
+        let pals = data.windows(4).position(|w| w == CHUNK_PALETTES).unwrap() + 8;
+        data[pals] += 1;
+        assert!(Project::from_bytes(&data).is_err());
+    }
+
+    #[test]
+    fn invalid_projects_are_rejected() {
+        let valid = |change: &dyn Fn(&mut Project)| {
+            let mut p = project();
+            change(&mut p);
+            Project::new_checked(p.atlas, p.tilemap, p.palettes, p.palette_size).is_ok()
+        };
+
+        assert!(valid(&|_| {}));
+        assert!(!valid(&|p| p.atlas = TextureAtlas::new(0, 3)));
+        assert!(!valid(&|p| p.atlas = TextureAtlas::new(2, 0)));
+        assert!(!valid(&|p| p.palette_size = 0));
+        assert!(!valid(&|p| p.palette_size = 256));
+        assert!(!valid(&|p| p.tilemap.tiles[0].tex_x = 2));
+        assert!(!valid(&|p| p.tilemap.tiles[0].palette = 2));
+        assert!(!valid(&|p| { p.palettes.pop(); }));
+    }
+}
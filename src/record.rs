@@ -0,0 +1,218 @@
+// Record commands to a log file with the frame they were applied on, and replay them.
+// The log starts with the seed for the random number generator used by commands, so replays are deterministic.
+//
+// Log format, one entry per line:
+//   seed <u64>
+//   <frame> <command>
+// Blank lines and lines starting with '#' are ignored.
+use super::command::Command;
+
+use std::{
+    fmt,
+    fs::{
+        self,
+        File
+    },
+    io::{
+        self,
+        BufWriter,
+        Write
+    },
+    path::Path
+};
+
+#[derive(Debug)]
+pub enum RecordError {
+    Io(io::Error),
+    Format{ line: usize, message: String }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::Io(e)                  => write!(f, "IO error: {}", e),
+            RecordError::Format{ line, message } => write!(f, "Invalid command log on line {}: {}", line, message)
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl From<io::Error> for RecordError {
+    fn from(e: io::Error) -> Self {
+        RecordError::Io(e)
+    }
+}
+
+// Writes commands to a log as they are applied.
+pub struct Recorder {
+    writer: BufWriter<File>
+}
+
+impl Recorder {
+    pub fn create(path: &Path, seed: u64) -> Result<Self, RecordError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "# tile_test command log")?;
+        writeln!(writer, "seed {}", seed)?;
+        writer.flush()?;
+        Ok(Recorder {
+            writer: writer
+        })
+    }
+
+    // Each command is flushed straight away, so the log is complete even if the program crashes.
+    pub fn record(&mut self, frame: u64, command: &Command) -> Result<(), RecordError> {
+        writeln!(self.writer, "{} {}", frame, command)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// A loaded command log, played back frame by frame.
+pub struct Replay {
+    pub seed: u64,
+    // Commands in frame order.
+    commands: Vec<(u64, Command)>,
+    next: usize
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, RecordError> {
+        Replay::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, RecordError> {
+        let mut seed = None;
+        let mut commands = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let format_err = |message: &str| RecordError::Format{ line: i + 1, message: message.to_string() };
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (first, rest) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+            if first == "seed" {
+                seed = Some(rest.trim().parse::<u64>().map_err(|_| format_err("bad seed"))?);
+            } else {
+                let frame = first.parse::<u64>().map_err(|_| format_err("bad frame number"))?;
+                let command = rest.trim().parse::<Command>().map_err(|e| format_err(&e.to_string()))?;
+                if commands.last().is_some_and(|(f, _)| *f > frame) {
+                    return Err(format_err("frames are out of order"));
+                }
+                commands.push((frame, command));
+            }
+        }
+
+        Ok(Replay {
+            seed: seed.ok_or(RecordError::Format{ line: 0, message: "missing seed".to_string() })?,
+            commands: commands,
+            next: 0
+        })
+    }
+
+    // Take the commands for a frame. Frames must be played in order.
+    // Commands for earlier frames that haven't been taken are included.
+    pub fn commands_for_frame(&mut self, frame: u64) -> Vec<Command> {
+        let start = self.next;
+        while self.commands.get(self.next).is_some_and(|(f, _)| *f <= frame) {
+            self.next += 1;
+        }
+        self.commands[start..self.next].iter().map(|(_, c)| *c).collect()
+    }
+
+    // Returns true once every command has been taken.
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.commands.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        export,
+        imagegen::TextureAtlas,
+        palette::{
+            self,
+            PaletteStore
+        },
+        scene::Scene,
+        tilemap::TileMap
+    };
+
+    use rand::{
+        rngs::StdRng,
+        SeedableRng
+    };
+
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    // Alpha is kept when a colour is set.
+    const TRANSLUCENT_BLUE: [u8; 4] = [0, 0, 255, 128];
+    const TRANSLUCENT_YELLOW: [u8; 4] = [255, 255, 0, 128];
+
+    // Replay a log into a scene with a 2x1 map of a 2x2 atlas of 2x2 textures, the way the demo does, and draw the map.
+    fn play(log: &str) -> Vec<u8> {
+        let mut replay = Replay::parse(log).unwrap();
+        let mut rng = StdRng::seed_from_u64(replay.seed);
+        let colour = |c: [u8; 4]| palette::from_rgba8(c);
+        let palettes = vec![
+            vec![colour(BLACK), colour([255, 0, 0, 255]), colour(GREEN), colour(TRANSLUCENT_BLUE)],
+            vec![colour(WHITE), colour(BLACK), colour(BLACK), colour(BLACK)]
+        ];
+        let mut scene = Scene::new(TileMap::new(2, 1), TextureAtlas::new(2, 2), PaletteStore::from_palettes(palettes, 4), 1 << 20, 4, 4);
+
+        let mut frame = 0;
+        while !replay.is_finished() {
+            for command in replay.commands_for_frame(frame).iter().filter(|c| !c.uses_files()) {
+                scene.apply(command, &mut rng).unwrap();
+            }
+            frame += 1;
+        }
+
+        export::render_tilemap(&scene.grid, &scene.atlas, scene.palette_store.palettes())
+    }
+
+    #[test]
+    fn replay_matches_golden_image() {
+        let log = "\
+            # tile_test command log\n\
+            seed 7\n\
+            0 texel 1 0 0 0 3\n\
+            0 texel 1 0 1 1 2\n\
+            1 texture 1 0 1 0\n\
+            2 palette 0 0 1\n\
+            3 texel 0 0 1 0 2\n\
+            3 undo\n\
+            4 colour 0 3 255 255 0\n\
+            5 save\n\
+            5 export\n\
+            6 load\n";
+
+        // The map is 4x2 pixels: tile (0, 0) is texture (0, 0) through palette 1, and tile (1, 0) is texture (1, 0).
+        let golden = [
+            WHITE, WHITE, TRANSLUCENT_YELLOW, BLACK,
+            WHITE, WHITE, BLACK, GREEN
+        ].concat();
+        assert_eq!(play(log), golden);
+    }
+
+    #[test]
+    fn replay_is_deterministic() {
+        let log = "seed 12345\n0 generate 0 0 random\n0 generate 1 1 checkerboard\n3 texture 0 0 1 1\n3 texture 1 0 0 0\n";
+        let image = play(log);
+        assert_eq!(image, play(log));
+        // A different seed generates different textures.
+        assert_ne!(image, play(&log.replace("12345", "54321")));
+    }
+
+    #[test]
+    fn file_commands_use_files() {
+        for (text, uses_files) in [("export", true), ("save", true), ("load", true), ("undo", false), ("palette 0 0 1", false)].iter() {
+            assert_eq!(text.parse::<Command>().unwrap().uses_files(), *uses_files);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+// The editable scene: a tile grid, its texture atlas and palettes, and the history of edits to them.
+// Commands are checked and applied here, so the demo and its replays behave the same.
+use super::{
+    command::Command,
+    error::TileError,
+    history::{
+        Edit,
+        History
+    },
+    imagegen::TextureAtlas,
+    palette::{
+        self,
+        PaletteStore
+    },
+    vertexgrid::{
+        AtlasSlot,
+        Flip,
+        TileCoord,
+        TileGrid
+    }
+};
+
+use rand::Rng;
+
+pub struct Scene<G: TileGrid> {
+    pub grid: G,
+    pub atlas: TextureAtlas,
+    pub palette_store: PaletteStore,
+    pub history: History,
+    // Number of palettes, and colours in each palette, that the renderer can show.
+    max_palettes: usize,
+    max_colours: usize
+}
+
+impl<G: TileGrid> Scene<G> {
+    // History budget: maximum memory to use for undoing edits, in bytes.
+    pub fn new(grid: G, atlas: TextureAtlas, palette_store: PaletteStore, history_budget: usize, max_palettes: usize, max_colours: usize) -> Self {
+        Scene {
+            grid: grid,
+            atlas: atlas,
+            palette_store: palette_store,
+            history: History::new(history_budget),
+            max_palettes: max_palettes,
+            max_colours: max_colours
+        }
+    }
+
+    // Palettes that tiles can use: those the renderer can show.
+    pub fn palettes(&self) -> usize {
+        self.palette_store.len().min(self.max_palettes)
+    }
+
+    // Colours in each palette that the renderer can show.
+    pub fn colours(&self) -> usize {
+        self.palette_store.palette_size().min(self.max_colours)
+    }
+
+    // Colours used by texture generators.
+    pub fn generator_colours(&self) -> u8 {
+        self.palette_store.palette_size().clamp(2, self.max_colours.max(2)).min(u8::MAX as usize) as u8
+    }
+
+    // Check that a command only refers to tiles, textures and colours that exist, and palettes the renderer can show.
+    pub fn check(&self, command: &Command) -> Result<(), TileError> {
+        match *command {
+            Command::ModifyTilePalette{ palette, x, y }        => {
+                self.grid.check_tile(TileCoord::new(x, y))?;
+                if palette as usize >= self.palettes() {
+                    return Err(TileError::PaletteOutOfRange{ palette: palette as usize, palettes: self.palettes() });
+                }
+                Ok(())
+            },
+            Command::ModifyTileTexture{ tex_x, tex_y, x, y }   => {
+                self.grid.check_tile(TileCoord::new(x, y))?;
+                self.atlas.check_slot(AtlasSlot::new(tex_x, tex_y))
+            },
+            Command::GenerateTexture{ tex_x, tex_y, .. }       => self.atlas.check_slot(AtlasSlot::new(tex_x, tex_y)),
+            Command::SetTexel{ tex_x, tex_y, x, y, .. }        => self.atlas.check_texel(AtlasSlot::new(tex_x, tex_y), x, y),
+            Command::SetColour{ palette, index, .. }           => {
+                if palette >= self.palette_store.len() {
+                    return Err(TileError::PaletteOutOfRange{ palette: palette, palettes: self.palette_store.len() });
+                }
+                if index >= self.palette_store.palette_size() {
+                    return Err(TileError::ColourOutOfRange{ index: index, colours: self.palette_store.palette_size() });
+                }
+                Ok(())
+            },
+            _ => Ok(())
+        }
+    }
+
+    // Check and apply a command, adding edits to the history.
+    // Returns false if nothing could be undone or redone. Commands that use files are left to the caller.
+    pub fn apply<R: Rng>(&mut self, command: &Command, rng: &mut R) -> Result<bool, TileError> {
+        self.check(command)?;
+
+        let edit = match *command {
+            Command::ModifyTilePalette{ palette, x, y }        => Edit::tile_palette(&self.grid, x, y, palette),
+            Command::ModifyTileTexture{ tex_x, tex_y, x, y }   => Edit::tile_texture(&self.grid, x, y, tex_x, tex_y, Flip::default()),
+            Command::GenerateTexture{ tex_x, tex_y, generator } => {
+                let texels = generator.generate(self.atlas.tex_size(), self.generator_colours(), rng);
+                Edit::texels(&self.atlas, tex_x, tex_y, texels)
+            },
+            Command::SetTexel{ tex_x, tex_y, x, y, value }     => Edit::texel(&self.atlas, tex_x, tex_y, x, y, value),
+            Command::SetColour{ palette, index, rgb }          => {
+                // Alpha is kept.
+                let mut colour = palette::from_rgba8([rgb[0], rgb[1], rgb[2], 0]);
+                colour.w = self.palette_store.colour(palette, index).map_or(1.0, |c| c.w);
+                Edit::colour(&self.palette_store, palette, index, colour)
+            },
+            Command::Undo                                      => return Ok(self.history.undo(&mut self.atlas, &mut self.grid, &mut self.palette_store)),
+            Command::Redo                                      => return Ok(self.history.redo(&mut self.atlas, &mut self.grid, &mut self.palette_store)),
+            Command::ExportImages |
+            Command::SaveProject |
+            Command::LoadProject                               => return Ok(true)
+        };
+
+        self.history.apply(edit, &mut self.atlas, &mut self.grid, &mut self.palette_store);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::TileMap;
+
+    use rand::{
+        rngs::StdRng,
+        SeedableRng
+    };
+
+    #[test]
+    fn out_of_range_commands_are_rejected() {
+        let palette_store = PaletteStore::from_palettes(vec![Vec::new(); 6], 8);
+        let mut scene = Scene::new(TileMap::new(2, 2), TextureAtlas::new(2, 2), palette_store, 1 << 20, 4, 4);
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(scene.palettes(), 4);
+        assert_eq!(scene.colours(), 4);
+
+        for text in ["palette 2 0 1", "palette 0 0 4", "texture 0 0 2 0", "generate 0 2", "texel 0 0 2 0 1", "colour 6 0 0 0 0", "colour 0 8 0 0 0"].iter() {
+            assert!(scene.apply(&text.parse().unwrap(), &mut rng).is_err(), "'{}' was applied", text);
+        }
+        assert!(!scene.history.can_undo());
+
+        assert_eq!(scene.apply(&"palette 1 1 3".parse().unwrap(), &mut rng).ok(), Some(true));
+        assert_eq!(scene.grid.tile_palette(1, 1), 3);
+        assert_eq!(scene.apply(&Command::Undo, &mut rng).ok(), Some(true));
+        assert_eq!(scene.apply(&Command::Undo, &mut rng).ok(), Some(false));
+    }
+}
@@ -0,0 +1,157 @@
+use vulkano::{
+    buffer::cpu_pool::CpuBufferPoolChunk,
+    command_buffer::{
+        AutoCommandBuffer,
+        CommandBufferExecFuture
+    },
+    device::{
+        Device,
+        Queue
+    },
+    format::R8Uint,
+    image::immutable::ImmutableImage,
+    memory::pool::StdMemoryPool,
+    pipeline::viewport::Viewport,
+    sync::NowFuture
+};
+
+use tile_test::{
+    error::TileError,
+    imagegen::TextureAtlas,
+    palette::Palette,
+    textgrid::TextGrid,
+    vertexgrid::VertexGrid,
+    Vertex
+};
+
+use super::atlasviewer::DIGITS;
+
+use std::sync::Arc;
+
+// 3x5 glyphs for upper case letters and punctuation, in the same layout as the atlas viewer's digits.
+const LETTERS: [(char, [u8; 5]); 28] = [
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100])
+];
+// The glyph atlas holds the digits, then the letters, then a blank texture for spaces and unknown characters.
+const GLYPH_ATLAS_SIZE: usize = 7;
+const GLYPH_SIZE: usize = 6;
+const BLANK: usize = GLYPH_ATLAS_SIZE * GLYPH_ATLAS_SIZE - 1;
+// Size of the text in cells.
+const COLUMNS: usize = 24;
+const ROWS: usize = 9;
+// Size of a cell in pixels.
+const CELL_SIZE: f32 = 12.0;
+
+// Frame statistics as white text on black, over the top left of the window.
+pub struct StatsOverlay {
+    text: TextGrid,
+    // One tile per text cell.
+    vertex_grid: VertexGrid,
+    glyphs: Arc<ImmutableImage<R8Uint>>,
+    viewport: Viewport,
+    lines: Vec<String>
+}
+
+impl StatsOverlay {
+    // Window: the viewport of the whole window.
+    pub fn new(device: &Arc<Device>, queue: Arc<Queue>, window: &Viewport) -> Result<(Self, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>), TileError> {
+        let mut text = TextGrid::new(COLUMNS, ROWS, 1);
+        let mut glyph_atlas = TextureAtlas::new(GLYPH_ATLAS_SIZE, GLYPH_SIZE);
+        let digits = DIGITS.iter().enumerate().map(|(d, glyph)| (std::char::from_digit(d as u32, 10).unwrap_or(' '), glyph));
+        let letters = LETTERS.iter().map(|(c, glyph)| (*c, glyph));
+        for (i, (ch, glyph)) in digits.chain(letters).enumerate() {
+            let (tex_x, tex_y) = (i % GLYPH_ATLAS_SIZE, i / GLYPH_ATLAS_SIZE);
+            for (y, row) in glyph.iter().enumerate() {
+                for x in 0..3 {
+                    if (row >> (2 - x)) & 1 == 1 {
+                        glyph_atlas.set_texel(tex_x, tex_y, x + 1, y, 1);
+                    }
+                }
+            }
+            text.set_glyph(ch, tex_x, tex_y);
+        }
+        text.set_fallback_glyph(BLANK % GLYPH_ATLAS_SIZE, BLANK / GLYPH_ATLAS_SIZE);
+        let (glyphs, future) = glyph_atlas.make_image(queue)?;
+
+        let viewport = Viewport {
+            origin: window.origin,
+            dimensions: [(COLUMNS as f32 * CELL_SIZE).min(window.dimensions[0]), (ROWS as f32 * CELL_SIZE).min(window.dimensions[1])],
+            depth_range: window.depth_range.clone()
+        };
+
+        let overlay = StatsOverlay {
+            text: text,
+            vertex_grid: VertexGrid::new(device, COLUMNS, ROWS, GLYPH_ATLAS_SIZE),
+            glyphs: glyphs,
+            viewport: viewport,
+            lines: Vec::new()
+        };
+        Ok((overlay, future))
+    }
+
+    pub fn viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    // Glyph texture atlas.
+    pub fn glyphs(&self) -> Arc<ImmutableImage<R8Uint>> {
+        self.glyphs.clone()
+    }
+
+    // Palettes for the text colours, to upload alongside the scene's palettes.
+    pub fn palettes(&self) -> Vec<Palette> {
+        self.text.palettes().iter().map(|p| p.to_vec()).collect()
+    }
+
+    // Show lines of text. Lines are cut off at the right edge, and lines past the bottom are dropped.
+    pub fn set_lines(&mut self, lines: Vec<String>) {
+        if lines == self.lines {
+            return;
+        }
+
+        for y in 0..ROWS {
+            self.text.clear_row(y);
+            if let Some(line) = lines.get(y) {
+                self.text.write_str(0, y, &line.chars().take(COLUMNS).collect::<String>());
+            }
+        }
+        self.lines = lines;
+    }
+
+    pub fn get_vertex_buffer(&mut self, frame: usize) -> Result<CpuBufferPoolChunk<Vertex, Arc<StdMemoryPool>>, TileError> {
+        self.text.update(&mut self.vertex_grid);
+        self.vertex_grid.get_vertex_buffer(frame)
+    }
+
+    // Bytes of vertex data uploaded since the last call.
+    pub fn take_uploaded_bytes(&mut self) -> usize {
+        self.vertex_grid.take_uploaded_bytes()
+    }
+}
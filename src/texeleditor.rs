@@ -0,0 +1,73 @@
+use vulkano::{
+    buffer::cpu_pool::CpuBufferPoolChunk,
+    device::Device,
+    memory::pool::StdMemoryPool,
+    pipeline::viewport::Viewport
+};
+
+use tile_test::{
+    error::TileError,
+    imagegen::TextureAtlas,
+    vertexgrid::VertexGrid,
+    Vertex
+};
+
+use std::sync::Arc;
+
+// Zoomed view of one atlas texture, drawn over the top right of the window while editing texels.
+// The view samples the atlas like any other tile, so edits show up in it and on the grid at the same time.
+pub struct TexelEditor {
+    // A single tile.
+    vertex_grid: VertexGrid,
+    viewport: Viewport,
+    tex_size: usize
+}
+
+impl TexelEditor {
+    // Window: the viewport of the whole window.
+    pub fn new(device: &Arc<Device>, window: &Viewport, texture_atlas: &TextureAtlas) -> Self {
+        let side = window.dimensions[0].min(window.dimensions[1]) / 2.0;
+        let viewport = Viewport {
+            origin: [window.origin[0] + window.dimensions[0] - side, window.origin[1]],
+            dimensions: [side, side],
+            depth_range: window.depth_range.clone()
+        };
+
+        let mut vertex_grid = VertexGrid::new(device, 1, 1, texture_atlas.atlas_size());
+        vertex_grid.set_tile_texture(0, 0, 0, 0);
+
+        TexelEditor {
+            vertex_grid: vertex_grid,
+            viewport: viewport,
+            tex_size: texture_atlas.tex_size()
+        }
+    }
+
+    pub fn viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    // Size of the texture in texels.
+    pub fn tex_size(&self) -> usize {
+        self.tex_size
+    }
+
+    // Show a texture with a palette.
+    pub fn show(&mut self, tex_x: usize, tex_y: usize, palette: u32) {
+        if self.vertex_grid.tile_texture(0, 0) != (tex_x, tex_y) {
+            self.vertex_grid.set_tile_texture(0, 0, tex_x, tex_y);
+        }
+        if self.vertex_grid.tile_palette(0, 0) != palette {
+            self.vertex_grid.set_tile_palette(0, 0, palette);
+        }
+    }
+
+    pub fn get_vertex_buffer(&mut self, frame: usize) -> Result<CpuBufferPoolChunk<Vertex, Arc<StdMemoryPool>>, TileError> {
+        self.vertex_grid.get_vertex_buffer(frame)
+    }
+
+    // Bytes of vertex data uploaded since the last call.
+    pub fn take_uploaded_bytes(&mut self) -> usize {
+        self.vertex_grid.take_uploaded_bytes()
+    }
+}
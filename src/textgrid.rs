@@ -0,0 +1,419 @@
+// Text-mode grid, drawn with a VertexGrid using glyph tiles from a TextureAtlas.
+use super::{
+    imagegen::TextureAtlas,
+    palette::Colour,
+    vertexgrid::VertexGrid
+};
+
+use cgmath::Vector4;
+
+use std::collections::HashMap;
+
+// A single character cell.
+// The background colour is palette entry 0, and the foreground colour is palette entry 1.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: Colour,
+    pub bg: Colour
+}
+
+// Grid of character cells, with a cursor.
+// Each distinct (background, foreground) pair is allocated a two-entry palette.
+pub struct TextGrid {
+    cells: Vec<Cell>,
+    cell_palettes: Vec<usize>,
+    dirty: Vec<bool>,
+    width: usize,
+    height: usize,
+
+    glyphs: HashMap<char, (usize, usize)>,
+    fallback_glyph: (usize, usize),
+
+    palettes: Vec<[Colour; 2]>,
+    palette_refs: Vec<usize>,
+    max_palettes: usize,
+
+    fg: Colour,
+    bg: Colour,
+
+    cursor: (usize, usize),
+    cursor_visible: bool,
+    drawn_cursor: Option<((usize, usize), usize)>,
+    // False if the drawn cursor's colours were approximated.
+    cursor_exact: bool
+}
+
+impl TextGrid {
+    // Make a new text grid of size (width * height) cells.
+    // Max palettes: the number of palettes available for colour pairs.
+    pub fn new(width: usize, height: usize, max_palettes: usize) -> Self {
+        let fg = Vector4::new(1.0, 1.0, 1.0, 1.0);
+        let bg = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let area = width * height;
+
+        let mut palette_refs = Vec::new();
+        if max_palettes > 0 {
+            palette_refs.push(area);
+        }
+
+        TextGrid {
+            cells: vec![Cell{ ch: ' ', fg: fg, bg: bg }; area],
+            cell_palettes: vec![0; area],
+            dirty: vec![true; area],
+            width: width,
+            height: height,
+
+            glyphs: HashMap::new(),
+            fallback_glyph: (0, 0),
+
+            palettes: if max_palettes > 0 { vec![[bg, fg]] } else { Vec::new() },
+            palette_refs: palette_refs,
+            max_palettes: max_palettes,
+
+            fg: fg,
+            bg: bg,
+
+            cursor: (0, 0),
+            cursor_visible: false,
+            drawn_cursor: None,
+            cursor_exact: true
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Map a character to a glyph tile in the atlas.
+    pub fn set_glyph(&mut self, ch: char, tex_x: usize, tex_y: usize) {
+        self.glyphs.insert(ch, (tex_x, tex_y));
+        self.invalidate();
+    }
+
+    // Map characters to glyph tiles, e.g. from a loaded font.
+    pub fn set_glyphs(&mut self, glyphs: &HashMap<char, (usize, usize)>) {
+        self.glyphs.extend(glyphs.iter().map(|(c, slot)| (*c, *slot)));
+        self.invalidate();
+    }
+
+    // Map a run of characters onto the atlas, starting at the first slot and continuing in row-major order.
+    pub fn set_glyph_range(&mut self, first: char, atlas: &TextureAtlas) {
+        let atlas_size = atlas.atlas_size();
+        for slot in 0..(atlas_size * atlas_size) {
+            if let Some(ch) = std::char::from_u32(first as u32 + slot as u32) {
+                self.glyphs.insert(ch, (slot % atlas_size, slot / atlas_size));
+            }
+        }
+        self.invalidate();
+    }
+
+    // Set the glyph tile used for characters that have no mapping.
+    pub fn set_fallback_glyph(&mut self, tex_x: usize, tex_y: usize) {
+        self.fallback_glyph = (tex_x, tex_y);
+        self.invalidate();
+    }
+
+    // Set the colours used for subsequent writes.
+    pub fn set_colours(&mut self, fg: Colour, bg: Colour) {
+        self.fg = fg;
+        self.bg = bg;
+    }
+
+    pub fn colours(&self) -> (Colour, Colour) {
+        (self.fg, self.bg)
+    }
+
+    pub fn set_cursor(&mut self, x: usize, y: usize) {
+        self.cursor = (x.min(self.width.saturating_sub(1)), y.min(self.height.saturating_sub(1)));
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        self.cursor
+    }
+
+    // The cursor is displayed by swapping the colours of the cell underneath it.
+    pub fn show_cursor(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+    }
+
+    // The cell at (x, y), or None if it is outside the grid.
+    pub fn cell(&self, x: usize, y: usize) -> Option<&Cell> {
+        if (x >= self.width) || (y >= self.height) {
+            return None;
+        }
+        self.cells.get(y * self.width + x)
+    }
+
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    // Get the characters in a row as a string.
+    pub fn row_text(&self, y: usize) -> String {
+        let start = y * self.width;
+        self.cells[start..(start + self.width)].iter().map(|c| c.ch).collect()
+    }
+
+    // Set a cell with explicit colours.
+    // Returns false if all palettes are in use and the cell is drawn with the nearest existing colour pair instead.
+    pub fn set_cell(&mut self, x: usize, y: usize, cell: Cell) -> bool {
+        if (x >= self.width) || (y >= self.height) {
+            return true;
+        }
+
+        let index = y * self.width + x;
+        let (palette, exact) = self.acquire_palette(cell.bg, cell.fg);
+        self.release_palette(self.cell_palettes[index]);
+
+        self.cells[index] = cell;
+        self.cell_palettes[index] = palette;
+        self.dirty[index] = true;
+        exact
+    }
+
+    // Set a cell with the current colours. Returns false if the colours had to be approximated, as with set_cell.
+    pub fn put_char(&mut self, x: usize, y: usize, ch: char) -> bool {
+        let cell = Cell{ ch: ch, fg: self.fg, bg: self.bg };
+        self.set_cell(x, y, cell)
+    }
+
+    // Write a string starting at (x, y), with the current colours.
+    // Text is laid out in a block from column x: both wrapping at the right edge and a newline or carriage return
+    // go back to column x, and the grid scrolls up when the bottom is reached. The cursor is left after the last character.
+    // Returns false if any character's colours had to be approximated, as with set_cell.
+    pub fn write_str(&mut self, x: usize, y: usize, s: &str) -> bool {
+        if (self.width == 0) || (self.height == 0) {
+            return true;
+        }
+
+        let start_x = x.min(self.width - 1);
+        let (mut x, mut y) = (start_x, y.min(self.height - 1));
+        let mut exact = true;
+
+        for ch in s.chars() {
+            match ch {
+                '\n' => {
+                    x = start_x;
+                    y = self.next_row(y);
+                },
+                '\r' => x = start_x,
+                _ => {
+                    if x >= self.width {
+                        x = start_x;
+                        y = self.next_row(y);
+                    }
+                    exact &= self.put_char(x, y, ch);
+                    x += 1;
+                }
+            }
+        }
+
+        self.cursor = (x.min(self.width - 1), y);
+        exact
+    }
+
+    // Clear all cells to spaces, in the current colours.
+    pub fn clear(&mut self) {
+        for y in 0..self.height {
+            self.clear_row(y);
+        }
+    }
+
+    // Clear a single row to spaces, in the current colours.
+    pub fn clear_row(&mut self, y: usize) {
+        for x in 0..self.width {
+            self.put_char(x, y, ' ');
+        }
+    }
+
+    // Scroll the rows in [top, bottom) up by n. New rows are cleared.
+    pub fn scroll_up(&mut self, top: usize, bottom: usize, n: usize) {
+        let bottom = bottom.min(self.height);
+        if top >= bottom {
+            return;
+        }
+        let n = n.min(bottom - top);
+
+        for y in top..(bottom - n) {
+            for x in 0..self.width {
+                let cell = self.cells[(y + n) * self.width + x];
+                self.set_cell(x, y, cell);
+            }
+        }
+        for y in (bottom - n)..bottom {
+            self.clear_row(y);
+        }
+    }
+
+    // Scroll the rows in [top, bottom) down by n. New rows are cleared.
+    pub fn scroll_down(&mut self, top: usize, bottom: usize, n: usize) {
+        let bottom = bottom.min(self.height);
+        if top >= bottom {
+            return;
+        }
+        let n = n.min(bottom - top);
+
+        for y in ((top + n)..bottom).rev() {
+            for x in 0..self.width {
+                let cell = self.cells[(y - n) * self.width + x];
+                self.set_cell(x, y, cell);
+            }
+        }
+        for y in top..(top + n) {
+            self.clear_row(y);
+        }
+    }
+
+    // Palettes used by the grid. Entry 0 is the background colour and entry 1 is the foreground colour.
+    pub fn palettes(&self) -> &[[Colour; 2]] {
+        &self.palettes
+    }
+
+    // Write changed cells into the vertex grid. The vertex grid must be the same size as the text grid.
+    // Returns false if all palettes are in use and the cursor is drawn with the nearest colour pair to its inverted colours.
+    pub fn update(&mut self, vertex_grid: &mut VertexGrid) -> bool {
+        let cursor = if self.cursor_visible && !self.cells.is_empty() { Some(self.cursor) } else { None };
+
+        // Restore the cell under the previously drawn cursor if it has moved or changed.
+        if let Some(((x, y), palette)) = self.drawn_cursor {
+            let index = y * self.width + x;
+            if (cursor != Some((x, y))) || self.dirty[index] {
+                self.release_palette(palette);
+                self.dirty[index] = true;
+                self.drawn_cursor = None;
+                self.cursor_exact = true;
+            }
+        }
+
+        for (i, dirty) in self.dirty.iter_mut().enumerate() {
+            if *dirty {
+                let (x, y) = (i % self.width, i / self.width);
+                let (tex_x, tex_y) = *self.glyphs.get(&self.cells[i].ch).unwrap_or(&self.fallback_glyph);
+
+                vertex_grid.set_tile_texture(x, y, tex_x, tex_y);
+                vertex_grid.set_tile_palette(x, y, self.cell_palettes[i] as u32);
+                *dirty = false;
+            }
+        }
+
+        if let (Some((x, y)), None) = (cursor, self.drawn_cursor) {
+            let cell = self.cells[y * self.width + x];
+            let (palette, exact) = self.acquire_palette(cell.fg, cell.bg);
+
+            vertex_grid.set_tile_palette(x, y, palette as u32);
+            self.drawn_cursor = Some(((x, y), palette));
+            self.cursor_exact = exact;
+        }
+        self.cursor_exact
+    }
+}
+
+// Internal
+impl TextGrid {
+    // Mark every cell as needing to be written to the vertex grid.
+    fn invalidate(&mut self) {
+        for d in self.dirty.iter_mut() {
+            *d = true;
+        }
+    }
+
+    // Move to the next row, scrolling if at the bottom.
+    fn next_row(&mut self, y: usize) -> usize {
+        if y + 1 >= self.height {
+            self.scroll_up(0, self.height, 1);
+            y
+        } else {
+            y + 1
+        }
+    }
+
+    // Find or allocate a palette for a colour pair, and take a reference to it.
+    // If all palettes are in use, the palette nearest to the pair is used and false is returned.
+    fn acquire_palette(&mut self, bg: Colour, fg: Colour) -> (usize, bool) {
+        let (index, exact) = if let Some(i) = self.palettes.iter().position(|p| (p[0] == bg) && (p[1] == fg)) {
+            (i, true)
+        } else if let Some(i) = self.palette_refs.iter().position(|r| *r == 0) {
+            self.palettes[i] = [bg, fg];
+            (i, true)
+        } else if self.palettes.len() < self.max_palettes {
+            self.palettes.push([bg, fg]);
+            self.palette_refs.push(0);
+            (self.palettes.len() - 1, true)
+        } else {
+            (self.nearest_palette(bg, fg), false)
+        };
+
+        if let Some(r) = self.palette_refs.get_mut(index) {
+            *r += 1;
+        }
+        (index, exact)
+    }
+
+    // The existing palette closest to a colour pair, by squared distance over both colours.
+    fn nearest_palette(&self, bg: Colour, fg: Colour) -> usize {
+        let distance = |a: Colour, b: Colour| {
+            let d = a - b;
+            d.x * d.x + d.y * d.y + d.z * d.z + d.w * d.w
+        };
+        self.palettes.iter()
+            .map(|p| distance(p[0], bg) + distance(p[1], fg))
+            .enumerate()
+            .fold((0, f32::INFINITY), |best, (i, d)| if d < best.1 { (i, d) } else { best })
+            .0
+    }
+
+    fn release_palette(&mut self, index: usize) {
+        if let Some(r) = self.palette_refs.get_mut(index) {
+            *r = r.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrapping_and_newlines_return_to_start_column() {
+        let mut grid = TextGrid::new(6, 3, 1);
+        grid.write_str(2, 0, "abcdef\rg\nh");
+        assert_eq!(grid.row_text(0), "  abcd");
+        assert_eq!(grid.row_text(1), "  gf  ");
+        assert_eq!(grid.row_text(2), "  h   ");
+        assert_eq!(grid.cursor(), (3, 2));
+    }
+
+    #[test]
+    fn full_palettes_use_the_nearest_pair() {
+        let black = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let white = Vector4::new(1.0, 1.0, 1.0, 1.0);
+        let red = Vector4::new(1.0, 0.0, 0.0, 1.0);
+        let dark_red = Vector4::new(0.8, 0.0, 0.0, 1.0);
+
+        let mut grid = TextGrid::new(4, 1, 2);
+        grid.set_colours(red, black);
+        assert!(grid.put_char(0, 0, 'a'));
+        grid.set_colours(dark_red, black);
+        assert!(!grid.put_char(1, 0, 'b'));
+        assert_eq!(grid.cell_palettes[1], grid.cell_palettes[0]);
+        assert_eq!(grid.palettes()[grid.cell_palettes[1]], [black, red]);
+
+        grid.set_colours(white, black);
+        assert!(grid.put_char(2, 0, 'c'));
+        assert_eq!(grid.palettes().len(), 2);
+    }
+
+    #[test]
+    fn cells_outside_the_grid_are_none() {
+        let mut grid = TextGrid::new(3, 2, 1);
+        grid.put_char(2, 1, 'z');
+        assert!(grid.cell(2, 1).is_some_and(|c| c.ch == 'z'));
+        assert!(grid.cell(3, 0).is_none());
+        assert!(grid.cell(0, 2).is_none());
+    }
+}
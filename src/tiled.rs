@@ -0,0 +1,576 @@
+// Import maps made with the Tiled map editor, in TMX (XML) or JSON format.
+// Tileset images are loaded into the atlas with tilesheet import, and each tile layer becomes a TileMap.
+// Only orthogonal, finite maps with PNG tileset images are supported.
+use super::{
+    imagegen::TextureAtlas,
+    palette::Palette,
+    tilemap::{
+        MapTile,
+        TileMap
+    },
+    tilesheet::{
+        self,
+        ImportError,
+        ImportOptions,
+        RgbaImage
+    },
+    vertexgrid::Flip
+};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs,
+    io::{
+        self,
+        Read
+    },
+    path::{
+        Path,
+        PathBuf
+    }
+};
+
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+const ROTATED_HEXAGONAL: u32 = 0x1000_0000;
+const GID_MASK: u32 = !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL);
+
+#[derive(Debug)]
+pub enum TiledError {
+    Io(io::Error),
+    Xml(String),
+    Json(serde_json::Error),
+    Import(ImportError),
+    Format(String)
+}
+
+impl fmt::Display for TiledError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TiledError::Io(e)       => write!(f, "IO error: {}", e),
+            TiledError::Xml(e)      => write!(f, "XML error: {}", e),
+            TiledError::Json(e)     => write!(f, "JSON error: {}", e),
+            TiledError::Import(e)   => write!(f, "Tileset import error: {}", e),
+            TiledError::Format(s)   => write!(f, "Invalid map: {}", s)
+        }
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+impl From<io::Error> for TiledError {
+    fn from(e: io::Error) -> Self {
+        TiledError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for TiledError {
+    fn from(e: serde_json::Error) -> Self {
+        TiledError::Json(e)
+    }
+}
+
+impl From<ImportError> for TiledError {
+    fn from(e: ImportError) -> Self {
+        TiledError::Import(e)
+    }
+}
+
+fn format_err<T>(msg: &str) -> Result<T, TiledError> {
+    Err(TiledError::Format(msg.to_string()))
+}
+
+pub struct TiledLayer {
+    pub name: String,
+    // Hidden layers, or layers in hidden groups, are left out when the map is flattened.
+    pub visible: bool,
+    pub tilemap: TileMap
+}
+
+// A loaded map. Tile layers are listed bottom to top.
+pub struct TiledMap {
+    pub width: usize,
+    pub height: usize,
+    pub layers: Vec<TiledLayer>,
+    pub palettes: Vec<Palette>,
+    // Number of atlas slots used, starting at the first slot in the import options.
+    pub slots_used: usize,
+    // Tile used for empty cells.
+    pub blank: MapTile,
+    // Files the map was loaded from: the map itself, external tilesets and tileset images.
+    pub files: Vec<PathBuf>
+}
+
+impl TiledMap {
+    // Combine all visible layers into a single map. Empty cells show the layer below.
+    pub fn flatten(&self) -> TileMap {
+        let mut tilemap = TileMap::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let tile = self.layers.iter().rev()
+                    .filter(|l| l.visible && (x < l.tilemap.width) && (y < l.tilemap.height))
+                    .map(|l| *l.tilemap.tile(x, y))
+                    .find(|t| *t != self.blank)
+                    .unwrap_or(self.blank);
+                tilemap.set_tile(x, y, tile);
+            }
+        }
+        tilemap
+    }
+}
+
+// A tileset, before its image is loaded.
+struct Tileset {
+    first_gid: u32,
+    image: PathBuf,
+    tile_width: usize,
+    tile_height: usize,
+    margin: usize,
+    spacing: usize,
+    columns: Option<usize>,
+    tile_count: Option<usize>,
+    // External tileset file, if the tileset isn't embedded in the map.
+    source: Option<PathBuf>
+}
+
+// A tile layer, before gids are resolved.
+struct Layer {
+    name: String,
+    visible: bool,
+    width: usize,
+    height: usize,
+    gids: Vec<u32>
+}
+
+// Load a map. The format is chosen by the file extension: .json for JSON, otherwise TMX.
+// Empty cells use a blank texture in the first slot, so tilesets are loaded from the slot after.
+pub fn load(path: &Path, atlas: &mut TextureAtlas, options: &ImportOptions) -> Result<TiledMap, TiledError> {
+    let text = fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let (width, height, tilesets, layers) = if path.extension().is_some_and(|e| e == "json") {
+        parse_json_map(&text, dir)?
+    } else {
+        parse_tmx_map(&text, dir)?
+    };
+
+    let mut map = build_map(width, height, tilesets, layers, atlas, options)?;
+    map.files.insert(0, path.to_path_buf());
+    Ok(map)
+}
+
+// Load tileset images and resolve layer gids into tiles.
+fn build_map(width: usize, height: usize, mut tilesets: Vec<Tileset>, layers: Vec<Layer>, atlas: &mut TextureAtlas, options: &ImportOptions) -> Result<TiledMap, TiledError> {
+    let atlas_size = atlas.atlas_size();
+    let tex_size = atlas.tex_size();
+
+    // Reserve a blank tile for empty cells.
+    let blank_slot = options.first_slot;
+    if blank_slot >= atlas_size * atlas_size {
+        return Err(TiledError::Import(ImportError::AtlasFull{ needed: 1, available: 0 }));
+    }
+    for y in 0..tex_size {
+        for x in 0..tex_size {
+            atlas.set_texel(blank_slot % atlas_size, blank_slot / atlas_size, x, y, 0);
+        }
+    }
+    let blank = MapTile {
+        tex_x: blank_slot % atlas_size,
+        tex_y: blank_slot / atlas_size,
+        palette: 0,
+        flip: Flip::default()
+    };
+
+    // Import each tileset, mapping each gid to a tile.
+    tilesets.sort_by_key(|t| t.first_gid);
+    let mut gid_tiles = HashMap::new();
+    let mut palettes = Vec::new();
+    let mut next_slot = blank_slot + 1;
+
+    for tileset in tilesets.iter() {
+        if (tileset.tile_width != tex_size) || (tileset.tile_height != tex_size) {
+            return format_err(&format!("tileset tile size {}x{} does not match atlas texture size {}",
+                tileset.tile_width, tileset.tile_height, tex_size));
+        }
+
+        let (image, columns) = load_tileset_image(tileset)?;
+        let tileset_options = ImportOptions {
+            first_slot: next_slot,
+            .. *options
+        };
+        let sheet = tilesheet::import_image(&image, atlas, &tileset_options)?;
+
+        let palette_offset = palettes.len() as u32;
+        let tile_count = tileset.tile_count.unwrap_or(sheet.tilemap.tiles.len()).min(sheet.tilemap.tiles.len());
+        for id in 0..tile_count {
+            let mut tile = *sheet.tilemap.tile(id % columns, id / columns);
+            tile.palette += palette_offset;
+            gid_tiles.insert(tileset.first_gid + id as u32, tile);
+        }
+
+        palettes.extend(sheet.palettes);
+        next_slot += sheet.slots_used;
+    }
+
+    let layers = layers.into_iter().map(|layer| {
+        let mut tilemap = TileMap::new(layer.width, layer.height);
+        for (i, raw_gid) in layer.gids.iter().enumerate() {
+            let gid = raw_gid & GID_MASK;
+            let tile = if gid == 0 {
+                blank
+            } else {
+                let mut tile = *gid_tiles.get(&gid).ok_or_else(|| TiledError::Format(format!("unknown tile gid {}", gid)))?;
+                tile.flip = Flip {
+                    x: (raw_gid & FLIPPED_HORIZONTALLY) != 0,
+                    y: (raw_gid & FLIPPED_VERTICALLY) != 0,
+                    diagonal: (raw_gid & FLIPPED_DIAGONALLY) != 0
+                };
+                tile
+            };
+            tilemap.set_tile(i % layer.width, i / layer.width, tile);
+        }
+        Ok(TiledLayer {
+            name: layer.name,
+            visible: layer.visible,
+            tilemap: tilemap
+        })
+    }).collect::<Result<Vec<_>, TiledError>>()?;
+
+    let files = tilesets.into_iter()
+        .flat_map(|t| t.source.into_iter().chain(Some(t.image)))
+        .collect();
+
+    Ok(TiledMap {
+        width: width,
+        height: height,
+        layers: layers,
+        palettes: palettes,
+        slots_used: next_slot - blank_slot,
+        blank: blank,
+        files: files
+    })
+}
+
+// Load a tileset image, removing margins and spacing so tiles are packed together.
+// Returns the image and the number of tile columns.
+fn load_tileset_image(tileset: &Tileset) -> Result<(RgbaImage, usize), TiledError> {
+    let image = RgbaImage::load_png(&tileset.image)?;
+    let (tw, th) = (tileset.tile_width, tileset.tile_height);
+    let fit = |size: usize, tile: usize| (size.saturating_sub(2 * tileset.margin) + tileset.spacing) / (tile + tileset.spacing);
+
+    let columns = tileset.columns.unwrap_or_else(|| fit(image.width, tw));
+    let rows = fit(image.height, th);
+    if (columns == 0) || (rows == 0) {
+        return format_err("tileset image is smaller than a tile");
+    }
+
+    let mut pixels = Vec::with_capacity(columns * tw * rows * th);
+    for y in 0..(rows * th) {
+        let src_y = tileset.margin + (y / th) * (th + tileset.spacing) + (y % th);
+        for x in 0..(columns * tw) {
+            let src_x = tileset.margin + (x / tw) * (tw + tileset.spacing) + (x % tw);
+            let pixel = if (src_x < image.width) && (src_y < image.height) {
+                image.pixels[src_y * image.width + src_x]
+            } else {
+                [0, 0, 0, 0]
+            };
+            pixels.push(pixel);
+        }
+    }
+
+    Ok((RgbaImage {
+        width: columns * tw,
+        height: rows * th,
+        pixels: pixels
+    }, columns))
+}
+
+// Decode layer data that is base64 encoded, and optionally compressed.
+fn decode_base64(data: &str, compression: Option<&str>) -> Result<Vec<u32>, TiledError> {
+    let bytes = base64::decode(data.trim()).or_else(|e| format_err(&format!("bad base64 data: {}", e)))?;
+    let bytes = match compression {
+        None | Some("") => bytes,
+        Some("zlib") => {
+            let mut out = Vec::new();
+            flate2::read::ZlibDecoder::new(&bytes[..]).read_to_end(&mut out)?;
+            out
+        },
+        Some("gzip") => {
+            let mut out = Vec::new();
+            flate2::read::GzDecoder::new(&bytes[..]).read_to_end(&mut out)?;
+            out
+        },
+        Some(c) => return format_err(&format!("unsupported compression '{}'", c))
+    };
+
+    Ok(bytes.chunks_exact(4)
+        .map(|b| u32::from(b[0]) | (u32::from(b[1]) << 8) | (u32::from(b[2]) << 16) | (u32::from(b[3]) << 24))
+        .collect())
+}
+
+fn decode_csv(data: &str) -> Result<Vec<u32>, TiledError> {
+    data.split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<u32>().or_else(|_| format_err(&format!("bad tile gid '{}'", s))))
+        .collect()
+}
+
+fn check_layer(layer: Layer) -> Result<Layer, TiledError> {
+    if layer.gids.len() != layer.width * layer.height {
+        format_err(&format!("layer '{}' has {} tiles, expected {}", layer.name, layer.gids.len(), layer.width * layer.height))
+    } else {
+        Ok(layer)
+    }
+}
+
+// TMX
+
+// Minimal XML element tree.
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+    text: String
+}
+
+impl Element {
+    fn parse(text: &str) -> Result<Element, TiledError> {
+        use xml::reader::{EventReader, XmlEvent};
+
+        let mut stack: Vec<Element> = Vec::new();
+        for event in EventReader::from_str(text) {
+            match event.map_err(|e| TiledError::Xml(e.to_string()))? {
+                XmlEvent::StartElement{ name, attributes, .. } => stack.push(Element {
+                    name: name.local_name,
+                    attributes: attributes.into_iter().map(|a| (a.name.local_name, a.value)).collect(),
+                    children: Vec::new(),
+                    text: String::new()
+                }),
+                XmlEvent::EndElement{ .. } => {
+                    let element = stack.pop().unwrap();
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element)
+                    }
+                },
+                XmlEvent::Characters(s) | XmlEvent::CData(s) => if let Some(e) = stack.last_mut() {
+                    e.text.push_str(&s);
+                },
+                _ => {}
+            }
+        }
+        Err(TiledError::Xml("no root element".to_string()))
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(|s| s.as_str())
+    }
+
+    fn attr_num<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, TiledError> {
+        match self.attr(name) {
+            Some(s) => s.parse::<T>().map(Some).or_else(|_| format_err(&format!("bad number for '{}' in <{}>", name, self.name))),
+            None => Ok(None)
+        }
+    }
+
+    fn required_num<T: std::str::FromStr>(&self, name: &str) -> Result<T, TiledError> {
+        self.attr_num(name)?.ok_or_else(|| TiledError::Format(format!("<{}> is missing '{}'", self.name, name)))
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+}
+
+fn parse_tmx_map(text: &str, dir: &Path) -> Result<(usize, usize, Vec<Tileset>, Vec<Layer>), TiledError> {
+    let map = Element::parse(text)?;
+    if map.name != "map" {
+        return format_err("root element is not <map>");
+    }
+    if map.attr("orientation").is_some_and(|o| o != "orthogonal") {
+        return format_err("only orthogonal maps are supported");
+    }
+    if map.attr("infinite") == Some("1") {
+        return format_err("infinite maps are not supported");
+    }
+
+    let mut tilesets = Vec::new();
+    for element in map.children.iter().filter(|c| c.name == "tileset") {
+        let first_gid = element.required_num("firstgid")?;
+        tilesets.push(match element.attr("source") {
+            Some(source) => load_external_tileset(&dir.join(source), first_gid)?,
+            None => parse_tmx_tileset(element, dir, first_gid)?
+        });
+    }
+
+    let mut layers = Vec::new();
+    collect_tmx_layers(&map, true, &mut layers)?;
+
+    Ok((map.required_num("width")?, map.required_num("height")?, tilesets, layers))
+}
+
+// Parse a tileset from a <tileset> element. Image paths are relative to dir.
+fn parse_tmx_tileset(element: &Element, dir: &Path, first_gid: u32) -> Result<Tileset, TiledError> {
+    let image = element.child("image").ok_or_else(|| TiledError::Format("tileset has no image".to_string()))?;
+    let source = image.attr("source").ok_or_else(|| TiledError::Format("tileset image has no source".to_string()))?;
+
+    Ok(Tileset {
+        first_gid: first_gid,
+        image: dir.join(source),
+        tile_width: element.required_num("tilewidth")?,
+        tile_height: element.required_num("tileheight")?,
+        margin: element.attr_num("margin")?.unwrap_or(0),
+        spacing: element.attr_num("spacing")?.unwrap_or(0),
+        columns: element.attr_num("columns")?,
+        tile_count: element.attr_num("tilecount")?,
+        source: None
+    })
+}
+
+// Layers may be nested inside groups. Visible: whether the parent is visible.
+fn collect_tmx_layers(parent: &Element, visible: bool, layers: &mut Vec<Layer>) -> Result<(), TiledError> {
+    for element in parent.children.iter() {
+        let visible = visible && (element.attr("visible") != Some("0"));
+        match element.name.as_str() {
+            "layer" => {
+                let data = element.child("data").ok_or_else(|| TiledError::Format("layer has no data".to_string()))?;
+                if data.child("chunk").is_some() {
+                    return format_err("infinite maps are not supported");
+                }
+
+                let gids = match data.attr("encoding") {
+                    Some("csv") => decode_csv(&data.text)?,
+                    Some("base64") => decode_base64(&data.text, data.attr("compression"))?,
+                    None => data.children.iter()
+                        .filter(|c| c.name == "tile")
+                        .map(|c| c.attr_num("gid").map(|g| g.unwrap_or(0)))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    Some(e) => return format_err(&format!("unsupported encoding '{}'", e))
+                };
+
+                layers.push(check_layer(Layer {
+                    name: element.attr("name").unwrap_or("").to_string(),
+                    visible: visible,
+                    width: element.required_num("width")?,
+                    height: element.required_num("height")?,
+                    gids: gids
+                })?);
+            },
+            "group" => collect_tmx_layers(element, visible, layers)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Load a tileset from a separate file. TSX and JSON tilesets are supported.
+fn load_external_tileset(path: &Path, first_gid: u32) -> Result<Tileset, TiledError> {
+    let text = fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut tileset = if path.extension().is_some_and(|e| e == "json") {
+        let value = serde_json::from_str::<serde_json::Value>(&text)?;
+        parse_json_tileset(&value, dir, first_gid)?
+    } else {
+        let element = Element::parse(&text)?;
+        if element.name != "tileset" {
+            return format_err("root element is not <tileset>");
+        }
+        parse_tmx_tileset(&element, dir, first_gid)?
+    };
+    tileset.source = Some(path.to_path_buf());
+    Ok(tileset)
+}
+
+// JSON
+
+fn json_num(value: &serde_json::Value, name: &str) -> Result<usize, TiledError> {
+    value.get(name)
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize)
+        .ok_or_else(|| TiledError::Format(format!("missing or invalid '{}'", name)))
+}
+
+fn json_opt_num(value: &serde_json::Value, name: &str) -> Option<usize> {
+    value.get(name).and_then(|v| v.as_u64()).map(|v| v as usize)
+}
+
+fn parse_json_map(text: &str, dir: &Path) -> Result<(usize, usize, Vec<Tileset>, Vec<Layer>), TiledError> {
+    let map = serde_json::from_str::<serde_json::Value>(text)?;
+    if map.get("orientation").and_then(|o| o.as_str()).is_some_and(|o| o != "orthogonal") {
+        return format_err("only orthogonal maps are supported");
+    }
+    if map.get("infinite").and_then(|i| i.as_bool()) == Some(true) {
+        return format_err("infinite maps are not supported");
+    }
+
+    let mut tilesets = Vec::new();
+    for value in map.get("tilesets").and_then(|t| t.as_array()).into_iter().flatten() {
+        let first_gid = json_num(value, "firstgid")? as u32;
+        tilesets.push(match value.get("source").and_then(|s| s.as_str()) {
+            Some(source) => load_external_tileset(&dir.join(source), first_gid)?,
+            None => parse_json_tileset(value, dir, first_gid)?
+        });
+    }
+
+    let mut layers = Vec::new();
+    if let Some(l) = map.get("layers").and_then(|l| l.as_array()) {
+        collect_json_layers(l, true, &mut layers)?;
+    }
+
+    Ok((json_num(&map, "width")?, json_num(&map, "height")?, tilesets, layers))
+}
+
+fn parse_json_tileset(value: &serde_json::Value, dir: &Path, first_gid: u32) -> Result<Tileset, TiledError> {
+    let image = value.get("image").and_then(|i| i.as_str())
+        .ok_or_else(|| TiledError::Format("tileset has no image".to_string()))?;
+
+    Ok(Tileset {
+        first_gid: first_gid,
+        image: dir.join(image),
+        tile_width: json_num(value, "tilewidth")?,
+        tile_height: json_num(value, "tileheight")?,
+        margin: json_opt_num(value, "margin").unwrap_or(0),
+        spacing: json_opt_num(value, "spacing").unwrap_or(0),
+        columns: json_opt_num(value, "columns"),
+        tile_count: json_opt_num(value, "tilecount"),
+        source: None
+    })
+}
+
+fn collect_json_layers(values: &[serde_json::Value], visible: bool, layers: &mut Vec<Layer>) -> Result<(), TiledError> {
+    for value in values.iter() {
+        let visible = visible && value.get("visible").and_then(|v| v.as_bool()).unwrap_or(true);
+        match value.get("type").and_then(|t| t.as_str()) {
+            Some("tilelayer") => {
+                if value.get("chunks").is_some() {
+                    return format_err("infinite maps are not supported");
+                }
+
+                let gids = match value.get("data") {
+                    Some(serde_json::Value::Array(a)) => a.iter()
+                        .map(|g| g.as_u64().map(|g| g as u32).ok_or_else(|| TiledError::Format("bad tile gid".to_string())))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    Some(serde_json::Value::String(s)) => decode_base64(s, value.get("compression").and_then(|c| c.as_str()))?,
+                    _ => return format_err("layer has no data")
+                };
+
+                layers.push(check_layer(Layer {
+                    name: value.get("name").and_then(|n| n.as_str()).unwrap_or("").to_string(),
+                    visible: visible,
+                    width: json_num(value, "width")?,
+                    height: json_num(value, "height")?,
+                    gids: gids
+                })?);
+            },
+            Some("group") => if let Some(l) = value.get("layers").and_then(|l| l.as_array()) {
+                collect_json_layers(l, visible, layers)?;
+            },
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+// Map of tiles, describing the texture and palette of each tile in a grid.
+use super::vertexgrid::{
+    Flip,
+    TileGrid,
+    VertexGrid
+};
+
+#[derive(Copy, Clone, Default, PartialEq)]
+pub struct MapTile {
+    pub tex_x: usize,
+    pub tex_y: usize,
+    pub palette: u32,
+    pub flip: Flip
+}
+
+pub struct TileMap {
+    pub width: usize,
+    pub height: usize,
+    pub tiles: Vec<MapTile>
+}
+
+impl TileMap {
+    pub fn new(width: usize, height: usize) -> Self {
+        TileMap {
+            width: width,
+            height: height,
+            tiles: vec![MapTile::default(); width * height]
+        }
+    }
+
+    pub fn tile(&self, x: usize, y: usize) -> &MapTile {
+        &self.tiles[y * self.width + x]
+    }
+
+    pub fn set_tile(&mut self, x: usize, y: usize, tile: MapTile) {
+        self.tiles[y * self.width + x] = tile;
+    }
+
+    // Make a map from the current state of a vertex grid.
+    pub fn from_vertex_grid(vertex_grid: &VertexGrid) -> Self {
+        let mut map = TileMap::new(vertex_grid.width(), vertex_grid.height());
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let (tex_x, tex_y) = vertex_grid.tile_texture(x, y);
+                map.set_tile(x, y, MapTile {
+                    tex_x: tex_x,
+                    tex_y: tex_y,
+                    palette: vertex_grid.tile_palette(x, y),
+                    flip: vertex_grid.tile_flip(x, y)
+                });
+            }
+        }
+        map
+    }
+
+    // Replace palettes past a limit, e.g. the palettes the renderer holds, with palette 0.
+    // Returns the number of tiles changed.
+    pub fn clamp_palettes(&mut self, palettes: usize) -> usize {
+        let mut clamped = 0;
+        for tile in self.tiles.iter_mut().filter(|t| t.palette as usize >= palettes) {
+            tile.palette = 0;
+            clamped += 1;
+        }
+        clamped
+    }
+
+    // Set the textures and palettes of a vertex grid from the map.
+    // Only the area covered by both the grid and the map is written.
+    pub fn apply(&self, vertex_grid: &mut VertexGrid) {
+        for y in 0..self.height.min(vertex_grid.height()) {
+            for x in 0..self.width.min(vertex_grid.width()) {
+                let tile = self.tile(x, y);
+                vertex_grid.set_tile_texture_flipped(x, y, tile.tex_x, tile.tex_y, tile.flip);
+                vertex_grid.set_tile_palette(x, y, tile.palette);
+            }
+        }
+    }
+}
+
+impl TileGrid for TileMap {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn tile_texture(&self, tile_x: usize, tile_y: usize) -> (usize, usize) {
+        let tile = self.tile(tile_x, tile_y);
+        (tile.tex_x, tile.tex_y)
+    }
+
+    fn tile_flip(&self, tile_x: usize, tile_y: usize) -> Flip {
+        self.tile(tile_x, tile_y).flip
+    }
+
+    fn tile_palette(&self, tile_x: usize, tile_y: usize) -> u32 {
+        self.tile(tile_x, tile_y).palette
+    }
+
+    fn set_tile_texture_flipped(&mut self, tile_x: usize, tile_y: usize, tex_x: usize, tex_y: usize, flip: Flip) {
+        let tile = &mut self.tiles[tile_y * self.width + tile_x];
+        tile.tex_x = tex_x;
+        tile.tex_y = tex_y;
+        tile.flip = flip;
+    }
+
+    fn set_tile_palette(&mut self, tile_x: usize, tile_y: usize, palette_index: u32) {
+        self.tiles[tile_y * self.width + tile_x].palette = palette_index;
+    }
+}
@@ -0,0 +1,373 @@
+// Import tile sheets from PNG images.
+// The image is sliced into tiles, each tile's colours are reduced to a palette of the configured bit depth,
+// and the indexed texels are written into atlas slots.
+use super::{
+    imagegen::TextureAtlas,
+    palette::{
+        self,
+        Palette
+    },
+    tilemap::{
+        MapTile,
+        TileMap
+    }
+};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io,
+    path::Path
+};
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    Decode(png::DecodingError),
+    // The image dimensions are not a multiple of the tile size.
+    Size{ width: usize, height: usize },
+    // The atlas textures have no texels to import into.
+    EmptyTextures,
+    // There are not enough free atlas slots for all unique tiles.
+    AtlasFull{ needed: usize, available: usize }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Io(e)                          => write!(f, "IO error: {}", e),
+            ImportError::Decode(e)                      => write!(f, "PNG decode error: {}", e),
+            ImportError::Size{ width, height }          => write!(f, "Image size {}x{} is not a multiple of the tile size", width, height),
+            ImportError::EmptyTextures                  => write!(f, "Atlas texture size is 0"),
+            ImportError::AtlasFull{ needed, available } => write!(f, "Atlas full: {} tiles needed, {} slots available", needed, available)
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl From<png::DecodingError> for ImportError {
+    fn from(e: png::DecodingError) -> Self {
+        ImportError::Decode(e)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct ImportOptions {
+    // Bits per texel. Each palette has 2^bit_depth colours.
+    pub bit_depth: u8,
+    // First atlas slot to write to, in row-major order.
+    pub first_slot: usize,
+    // Identical tiles share a single atlas slot.
+    pub dedup_tiles: bool
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            bit_depth: 2,
+            first_slot: 0,
+            dedup_tiles: true
+        }
+    }
+}
+
+// Result of importing a tile sheet.
+pub struct TileSheet {
+    pub palettes: Vec<Palette>,
+    // Map of the original image, in tiles.
+    pub tilemap: TileMap,
+    // Number of atlas slots used, starting at the first slot.
+    pub slots_used: usize
+}
+
+type Rgba = [u8; 4];
+
+// An RGBA8 image.
+pub struct RgbaImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Rgba>
+}
+
+impl RgbaImage {
+    // Load a PNG file, converting it to RGBA.
+    pub fn load_png(path: &Path) -> Result<Self, ImportError> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info()?;
+
+        let mut buf = vec![0; info.buffer_size()];
+        reader.next_frame(&mut buf)?;
+
+        let samples = info.color_type.samples();
+        let pixels = buf.chunks_exact(samples).map(|p| match info.color_type {
+            png::ColorType::Grayscale       => [p[0], p[0], p[0], 255],
+            png::ColorType::GrayscaleAlpha  => [p[0], p[0], p[0], p[1]],
+            png::ColorType::RGB             => [p[0], p[1], p[2], 255],
+            png::ColorType::RGBA            => [p[0], p[1], p[2], p[3]],
+            // Indexed images are expanded by the decoder.
+            png::ColorType::Indexed         => [p[0], p[0], p[0], 255]
+        }).collect();
+
+        Ok(RgbaImage {
+            width: info.width as usize,
+            height: info.height as usize,
+            pixels: pixels
+        })
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> Rgba {
+        self.pixels[y * self.width + x]
+    }
+}
+
+// Load a PNG tile sheet into the atlas.
+pub fn import_png(path: &Path, atlas: &mut TextureAtlas, options: &ImportOptions) -> Result<TileSheet, ImportError> {
+    let image = RgbaImage::load_png(path)?;
+    import_image(&image, atlas, options)
+}
+
+// Slice an image into tiles and load them into the atlas.
+pub fn import_image(image: &RgbaImage, atlas: &mut TextureAtlas, options: &ImportOptions) -> Result<TileSheet, ImportError> {
+    let tex_size = atlas.tex_size();
+    if tex_size == 0 {
+        return Err(ImportError::EmptyTextures);
+    }
+    if !image.width.is_multiple_of(tex_size) || !image.height.is_multiple_of(tex_size) {
+        return Err(ImportError::Size{ width: image.width, height: image.height });
+    }
+
+    let map_width = image.width / tex_size;
+    let map_height = image.height / tex_size;
+    let max_colours = 1 << options.bit_depth.min(8);
+
+    let mut palettes: Vec<Vec<Rgba>> = Vec::new();
+    let mut unique_tiles: Vec<(Vec<u8>, u32)> = Vec::new();
+    let mut tile_lookup: HashMap<(Vec<u8>, u32), usize> = HashMap::new();
+    let mut tilemap = TileMap::new(map_width, map_height);
+
+    for map_y in 0..map_height {
+        for map_x in 0..map_width {
+            let pixels = (0..(tex_size * tex_size)).map(|i| {
+                image.pixel(map_x * tex_size + (i % tex_size), map_y * tex_size + (i / tex_size))
+            }).collect::<Vec<_>>();
+
+            let colours = quantise(&pixels, max_colours);
+            let palette = merge_palette(&mut palettes, &colours, max_colours);
+            let texels = pixels.iter().map(|p| nearest(&palettes[palette], *p) as u8).collect::<Vec<_>>();
+
+            let key = (texels, palette as u32);
+            let index = match tile_lookup.get(&key) {
+                Some(i) if options.dedup_tiles => *i,
+                _ => {
+                    unique_tiles.push(key.clone());
+                    tile_lookup.insert(key, unique_tiles.len() - 1);
+                    unique_tiles.len() - 1
+                }
+            };
+
+            let slot = options.first_slot + index;
+            let atlas_size = atlas.atlas_size();
+            tilemap.set_tile(map_x, map_y, MapTile {
+                tex_x: slot % atlas_size,
+                tex_y: slot / atlas_size,
+                palette: palette as u32,
+                flip: Default::default()
+            });
+        }
+    }
+
+    let atlas_size = atlas.atlas_size();
+    let available = (atlas_size * atlas_size).saturating_sub(options.first_slot);
+    if unique_tiles.len() > available {
+        return Err(ImportError::AtlasFull{ needed: unique_tiles.len(), available: available });
+    }
+
+    for (i, (texels, _)) in unique_tiles.iter().enumerate() {
+        let slot = options.first_slot + i;
+        let (tex_x, tex_y) = (slot % atlas_size, slot / atlas_size);
+        for (t, texel) in texels.iter().enumerate() {
+            atlas.set_texel(tex_x, tex_y, t % tex_size, t / tex_size, *texel);
+        }
+    }
+
+    Ok(TileSheet {
+        palettes: palettes.iter().map(|p| {
+            let mut palette = p.iter().map(|c| palette::from_rgba8(*c)).collect::<Palette>();
+            palette.resize(max_colours, palette::from_rgba8([0, 0, 0, 0]));
+            palette
+        }).collect(),
+        tilemap: tilemap,
+        slots_used: unique_tiles.len()
+    })
+}
+
+// Reduce a tile's pixels to at most max_colours colours.
+// Transparent pixels all map to a single fully transparent colour, which is placed first.
+// With only one colour there is no room for a separate transparent entry, so it is quantised with the rest.
+fn quantise(pixels: &[Rgba], max_colours: usize) -> Vec<Rgba> {
+    let mut counts: HashMap<Rgba, usize> = HashMap::new();
+    for p in pixels.iter() {
+        let p = if p[3] == 0 { [0, 0, 0, 0] } else { *p };
+        *counts.entry(p).or_insert(0) += 1;
+    }
+
+    let transparent = (max_colours > 1) && counts.remove(&[0, 0, 0, 0]).is_some();
+    let mut colours = counts.into_iter().collect::<Vec<_>>();
+    // Sort so the result doesn't depend on hash order.
+    colours.sort();
+
+    let opaque_max = if transparent { max_colours - 1 } else { max_colours };
+    let mut result = if transparent { vec![[0, 0, 0, 0]] } else { Vec::new() };
+    if colours.len() <= opaque_max {
+        result.extend(colours.iter().map(|(c, _)| *c));
+    } else {
+        result.extend(median_cut(colours, opaque_max));
+    }
+    result
+}
+
+// Median cut quantisation: repeatedly split the box with the widest channel range at its weighted median.
+fn median_cut(colours: Vec<(Rgba, usize)>, target: usize) -> Vec<Rgba> {
+    let mut boxes = vec![colours];
+
+    while boxes.len() < target {
+        // Find the box and channel with the largest range.
+        let mut best = None;
+        for (i, b) in boxes.iter().enumerate() {
+            if b.len() < 2 {
+                continue;
+            }
+            for channel in 0..4 {
+                let min = b.iter().map(|(c, _)| c[channel]).min().unwrap();
+                let max = b.iter().map(|(c, _)| c[channel]).max().unwrap();
+                let range = max - min;
+                if best.is_none_or(|(_, _, r)| range > r) {
+                    best = Some((i, channel, range));
+                }
+            }
+        }
+
+        let (index, channel, _) = match best {
+            Some(b) => b,
+            None => break
+        };
+
+        let mut b = boxes.swap_remove(index);
+        b.sort_by_key(|(c, _)| c[channel]);
+        let total = b.iter().map(|(_, n)| n).sum::<usize>();
+        let mut running = 0;
+        let mut split = 1;
+        for (i, (_, n)) in b.iter().enumerate() {
+            running += n;
+            if running * 2 >= total {
+                split = (i + 1).max(1).min(b.len() - 1);
+                break;
+            }
+        }
+        let upper = b.split_off(split);
+        boxes.push(b);
+        boxes.push(upper);
+    }
+
+    // Each box is represented by its weighted average colour.
+    boxes.iter().map(|b| {
+        let total = b.iter().map(|(_, n)| n).sum::<usize>().max(1);
+        let mut sum = [0; 4];
+        for (c, n) in b.iter() {
+            for channel in 0..4 {
+                sum[channel] += c[channel] as usize * n;
+            }
+        }
+        [(sum[0] / total) as u8, (sum[1] / total) as u8, (sum[2] / total) as u8, (sum[3] / total) as u8]
+    }).collect()
+}
+
+// Find or make a palette that contains all of the colours, returning its index.
+// Tiles whose colours fit into an existing palette share it.
+fn merge_palette(palettes: &mut Vec<Vec<Rgba>>, colours: &[Rgba], max_colours: usize) -> usize {
+    // An existing palette that contains all colours.
+    if let Some(i) = palettes.iter().position(|p| colours.iter().all(|c| p.contains(c))) {
+        return i;
+    }
+
+    // An existing palette with room for the missing colours.
+    // Palettes with transparency keep it at index 0, so only merge palettes that agree on it.
+    let transparent = colours.first() == Some(&[0, 0, 0, 0]);
+    for (i, p) in palettes.iter_mut().enumerate() {
+        if (p.first() == Some(&[0, 0, 0, 0])) != transparent {
+            continue;
+        }
+        let missing = colours.iter().filter(|c| !p.contains(c)).cloned().collect::<Vec<_>>();
+        if p.len() + missing.len() <= max_colours {
+            p.extend(missing);
+            return i;
+        }
+    }
+
+    palettes.push(colours.to_vec());
+    palettes.len() - 1
+}
+
+// Find the index of the nearest colour in the palette.
+fn nearest(palette: &[Rgba], colour: Rgba) -> usize {
+    if colour[3] == 0 {
+        if let Some(i) = palette.iter().position(|c| c[3] == 0) {
+            return i;
+        }
+    }
+
+    let distance = |c: &Rgba| (0..4).map(|i| {
+        let d = i32::from(c[i]) - i32::from(colour[i]);
+        d * d
+    }).sum::<i32>();
+
+    palette.iter().enumerate()
+        .min_by_key(|(_, c)| distance(c))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: usize, height: usize, pixels: Vec<Rgba>) -> RgbaImage {
+        RgbaImage {
+            width: width,
+            height: height,
+            pixels: pixels
+        }
+    }
+
+    #[test]
+    fn empty_textures_are_rejected() {
+        let mut atlas = TextureAtlas::new(2, 0);
+        let result = import_image(&image(2, 2, vec![[0, 0, 0, 255]; 4]), &mut atlas, &ImportOptions::default());
+        assert!(matches!(result, Err(ImportError::EmptyTextures)));
+    }
+
+    #[test]
+    fn texels_are_inside_palettes() {
+        let pixels = vec![[0, 0, 0, 0], [255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 128]];
+        for bit_depth in 0..=2 {
+            let mut atlas = TextureAtlas::new(2, 2);
+            let options = ImportOptions{ bit_depth: bit_depth, ..Default::default() };
+            let sheet = import_image(&image(2, 2, pixels.clone()), &mut atlas, &options).unwrap();
+            for palette in sheet.palettes.iter() {
+                assert_eq!(palette.len(), 1 << bit_depth);
+            }
+            let tile = sheet.tilemap.tile(0, 0);
+            let colours = sheet.palettes[tile.palette as usize].len();
+            assert!(atlas.tile_texels(tile.tex_x, tile.tex_y).iter().all(|t| (*t as usize) < colours));
+        }
+    }
+}
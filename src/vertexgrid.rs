@@ -1,4 +1,7 @@
-use super::Vertex;
+use super::{
+    error::TileError,
+    Vertex
+};
 
 use vulkano::{
     buffer::CpuBufferPool,
@@ -9,13 +12,115 @@ use vulkano::{
 
 use std::sync::Arc;
 
-// Struct that contains the vertices to be used for rendering, in addition to the buffer pool and cached buffer chunk for rendering.
+// Corners of each vertex in a tile, in units of the tile size.
+const CORNERS: [(f32, f32); 6] = [(0.0, 0.0), (0.0, 1.0), (1.0, 0.0), (0.0, 1.0), (1.0, 0.0), (1.0, 1.0)];
+
+// Flips to apply to a tile texture. The diagonal flip is applied first, then the horizontal and vertical flips.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct Flip {
+    pub x: bool,
+    pub y: bool,
+    // Swaps the x and y axes of the texture.
+    pub diagonal: bool
+}
+
+impl Flip {
+    // Transform a tile corner.
+    fn apply(&self, corner: (f32, f32)) -> (f32, f32) {
+        let (u, v) = if self.diagonal { (corner.1, corner.0) } else { corner };
+        (if self.x { 1.0 - u } else { u }, if self.y { 1.0 - v } else { v })
+    }
+
+    // Get the texel sampled at a position in a tile of the given size.
+    pub fn apply_texel(&self, x: usize, y: usize, size: usize) -> (usize, usize) {
+        let (u, v) = if self.diagonal { (y, x) } else { (x, y) };
+        (if self.x { size - 1 - u } else { u }, if self.y { size - 1 - v } else { v })
+    }
+}
+
+// Position of a tile in a grid, in tiles.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TileCoord {
+    pub x: usize,
+    pub y: usize
+}
+
+impl TileCoord {
+    pub fn new(x: usize, y: usize) -> Self {
+        TileCoord{ x: x, y: y }
+    }
+}
+
+// Position of a texture in a square atlas, in textures.
+// Slots can also be numbered from 0 along each row in turn.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct AtlasSlot {
+    pub x: usize,
+    pub y: usize
+}
+
+impl AtlasSlot {
+    pub fn new(x: usize, y: usize) -> Self {
+        AtlasSlot{ x: x, y: y }
+    }
+
+    // Get the slot with an index in an atlas of the given size.
+    pub fn from_index(index: usize, atlas_size: usize) -> Result<Self, TileError> {
+        let row_len = atlas_size.max(1);
+        let slot = AtlasSlot::new(index % row_len, index / row_len);
+        slot.check(atlas_size)?;
+        Ok(slot)
+    }
+
+    // Index of the slot in an atlas of the given size.
+    pub fn index(&self, atlas_size: usize) -> usize {
+        self.y * atlas_size + self.x
+    }
+
+    // Check that the slot is inside an atlas of the given size.
+    pub fn check(&self, atlas_size: usize) -> Result<(), TileError> {
+        if (self.x < atlas_size) && (self.y < atlas_size) {
+            Ok(())
+        } else {
+            Err(TileError::TextureOutOfRange{ tex_x: self.x, tex_y: self.y, atlas_size: atlas_size })
+        }
+    }
+}
+
+// A grid of tiles, each with a texture and a palette. Edits act on this, so they can be made without a device.
+pub trait TileGrid {
+    // Size of the grid in tiles.
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+
+    // Atlas position of the texture for a tile.
+    fn tile_texture(&self, tile_x: usize, tile_y: usize) -> (usize, usize);
+    fn tile_flip(&self, tile_x: usize, tile_y: usize) -> Flip;
+    fn tile_palette(&self, tile_x: usize, tile_y: usize) -> u32;
+
+    fn set_tile_texture_flipped(&mut self, tile_x: usize, tile_y: usize, tex_x: usize, tex_y: usize, flip: Flip);
+    fn set_tile_palette(&mut self, tile_x: usize, tile_y: usize, palette_index: u32);
+
+    // Check that a tile is inside the grid.
+    fn check_tile(&self, tile: TileCoord) -> Result<(), TileError> {
+        if (tile.x < self.width()) && (tile.y < self.height()) {
+            Ok(())
+        } else {
+            Err(TileError::TileOutOfRange{ x: tile.x, y: tile.y, width: self.width(), height: self.height() })
+        }
+    }
+}
+
+// Struct that contains the vertices to be used for rendering, in addition to the buffer pool and cached buffer chunks for rendering.
+// Each frame in flight has its own chunk, since a chunk can only be used by one submission at a time.
 pub struct VertexGrid {
     vertices: Vec<Vertex>,
     row_len: usize,
     atlas_size: f32,
     buffer_pool: CpuBufferPool<Vertex>,
-    current_buffer: Option<CpuBufferPoolChunk<Vertex, Arc<StdMemoryPool>>>
+    current_buffers: Vec<Option<CpuBufferPoolChunk<Vertex, Arc<StdMemoryPool>>>>,
+    // Bytes copied into new buffer chunks since the last call to take_uploaded_bytes.
+    uploaded_bytes: usize
 }
 
 impl VertexGrid {
@@ -26,7 +131,8 @@ impl VertexGrid {
             row_len: x_size,
             atlas_size: atlas_size as f32,
             buffer_pool: CpuBufferPool::vertex_buffer(device.clone()),
-            current_buffer: None
+            current_buffers: Vec::new(),
+            uploaded_bytes: 0
         };
 
         if (x_size > 0) && (y_size > 0) {
@@ -57,23 +163,68 @@ impl VertexGrid {
         grid
     }
 
+    // Width of the grid in tiles.
+    pub fn width(&self) -> usize {
+        self.row_len
+    }
+
+    // Height of the grid in tiles.
+    pub fn height(&self) -> usize {
+        if self.row_len == 0 {
+            0
+        } else {
+            self.vertices.len() / (self.row_len * 6)
+        }
+    }
+
+    // Size of the atlas in textures.
+    pub fn atlas_size(&self) -> usize {
+        self.atlas_size as usize
+    }
+
+    // Gets the tile at a point in normalised device coordinates. The grid covers -1 to 1 on both axes.
+    pub fn tile_at(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        if !(-1.0..1.0).contains(&x) || !(-1.0..1.0).contains(&y) {
+            return None;
+        }
+
+        let tile_x = ((x + 1.0) / 2.0 * self.width() as f32) as usize;
+        let tile_y = ((y + 1.0) / 2.0 * self.height() as f32) as usize;
+        if (tile_x < self.width()) && (tile_y < self.height()) {
+            Some((tile_x, tile_y))
+        } else {
+            None
+        }
+    }
+
     // Sets the tex coords for a tile.
+    // Textures outside the atlas aren't checked, and wrap around. Use try_set_tile_texture to check them.
     pub fn set_tile_texture(&mut self, tile_x: usize, tile_y: usize, tex_x: usize, tex_y: usize) {
+        self.set_tile_texture_flipped(tile_x, tile_y, tex_x, tex_y, Flip::default());
+    }
+
+    // Sets the tex coords for a tile, flipping the texture.
+    pub fn set_tile_texture_flipped(&mut self, tile_x: usize, tile_y: usize, tex_x: usize, tex_y: usize, flip: Flip) {
         let y_offset = tile_y * self.row_len * 6;
         let index = y_offset + (tile_x * 6);
 
         let top_left = (tex_x as f32 / self.atlas_size, tex_y as f32 / self.atlas_size);
-        let bottom_right = (top_left.0 + 1.0 / self.atlas_size, top_left.1 + 1.0 / self.atlas_size);
 
-        self.vertices[index].tex_coord =        [top_left.0, top_left.1];
-        self.vertices[index + 1].tex_coord =    [top_left.0, bottom_right.1];
-        self.vertices[index + 2].tex_coord =    [bottom_right.0, top_left.1];
-        self.vertices[index + 3].tex_coord =    [top_left.0, bottom_right.1];
-        self.vertices[index + 4].tex_coord =    [bottom_right.0, top_left.1];
-        self.vertices[index + 5].tex_coord =    [bottom_right.0, bottom_right.1];
+        for (vertex, corner) in self.vertices[index..(index + 6)].iter_mut().zip(CORNERS.iter()) {
+            let (u, v) = flip.apply(*corner);
+            vertex.tex_coord = [top_left.0 + u / self.atlas_size, top_left.1 + v / self.atlas_size];
+        }
+
+        // Invalidate buffer chunks.
+        self.current_buffers.clear();
+    }
 
-        // Invalidate buffer chunk.
-        self.current_buffer = None;
+    // Sets the texture for a tile, checking that the tile is in the grid and the texture is in the atlas.
+    pub fn try_set_tile_texture(&mut self, tile: TileCoord, slot: AtlasSlot, flip: Flip) -> Result<(), TileError> {
+        self.check_tile(tile)?;
+        slot.check(self.atlas_size())?;
+        self.set_tile_texture_flipped(tile.x, tile.y, slot.x, slot.y, flip);
+        Ok(())
     }
 
     // Sets the palette for a tile.
@@ -88,18 +239,110 @@ impl VertexGrid {
         self.vertices[index + 4].palette_index =    palette_index;
         self.vertices[index + 5].palette_index =    palette_index;
 
-        // Invalidate buffer chunk.
-        self.current_buffer = None;
+        // Invalidate buffer chunks.
+        self.current_buffers.clear();
+    }
+
+    // Sets the palette for a tile, checking that the tile is in the grid.
+    pub fn try_set_tile_palette(&mut self, tile: TileCoord, palette_index: u32) -> Result<(), TileError> {
+        self.check_tile(tile)?;
+        self.set_tile_palette(tile.x, tile.y, palette_index);
+        Ok(())
+    }
+
+    // Gets the atlas position of the texture for a tile.
+    pub fn tile_texture(&self, tile_x: usize, tile_y: usize) -> (usize, usize) {
+        let index = (tile_y * self.row_len + tile_x) * 6;
+        let tile = &self.vertices[index..(index + 6)];
+        let u = tile.iter().map(|v| v.tex_coord[0]).fold(f32::MAX, f32::min);
+        let v = tile.iter().map(|v| v.tex_coord[1]).fold(f32::MAX, f32::min);
+        ((u * self.atlas_size).round() as usize, (v * self.atlas_size).round() as usize)
+    }
+
+    // Gets the atlas slot of the texture for a tile, checking that the tile is in the grid.
+    pub fn try_tile_slot(&self, tile: TileCoord) -> Result<AtlasSlot, TileError> {
+        self.check_tile(tile)?;
+        let (tex_x, tex_y) = self.tile_texture(tile.x, tile.y);
+        Ok(AtlasSlot::new(tex_x, tex_y))
+    }
+
+    // Gets the flip applied to the texture for a tile.
+    pub fn tile_flip(&self, tile_x: usize, tile_y: usize) -> Flip {
+        let index = (tile_y * self.row_len + tile_x) * 6;
+        let (tex_x, tex_y) = self.tile_texture(tile_x, tile_y);
+        let top_left = (tex_x as f32 / self.atlas_size, tex_y as f32 / self.atlas_size);
+        let is_far = |coord: f32, base: f32| (coord - base) * self.atlas_size > 0.5;
+
+        // Corner (0, 0) is only moved by the x and y flips. Corner (0, 1) is moved to (1, 0) by a diagonal flip.
+        let origin = self.vertices[index].tex_coord;
+        let x = is_far(origin[0], top_left.0);
+        let y = is_far(origin[1], top_left.1);
+        let bottom_left = self.vertices[index + 1].tex_coord;
+        let diagonal = is_far(bottom_left[0], top_left.0) != x;
+
+        Flip{ x: x, y: y, diagonal: diagonal }
+    }
+
+    // Gets the palette for a tile.
+    pub fn tile_palette(&self, tile_x: usize, tile_y: usize) -> u32 {
+        let index = (tile_y * self.row_len + tile_x) * 6;
+        self.vertices[index].palette_index
     }
 
-    // Makes a new vertex buffer if the data has changed. Else, retrieves the current one.
-    pub fn get_vertex_buffer(&mut self) -> CpuBufferPoolChunk<Vertex, Arc<StdMemoryPool>> {
-        if let Some(buf) = &self.current_buffer {
-            buf.clone()
+    // Gets the palette for a tile, checking that the tile is in the grid.
+    pub fn try_tile_palette(&self, tile: TileCoord) -> Result<u32, TileError> {
+        self.check_tile(tile)?;
+        Ok(self.tile_palette(tile.x, tile.y))
+    }
+
+    // Makes a new vertex buffer for a frame in flight if the data has changed. Else, retrieves the current one.
+    pub fn get_vertex_buffer(&mut self, frame: usize) -> Result<CpuBufferPoolChunk<Vertex, Arc<StdMemoryPool>>, TileError> {
+        if frame >= self.current_buffers.len() {
+            self.current_buffers.resize(frame + 1, None);
+        }
+
+        if let Some(buf) = &self.current_buffers[frame] {
+            Ok(buf.clone())
         } else {
-            let b = self.buffer_pool.chunk(self.vertices.iter().cloned()).unwrap();
-            self.current_buffer = Some(b.clone());
-            b
+            let b = self.buffer_pool.chunk(self.vertices.iter().cloned())?;
+            self.current_buffers[frame] = Some(b.clone());
+            self.uploaded_bytes += self.vertices.len() * std::mem::size_of::<Vertex>();
+            Ok(b)
         }
     }
-}
\ No newline at end of file
+
+    // Bytes of vertex data uploaded since the last call.
+    pub fn take_uploaded_bytes(&mut self) -> usize {
+        std::mem::replace(&mut self.uploaded_bytes, 0)
+    }
+}
+
+impl TileGrid for VertexGrid {
+    fn width(&self) -> usize {
+        VertexGrid::width(self)
+    }
+
+    fn height(&self) -> usize {
+        VertexGrid::height(self)
+    }
+
+    fn tile_texture(&self, tile_x: usize, tile_y: usize) -> (usize, usize) {
+        VertexGrid::tile_texture(self, tile_x, tile_y)
+    }
+
+    fn tile_flip(&self, tile_x: usize, tile_y: usize) -> Flip {
+        VertexGrid::tile_flip(self, tile_x, tile_y)
+    }
+
+    fn tile_palette(&self, tile_x: usize, tile_y: usize) -> u32 {
+        VertexGrid::tile_palette(self, tile_x, tile_y)
+    }
+
+    fn set_tile_texture_flipped(&mut self, tile_x: usize, tile_y: usize, tex_x: usize, tex_y: usize, flip: Flip) {
+        VertexGrid::set_tile_texture_flipped(self, tile_x, tile_y, tex_x, tex_y, flip)
+    }
+
+    fn set_tile_palette(&mut self, tile_x: usize, tile_y: usize, palette_index: u32) {
+        VertexGrid::set_tile_palette(self, tile_x, tile_y, palette_index)
+    }
+}
@@ -0,0 +1,109 @@
+// Watch files for changes by polling their modification times on a background thread.
+use std::{
+    collections::HashMap,
+    fs,
+    path::{
+        Path,
+        PathBuf
+    },
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering
+        },
+        mpsc::{
+            self,
+            Receiver
+        },
+        Arc,
+        Mutex
+    },
+    thread,
+    time::{
+        Duration,
+        SystemTime
+    }
+};
+
+// Last modification time of each watched file. None if the file doesn't exist.
+type WatchedFiles = Arc<Mutex<HashMap<PathBuf, Option<SystemTime>>>>;
+
+pub struct FileWatcher {
+    files: WatchedFiles,
+    changes: Receiver<PathBuf>,
+    running: Arc<AtomicBool>
+}
+
+impl FileWatcher {
+    // Start polling the watched files every interval.
+    // Wakeup is called from the watcher thread whenever changes are found, e.g. to wake an event loop.
+    pub fn new<F>(interval: Duration, wakeup: F) -> Self
+        where F: Fn() + Send + 'static {
+        let files: WatchedFiles = Arc::new(Mutex::new(HashMap::new()));
+        let running = Arc::new(AtomicBool::new(true));
+        let (sender, changes) = mpsc::channel();
+
+        let thread_files = files.clone();
+        let thread_running = running.clone();
+        thread::spawn(move || {
+            while thread_running.load(Ordering::Relaxed) {
+                thread::sleep(interval);
+
+                let mut changed = false;
+                for (path, modified) in thread_files.lock().unwrap().iter_mut() {
+                    let current = modified_time(path);
+                    if current != *modified {
+                        *modified = current;
+                        changed = true;
+                        if sender.send(path.clone()).is_err() {
+                            return;
+                        }
+                    }
+                }
+
+                if changed {
+                    wakeup();
+                }
+            }
+        });
+
+        FileWatcher {
+            files: files,
+            changes: changes,
+            running: running
+        }
+    }
+
+    // Start watching a file. Files that don't exist yet are reported when they are created.
+    pub fn watch(&self, path: &Path) {
+        self.files.lock().unwrap()
+            .entry(path.to_path_buf())
+            .or_insert_with(|| modified_time(path));
+    }
+
+    // Stop watching a file.
+    pub fn unwatch(&self, path: &Path) {
+        self.files.lock().unwrap().remove(path);
+    }
+
+    // Files that have changed since the last call. Each file is listed once.
+    pub fn changed(&self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for path in self.changes.try_iter() {
+            if !changed.contains(&path) {
+                changed.push(path);
+            }
+        }
+        changed
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}